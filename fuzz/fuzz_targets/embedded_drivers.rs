@@ -0,0 +1,62 @@
+// fuzz_targets/embedded_drivers.rs
+// The embedded-io drivers read arbitrary bytes until the end of the stream: they return errors, never panic.
+#![no_main]
+
+use std::convert::Infallible;
+
+use libfuzzer_sys::fuzz_target;
+use sensor::embedded::ld2410c::Ld2410C;
+use sensor::embedded::rd03d::RD03D;
+use sensor::embedded::tf_luna::TfLuna;
+
+// Serial port returning the fuzzed bytes by chunks of `chunk` bytes, then the end of the stream.
+struct FuzzSerial<'a> {
+    rx: &'a [u8],
+    chunk: usize,
+}
+
+impl embedded_io::ErrorType for FuzzSerial<'_> {
+    type Error = Infallible;
+}
+
+impl embedded_io::Read for FuzzSerial<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let n = buf.len().min(self.chunk).min(self.rx.len());
+        buf[..n].copy_from_slice(&self.rx[..n]);
+        self.rx = &self.rx[n..];
+        Ok(n)
+    }
+}
+
+impl embedded_io::Write for FuzzSerial<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte chooses the size of the chunks.
+    let Some((chunk, rx)) = data.split_first() else {
+        return;
+    };
+    let serial = || FuzzSerial {
+        rx,
+        chunk: usize::from(*chunk).max(1),
+    };
+
+    let mut ld2410c = Ld2410C::new(serial());
+    while !matches!(ld2410c.read_data(), Err(sensor::embedded::Error::UnexpectedEof)) {}
+    let _ = Ld2410C::new(serial()).read_parameter();
+    let _ = Ld2410C::new(serial()).query_auxiliary_control();
+
+    let mut rd03d = RD03D::new(serial());
+    while rd03d.update().is_ok() {}
+    let _ = RD03D::new(serial()).open_command_mode();
+
+    let mut tf_luna = TfLuna::new(serial());
+    while tf_luna.read_data().is_ok() {}
+});
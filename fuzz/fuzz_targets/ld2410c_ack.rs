@@ -0,0 +1,24 @@
+// fuzz_targets/ld2410c_ack.rs
+// LD2410C command ACKs: the parsers of the query responses and the ACK search, on any received bytes.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sensor::protocol::find_ack_frame;
+use sensor::protocol::ld2410c::{
+    find_ack, AuxiliaryControl, DistanceResolution, FirmwareVersion, MacAddress, RadarParameters,
+    COMMAND_FRAME_HEADER,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = FirmwareVersion::from_response(data);
+    let _ = MacAddress::from_response(data);
+    let _ = RadarParameters::from_response(data);
+    let _ = DistanceResolution::from_response(data);
+    let _ = AuxiliaryControl::from_response(data);
+    // The first two bytes choose the command word looked for.
+    if let [low, high, rest @ ..] = data {
+        let word = u16::from_le_bytes([*low, *high]);
+        let _ = find_ack(rest, word);
+        let _ = find_ack_frame(rest, COMMAND_FRAME_HEADER, word);
+    }
+});
@@ -0,0 +1,12 @@
+// fuzz_targets/ld2410c_report.rs
+// LD2410C report frames: the decoder returns an error on any malformed frame, it never panics.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sensor::protocol::ld2410c::Ld2410CData;
+use sensor::protocol::{find_frame, ld2410c::REPORT_FRAME_HEADER};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Ld2410CData::from_frame(data);
+    let _ = find_frame(data, REPORT_FRAME_HEADER);
+});
@@ -0,0 +1,20 @@
+// fuzz_targets/rd03d_report.rs
+// RD03D report frames and ACKs, including the reception buffer handling of the std drivers.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sensor::protocol::find_ack_frame;
+use sensor::protocol::rd03d::{decode_frame, latest_frame, COMMAND_FRAME_HEADER};
+use sensor::rd03d::extract_latest_targets;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_frame(data);
+    let _ = latest_frame(data);
+    let _ = find_ack_frame(data, COMMAND_FRAME_HEADER, 0x00FF);
+    // The drivers feed the buffer by chunks.
+    let mut buffer = Vec::new();
+    for chunk in data.chunks(16) {
+        buffer.extend_from_slice(chunk);
+        let _ = extract_latest_targets(&mut buffer);
+    }
+});
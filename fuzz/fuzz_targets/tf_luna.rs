@@ -0,0 +1,17 @@
+// fuzz_targets/tf_luna.rs
+// TF-Luna data frames and command responses, including truncated frames.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sensor::protocol::tf_luna::{find_data_frame, find_response, TfLunaData};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = TfLunaData::new(data);
+    if let Some((frame, _)) = find_data_frame(data) {
+        let _ = TfLunaData::new(frame);
+    }
+    // The first byte chooses the command id looked for.
+    if let [id, rest @ ..] = data {
+        let _ = find_response(rest, *id);
+    }
+});
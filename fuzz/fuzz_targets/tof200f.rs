@@ -0,0 +1,13 @@
+// fuzz_targets/tof200f.rs
+// TOF200F responses, including truncated ones.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sensor::protocol::tof200f::{find_response, Tof200fData};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Tof200fData::from_frame(data);
+    if let Some((frame, _)) = find_response(data) {
+        let _ = Tof200fData::from_frame(frame);
+    }
+});
@@ -1,30 +1,56 @@
 use std::convert::Infallible;
+use std::sync::Arc;
 
+use crate::config::{Config, SensorConfig, SensorKind, StreamConfig};
+use crate::fusion::room;
+use crate::device::{self, PortInfo, Probe};
+use crate::export::{self, Format};
+use crate::history::{self, History};
+use crate::stream::{self, Rate, Reduce};
+use crate::hub::{
+    Command, Hub, Ld2410cCommand, Rd03dCommand, Reading, Sample, SendError, SensorStatus, TflunaCommand,
+};
 use crate::{ld2410c, rd03d, tf_luna};
 use askama::Template;
 use axum::{
+    extract::{FromRef, Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
     response::{
         sse::{Event, KeepAlive},
-        Sse,
+        Html, IntoResponse, Response, Sse,
     },
-    routing::get,
-    Router,
+    routing::{get, post, put},
+    Json, Router,
 };
 use futures::Stream;
-use serde::Serialize;
-use tokio_stream::{wrappers::IntervalStream, StreamExt};
-use tower_http::cors::{Any, CorsLayer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_stream::StreamExt;
+use subtle::ConstantTimeEq;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 #[derive(Template, Serialize)]
 #[template(path = "tfluna.html")]
 struct TfLunaTemplate {
-    pub data: tf_luna::TfLunaData, // Replace TfLunaData with the actual data type returned by TfLuna::read_data()
+    pub data: tf_luna::TfLunaData,
+    sensor_id: String,
 }
 
 #[derive(Template, Serialize)]
 #[template(path = "rd03d.html")]
 struct Rd03dTemplate<'a> {
     targets: &'a [rd03d::Target],
+    sensor_id: &'a str,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "room.html")]
+struct RoomTemplate<'a> {
+    targets: &'a [room::RoomTarget],
+    // Targets and poses of the radars, as JSON for the map.
+    targets_json: String,
+    radars: String,
 }
 
 #[derive(Template, Serialize)]
@@ -33,185 +59,944 @@ struct Ld2410cTemplate {
     data: ld2410c::Ld2410CData,
 }
 
-pub async fn api(port: String, set_data_type_ld2410c: ld2410c::DataType) -> Router {
-    let rd03d_port = port.clone();
-    let rd03d_sse_port = port.clone();
-    let ld2410c_port = port.clone();
-    let ld2410c_sse_port = port.clone();
-    Router::new()
-        .route("/rd03d", get(move || rd03d_handler(rd03d_port.clone())))
+#[derive(Clone)]
+struct AppState {
+    hub: Hub,
+    api_token: Option<Arc<str>>,
+    stream: StreamConfig,
+    history: Option<History>,
+}
+
+impl FromRef<AppState> for Hub {
+    fn from_ref(state: &AppState) -> Hub {
+        state.hub.clone()
+    }
+}
+
+pub fn api(hub: Hub, history: Option<History>, config: &Config) -> Router {
+    let state = AppState {
+        hub,
+        api_token: config.api_token.as_deref().map(Arc::from),
+        stream: config.stream.clone(),
+        history,
+    };
+    // Configuration endpoints, they require the API token.
+    let settings = Router::new()
+        .route("/api/sensors/{id}/firmware", get(firmware_handler))
+        .route("/api/sensors/{id}/mac-address", get(mac_address_handler))
+        .route("/api/sensors/{id}/parameters", get(parameters_handler))
         .route(
-            "/rd03d/sse",
-            get(move || rd03d_sse_handler(rd03d_sse_port.clone())),
+            "/api/sensors/{id}/resolution",
+            get(resolution_handler).put(set_resolution_handler),
         )
         .route(
-            "/ld2410c",
-            get(move || ld2410c_handler(ld2410c_port.clone())),
+            "/api/sensors/{id}/auxiliary-control",
+            get(auxiliary_control_handler).put(set_auxiliary_control_handler),
         )
-        .route(
-            "/ld2410c/sse",
-            get(move || ld2410c_sse_handler(ld2410c_sse_port.clone(), set_data_type_ld2410c)),
+        .route("/api/sensors/{id}/engineering-mode", put(set_engineering_mode_handler))
+        .route("/api/sensors/{id}/gate-sensitivity", put(set_gate_sensitivity_handler))
+        .route("/api/sensors/{id}/baud-rate", put(set_baud_rate_handler))
+        .route("/api/sensors/{id}/bluetooth", put(set_bluetooth_handler))
+        .route("/api/sensors/{id}/bluetooth-password", put(set_bluetooth_password_handler))
+        .route("/api/sensors/{id}/frequency", put(set_frequency_handler))
+        .route("/api/sensors/{id}/distance-limit", put(set_distance_limit_handler))
+        .route("/api/sensors/{id}/output-format", put(set_output_format_handler))
+        .route("/api/sensors/{id}/multi-mode", put(set_multi_mode_handler))
+        .route("/api/sensors/{id}/mode", put(set_mode_handler))
+        .route("/api/sensors/{id}/settings", get(settings_handler))
+        .route("/api/sensors/{id}/restart", post(restart_handler))
+        .route("/api/sensors/{id}/factory-reset", post(factory_reset_handler))
+        .route("/api/ports/probe", post(probe_ports_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state.clone());
+    let ws = crate::ws::router(state.hub.clone(), settings.clone(), config.stream.clone());
+    Router::new()
+        .route("/rd03d", get(rd03d_handler))
+        .route("/rd03d/sse", get(rd03d_sse_handler))
+        .route("/ld2410c", get(ld2410c_handler))
+        .route("/ld2410c/sse", get(ld2410c_sse_handler))
+        .route("/tfluna", get(tf_luna_handler))
+        .route("/tfluna/sse", get(tf_luna_sse_handler))
+        .route("/room", get(room_handler))
+        .route("/room/sse", get(room_sse_handler))
+        .route("/api/sensors", get(sensors_handler))
+        .route("/api/sensors/{id}/latest", get(latest_handler))
+        .route("/api/sensors/{id}/status", get(status_handler))
+        .route("/api/sensors/{id}/history", get(history_handler))
+        .route("/api/events", get(events_handler))
+        .route("/api/ports", get(ports_handler))
+        .route("/api/export", get(export_handler))
+        .route("/metrics", get(metrics_handler))
+        .with_state(state)
+        .merge(settings)
+        .merge(ws)
+        .layer(cors(&config.allowed_origins))
+}
+
+// cors() Returns the CORS layer allowing the configured origins ("*" for any); with no origins, only the
+// pages of the server itself may call the API.
+fn cors(allowed_origins: &[String]) -> CorsLayer {
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        return CorsLayer::new().allow_origin(Any).allow_headers(Any).allow_methods(Any);
+    }
+    let origins = allowed_origins.iter().filter_map(|origin| origin.parse().ok());
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+        .allow_methods(Any)
+}
+
+// require_token() Rejects the requests without the header `Authorization: Bearer <api_token>`.
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(api_token) = state.api_token else {
+        return ApiError::new(
+            StatusCode::FORBIDDEN,
+            "configuration endpoints are disabled, set api_token in the configuration",
         )
-        .route("/tfluna", get({
-            let port = port.clone();
-            move || tf_luna_handler(port.clone())
-        }))
-        .route("/tfluna/sse", get({
-            let port = port.clone();
-            move || tf_luna_sse_handler(port.clone())
-        }))
-        .layer(CorsLayer::new().allow_origin(Any))
-}
-
-async fn rd03d_sse_handler(port: String) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let interval = tokio::time::interval(std::time::Duration::from_secs(1));
-    let stream = IntervalStream::new(interval).then(move |_| {
-        let port = port.clone();
-        async move {
-            let mut rd03d = rd03d::RD03D::new(port.clone());
-            if rd03d.connect().await.is_ok() {
-                if rd03d.update().await.unwrap() {
-                    let target1 = rd03d.get_target(1);
-                    let target2 = rd03d.get_target(2);
-                    let target3 = rd03d.get_target(3);
-                    if let (Some(t1), Some(t2), Some(t3)) = (target1, target2, target3) {
-                        rd03d.targets = vec![t1.clone(), t2.clone(), t3.clone()];
-                        let data = serde_json::to_string(&rd03d.targets)
-                            .unwrap_or_else(|_| "[]".to_string());
-                        Ok(Event::default().data(data))
-                    } else {
-                        rd03d.targets.clear();
-                        Ok(Event::default().data("[]"))
-                    }
-                } else {
-                    Ok(Event::default().data("[]"))
-                }
-            } else {
-                Ok(Event::default().data("[]"))
-            }
-        }
-    });
-    Sse::new(stream).keep_alive(KeepAlive::default())
+        .into_response();
+    };
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Constant-time comparison: the time taken does not tell how much of the token is right.
+    let valid = token.is_some_and(|token| bool::from(token.as_bytes().ct_eq(api_token.as_bytes())));
+    if !valid {
+        return ApiError::new(StatusCode::UNAUTHORIZED, "missing or invalid API token").into_response();
+    }
+    next.run(request).await
+}
+
+// ApiError is returned by the JSON endpoints as {"error": "..."} with the given status code.
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
 }
 
-async fn rd03d_handler(port: String) -> axum::response::Html<String> {
-    let mut rd03d = rd03d::RD03D::new(port);
-    if let Err(e) = rd03d.connect().await {
-        return axum::response::Html(format!("<p>Erreur connexion RD03D: {e}</p>"));
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn unknown_sensor(id: &str) -> Self {
+        Self::new(StatusCode::NOT_FOUND, format!("unknown sensor {id:?}"))
     }
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-        let _ = rd03d.update().await;
-        let tpl = Rd03dTemplate {
-            targets: &rd03d.targets,
+}
+
+impl From<SendError> for ApiError {
+    fn from(error: SendError) -> Self {
+        let status = match error {
+            SendError::Unsupported(_) => StatusCode::BAD_REQUEST,
+            SendError::NotConnected | SendError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            SendError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            SendError::Failed(_) => StatusCode::BAD_GATEWAY,
         };
-        return axum::response::Html(tpl.render().unwrap());
+        Self::new(status, error.to_string())
     }
 }
 
-async fn ld2410c_handler(port: String) -> axum::response::Html<String> {
-    let mut ld2410c = ld2410c::Ld2410C::new(port);
-    if let Err(e) = ld2410c.connect().await {
-        return axum::response::Html(format!("<p>Erreur connexion LD2410C: {e}</p>"));
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.message });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+// render() Renders a dashboard template, a rendering error is returned as 500 Internal Server Error.
+fn render(template: &impl Template) -> Result<Html<String>, ApiError> {
+    template
+        .render()
+        .map(Html)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// latest_reading() Returns the latest reading of the first sensor of `kind`, if any.
+fn latest_reading(hub: &Hub, kind: SensorKind) -> Option<Reading> {
+    hub.first(kind)?.latest().map(|sample| sample.data)
+}
+
+// StreamQuery is the query of the SSE streams: `?hz=20` or `?hz=frame` (every new frame), and
+// `&mode=average` to average the readings of each period instead of sending the latest one.
+#[derive(Deserialize)]
+struct StreamQuery {
+    hz: Option<String>,
+    #[serde(default)]
+    mode: Reduce,
+}
+
+type SseStream = Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>>;
+
+// sse_stream() Sends the JSON of the readings of the first sensor of `kind`, at the rate asked by the
+// client (the default rate of the configuration otherwise, capped by max_hz).
+fn sse_stream(state: &AppState, kind: SensorKind, query: StreamQuery) -> Result<SseStream, ApiError> {
+    let sensor = state
+        .hub
+        .first(kind)
+        .cloned()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("no {} sensor configured", kind.name())))?;
+    let requested = query
+        .hz
+        .map(|hz| hz.parse::<Rate>())
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let rate = state.stream.rate(requested);
+    let client = sensor.sse_client();
+    let stream = stream::samples(sensor, rate, query.mode).map(move |sample| {
+        let _client = &client;
+        let data = serde_json::to_string(&sample.data).unwrap_or_default();
+        Ok(Event::default().data(data))
+    });
+    Ok(Sse::new(Box::pin(stream) as _).keep_alive(KeepAlive::default()))
+}
+
+async fn rd03d_sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Result<SseStream, ApiError> {
+    sse_stream(&state, SensorKind::Rd03d, query)
+}
+
+async fn rd03d_handler(State(hub): State<Hub>) -> Result<Html<String>, ApiError> {
+    let Some(sensor) = hub.first(SensorKind::Rd03d) else {
+        return Ok(Html("<p>Aucun RD03D configuré</p>".to_string()));
+    };
+    let targets = match latest_reading(&hub, SensorKind::Rd03d) {
+        Some(Reading::Rd03d(targets)) => targets,
+        _ => Vec::new(),
+    };
+    let tpl = Rd03dTemplate {
+        targets: &targets,
+        sensor_id: &sensor.config.id,
+    };
+    render(&tpl)
+}
+
+async fn ld2410c_handler(State(hub): State<Hub>) -> Result<Html<String>, ApiError> {
+    if hub.first(SensorKind::Ld2410c).is_none() {
+        return Ok(Html("<p>Aucun LD2410C configuré</p>".to_string()));
     }
-    let data = match ld2410c.read_data().await {
-        Ok(d) => d,
-        Err(e) => return axum::response::Html(format!("<p>Erreur lecture LD2410C: {e}</p>")),
+    let data = match latest_reading(&hub, SensorKind::Ld2410c) {
+        Some(Reading::Ld2410c(data)) => data,
+        _ => ld2410c::Ld2410CData::default(),
     };
     let tpl = Ld2410cTemplate { data };
-    axum::response::Html(tpl.render().unwrap())
+    render(&tpl)
 }
 
 // Handler SSE pour /ld2410c/sse
 async fn ld2410c_sse_handler(
-    port: String,
-    data_type: ld2410c::DataType,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let port = port.clone();
-    let stream = IntervalStream::new(tokio::time::interval(std::time::Duration::from_secs(1)))
-        .then(move |_| {
-            let port = port.clone();
-            {
-                let value = data_type.clone();
-                async move {
-                    match value {
-                        ld2410c::DataType::EngineeringMode => {
-                            let mut ld2410c = ld2410c::Ld2410C::new(port);
-                            let mut data = String::from("{}");
-
-                            if ld2410c.set_engineering_mode().await.is_ok()
-                                && ld2410c.connect().await.is_ok()
-                            {
-                                match ld2410c.read_data().await {
-                                    Ok(d) => {
-                                        data = serde_json::to_string(&d)
-                                            .unwrap_or_else(|_| "{}".to_string())
-                                    }
-                                    Err(e) => eprintln!("Erreur lecture LD2410C: {e}"),
-                                }
-                            }
-                            Ok::<_, Infallible>(Event::default().data(data))
-                        }
-                        ld2410c::DataType::TargetBasicInformation => {
-                            let mut ld2410c = ld2410c::Ld2410C::new(port);
-                            let mut data = String::from("{}");
-                            if ld2410c.connect().await.is_ok() {
-                                match ld2410c.read_data().await {
-                                    Ok(d) => {
-                                        data = serde_json::to_string(&d)
-                                            .unwrap_or_else(|_| "{}".to_string())
-                                    }
-                                    Err(e) => eprintln!("Erreur lecture LD2410C: {e}"),
-                                }
-                            };
-                            Ok::<_, Infallible>(Event::default().data(data))
-                        }
-                        _ => Ok::<_, Infallible>(Event::default().data("{}")),
-                    }
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Result<SseStream, ApiError> {
+    sse_stream(&state, SensorKind::Ld2410c, query)
+}
+
+async fn tf_luna_handler(State(hub): State<Hub>) -> Result<Html<String>, ApiError> {
+    let Some(sensor) = hub.first(SensorKind::Tfluna) else {
+        return Ok(Html("<p>Aucun TF-Luna configuré</p>".to_string()));
+    };
+    let data = match latest_reading(&hub, SensorKind::Tfluna) {
+        Some(Reading::Tfluna(data)) => data,
+        _ => tf_luna::TfLunaData::default(),
+    };
+    let tpl = TfLunaTemplate {
+        data,
+        sensor_id: sensor.config.id.clone(),
+    };
+    render(&tpl)
+}
+
+async fn tf_luna_sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Result<SseStream, ApiError> {
+    sse_stream(&state, SensorKind::Tfluna, query)
+}
+
+// room_handler() GET /room: map of the people tracked by the first room sensor, with its radars.
+async fn room_handler(State(hub): State<Hub>) -> Result<Html<String>, ApiError> {
+    let Some(sensor) = hub.first(SensorKind::Room) else {
+        return Ok(Html("<p>Aucune pièce configurée</p>".to_string()));
+    };
+    let room = match latest_reading(&hub, SensorKind::Room) {
+        Some(Reading::Room(room)) => room,
+        _ => room::Room::default(),
+    };
+    let radars = sensor.config.room.as_ref().map(|config| config.radars.as_slice()).unwrap_or_default();
+    let tpl = RoomTemplate {
+        targets: &room.targets,
+        targets_json: serde_json::to_string(&room.targets).unwrap_or_default(),
+        radars: serde_json::to_string(radars).unwrap_or_default(),
+    };
+    render(&tpl)
+}
+
+async fn room_sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Result<SseStream, ApiError> {
+    sse_stream(&state, SensorKind::Room, query)
+}
+
+// metrics_handler() GET /metrics: Prometheus metrics of all the sensors.
+async fn metrics_handler(State(hub): State<Hub>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        crate::metrics::render(&hub),
+    )
+}
+
+// sensors_handler() GET /api/sensors: status of all the configured sensors.
+async fn sensors_handler(State(hub): State<Hub>) -> Json<Vec<SensorStatus>> {
+    Json(hub.sensors().iter().map(|sensor| sensor.status()).collect())
+}
+
+// events_handler() GET /api/events: SSE stream of the changes of connection state of the sensors, one
+// "state" event per change (SensorEvent).
+async fn events_handler(State(hub): State<Hub>) -> SseStream {
+    let mut events = hub.events();
+    let stream = async_stream::stream! {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    yield Ok(Event::default().event("state").data(data));
                 }
+                // Events missed by a slow client.
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
             }
-        });
-    Sse::new(stream).keep_alive(KeepAlive::default())
+        }
+    };
+    Sse::new(Box::pin(stream) as _).keep_alive(KeepAlive::default())
+}
+
+fn sensor_configs(hub: &Hub) -> Vec<SensorConfig> {
+    hub.sensors().iter().map(|sensor| sensor.config.clone()).collect()
+}
+
+// ports_handler() GET /api/ports: serial ports of the system, with their USB identifiers and the
+// configured sensor using them.
+async fn ports_handler(State(hub): State<Hub>) -> Json<Vec<PortInfo>> {
+    Json(device::list(&sensor_configs(&hub)))
+}
+
+// probe_ports_handler() POST /api/ports/probe: identifies the sensors on the ports not used by a configured
+// sensor. Takes a few seconds per port without a sensor.
+async fn probe_ports_handler(State(hub): State<Hub>) -> Json<Vec<Probe>> {
+    Json(device::probe_all(&sensor_configs(&hub)).await)
+}
+
+// latest_handler() GET /api/sensors/{id}/latest: latest reading of the sensor with its timestamp.
+async fn latest_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Sample>, ApiError> {
+    let sensor = hub.get(&id).ok_or_else(|| ApiError::unknown_sensor(&id))?;
+    let sample = sensor.latest().ok_or_else(|| {
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, format!("no frame received from {id:?} yet"))
+    })?;
+    Ok(Json(sample))
+}
+
+// status_handler() GET /api/sensors/{id}/status: connection state, last frame age and counters.
+async fn status_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<SensorStatus>, ApiError> {
+    let sensor = hub.get(&id).ok_or_else(|| ApiError::unknown_sensor(&id))?;
+    Ok(Json(sensor.status()))
 }
 
-async fn tf_luna_handler(
-    port: String,
-) -> axum::response::Html<String> {
-    let mut tf_luna = crate::tf_luna::TfLuna::new(port);
-    if let Err(e) = tf_luna.connect().await {
-        return axum::response::Html(format!("<p>Erreur connexion TF-Luna: {e}</p>"));
+#[derive(Deserialize)]
+struct HistoryQuery {
+    // Milliseconds since the Unix epoch, the last hour by default.
+    from: Option<u64>,
+    to: Option<u64>,
+    // Averaging period, e.g. "10s", "1m" or "1h".
+    resolution: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse {
+    sensor: String,
+    from: u64,
+    to: u64,
+    // Averaging period of the readings (ms), null for the recorded readings.
+    resolution_ms: Option<u64>,
+    samples: Vec<Sample>,
+}
+
+// history_handler() GET /api/sensors/{id}/history?from=&to=&resolution=: recorded readings of the sensor.
+async fn history_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, ApiError> {
+    let sensor = state.hub.get(&id).ok_or_else(|| ApiError::unknown_sensor(&id))?;
+    let history = state.history.clone().ok_or_else(|| {
+        ApiError::new(StatusCode::NOT_FOUND, "history is disabled, add a [history] section to the configuration")
+    })?;
+    let to = query.to.unwrap_or_else(crate::hub::now_ms);
+    let from = query.from.unwrap_or(to.saturating_sub(3_600_000));
+    if from > to {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "from must not be after to"));
     }
-    let data = match tf_luna.read_data().await {
-        Ok(d) => d,
-        Err(e) => return axum::response::Html(format!("<p>Erreur lecture TF-Luna: {e}</p>")),
+    let resolution = query
+        .resolution
+        .map(|text| {
+            history::parse_duration(&text)
+                .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, format!("invalid resolution {text:?}")))
+        })
+        .transpose()?;
+    let kind = sensor.config.kind;
+    let result = tokio::task::spawn_blocking(move || history.query(&id, kind, from, to, resolution)).await;
+    let (samples, resolution_ms) = match result {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        Err(e) => return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     };
-    let tpl = TfLunaTemplate { data };
-    axum::response::Html(tpl.render().unwrap())
+    Ok(Json(HistoryResponse {
+        sensor: sensor.config.id.clone(),
+        from,
+        to,
+        resolution_ms,
+        samples,
+    }))
 }
 
-async fn tf_luna_sse_handler(
-    port: String,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let port = port.clone();
-    let stream = IntervalStream::new(tokio::time::interval(std::time::Duration::from_secs(1)))
-        .then(move |_| {
-            let port = port.clone();
-            async move {
-                let mut tf_luna = crate::tf_luna::TfLuna::new(port);
-                if tf_luna.connect().await.is_ok() {
-                    match tf_luna.read_data().await {
-                        Ok(data) => {
-                            Ok(Event::default().data(serde_json::to_string(&data).unwrap_or_else(|_| "{}".to_string())))
-                        }
-                        Err(e) => {
-                            eprintln!("Erreur lecture TF-Luna: {e}");
-                            Ok(Event::default().data("{}"))
-                        }
-                    }
-                } else {
-                    Ok(Event::default().data("{}"))
-                }
+#[derive(Deserialize)]
+struct ExportQuery {
+    // Comma-separated sensor ids, all the sensors by default.
+    sensors: Option<String>,
+    // Milliseconds since the Unix epoch or a duration before now ("24h"), the last hour by default.
+    from: Option<String>,
+    to: Option<String>,
+    resolution: Option<String>,
+    // csv (default), jsonl or parquet.
+    format: Option<String>,
+}
+
+// export_handler() GET /api/export?sensors=&from=&to=&resolution=&format=: recorded readings as a file.
+async fn export_handler(State(state): State<AppState>, Query(query): Query<ExportQuery>) -> Result<Response, ApiError> {
+    let bad_request = |message: String| ApiError::new(StatusCode::BAD_REQUEST, message);
+    let history = state.history.clone().ok_or_else(|| {
+        ApiError::new(StatusCode::NOT_FOUND, "history is disabled, add a [history] section to the configuration")
+    })?;
+    let sensors = match &query.sensors {
+        Some(ids) => ids
+            .split(',')
+            .map(|id| {
+                let sensor = state.hub.get(id).ok_or_else(|| ApiError::unknown_sensor(id))?;
+                Ok((sensor.config.id.clone(), sensor.config.kind))
+            })
+            .collect::<Result<Vec<_>, ApiError>>()?,
+        None => state
+            .hub
+            .sensors()
+            .iter()
+            .map(|sensor| (sensor.config.id.clone(), sensor.config.kind))
+            .collect(),
+    };
+    let now = crate::hub::now_ms();
+    let time = |text: &Option<String>, default: u64| match text {
+        Some(text) => export::parse_time(text, now).ok_or_else(|| bad_request(format!("invalid time {text:?}"))),
+        None => Ok(default),
+    };
+    let to = time(&query.to, now)?;
+    let from = time(&query.from, to.saturating_sub(3_600_000))?;
+    if from > to {
+        return Err(bad_request("from must not be after to".to_string()));
+    }
+    let resolution = query
+        .resolution
+        .map(|text| history::parse_duration(&text).ok_or_else(|| bad_request(format!("invalid resolution {text:?}"))))
+        .transpose()?;
+    let format: Format = query
+        .format
+        .as_deref()
+        .unwrap_or("csv")
+        .parse()
+        .map_err(|e: anyhow::Error| bad_request(e.to_string()))?;
+    if format != Format::Jsonl && sensors.iter().any(|(_, kind)| *kind != sensors[0].1) {
+        return Err(bad_request(format!(
+            "the {} format needs sensors of the same kind, use jsonl",
+            format.extension()
+        )));
+    }
+    let result = tokio::task::spawn_blocking(move || export::export(&history, &sensors, from, to, resolution, format)).await;
+    let body = match result {
+        Ok(Ok(body)) => body,
+        Ok(Err(e)) => return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        Err(e) => return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
+    let disposition = format!("attachment; filename=\"sensors-{from}-{to}.{}\"", format.extension());
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type().to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        body,
+    )
+        .into_response())
+}
+
+// send_command() Runs a command in the task of the sensor `id` and returns its JSON result.
+async fn send_command(hub: &Hub, id: &str, command: Command) -> Result<Json<Value>, ApiError> {
+    let sensor = hub.get(id).ok_or_else(|| ApiError::unknown_sensor(id))?;
+    Ok(Json(sensor.send(command).await?))
+}
+
+// send_ld2410c_command() Runs an LD2410C configuration command.
+async fn send_ld2410c_command(hub: &Hub, id: &str, command: Ld2410cCommand) -> Result<Json<Value>, ApiError> {
+    send_command(hub, id, Command::Ld2410c(command)).await
+}
+
+// firmware_handler() GET /api/sensors/{id}/firmware: firmware version of an LD2410C.
+async fn firmware_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::ReadFirmwareVersion).await
+}
+
+// mac_address_handler() GET /api/sensors/{id}/mac-address: MAC address of the Bluetooth module.
+async fn mac_address_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::GetMacAddress).await
+}
+
+// parameters_handler() GET /api/sensors/{id}/parameters: maximum gates, gate sensitivities and no one duration.
+async fn parameters_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::ReadParameters).await
+}
+
+// resolution_handler() GET /api/sensors/{id}/resolution: distance covered by each gate ("0.75m" or "0.2m").
+async fn resolution_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::QueryDistanceResolution).await
+}
+
+#[derive(Deserialize)]
+struct ResolutionBody {
+    resolution: ld2410c::DistanceResolution,
+}
+
+// set_resolution_handler() PUT /api/sensors/{id}/resolution {"resolution": "0.2m"}, applied after a restart.
+async fn set_resolution_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<ResolutionBody>,
+) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::SetDistanceResolution(body.resolution)).await
+}
+
+// auxiliary_control_handler() GET /api/sensors/{id}/auxiliary-control: light control of the OUT pin.
+async fn auxiliary_control_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::QueryAuxiliaryControl).await
+}
+
+// set_auxiliary_control_handler() PUT /api/sensors/{id}/auxiliary-control
+// {"light_control": "BelowThreshold", "light_threshold": 128, "out_pin_default_level": "Low"}
+async fn set_auxiliary_control_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<ld2410c::AuxiliaryControl>,
+) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::SetAuxiliaryControl(body)).await
+}
+
+#[derive(Deserialize)]
+struct EnabledBody {
+    enabled: bool,
+}
+
+// set_engineering_mode_handler() PUT /api/sensors/{id}/engineering-mode {"enabled": true}
+async fn set_engineering_mode_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<EnabledBody>,
+) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::SetEngineeringMode(body.enabled)).await
+}
+
+#[derive(Deserialize)]
+struct GateSensitivityBody {
+    // Gate 0 to 8, all the gates if missing.
+    gate: Option<u8>,
+    motion_sensitivity: u8,
+    standstill_sensitivity: u8,
+}
+
+// set_gate_sensitivity_handler() PUT /api/sensors/{id}/gate-sensitivity
+// {"gate": 3, "motion_sensitivity": 40, "standstill_sensitivity": 30}
+async fn set_gate_sensitivity_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<GateSensitivityBody>,
+) -> Result<Json<Value>, ApiError> {
+    let command = Ld2410cCommand::gate_sensitivity(body.gate, body.motion_sensitivity, body.standstill_sensitivity)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    send_ld2410c_command(&hub, &id, command).await
+}
+
+#[derive(Deserialize)]
+struct BaudRateBody {
+    baud_rate: u32,
+}
+
+// set_baud_rate_handler() PUT /api/sensors/{id}/baud-rate {"baud_rate": 115200}, for the LD2410C (the radar is
+// restarted and reopened at the new rate) and the TF-Luna.
+async fn set_baud_rate_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<BaudRateBody>,
+) -> Result<Json<Value>, ApiError> {
+    let sensor = hub.get(&id).ok_or_else(|| ApiError::unknown_sensor(&id))?;
+    let unsupported = || ApiError::new(StatusCode::BAD_REQUEST, format!("unsupported baud rate {}", body.baud_rate));
+    let command = match sensor.config.kind {
+        SensorKind::Tfluna => {
+            if !tf_luna::BAUD_RATES.contains(&body.baud_rate) {
+                return Err(unsupported());
             }
+            Command::Tfluna(TflunaCommand::SetBaudRate(body.baud_rate))
+        }
+        SensorKind::Ld2410c => Command::Ld2410c(Ld2410cCommand::SetBaudRate(
+            ld2410c::BaudRate::from_baud(body.baud_rate).ok_or_else(unsupported)?,
+        )),
+        kind => {
+            let message = format!("baud rate not configurable for {}", kind.name());
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, message));
+        }
+    };
+    send_command(&hub, &id, command).await
+}
+
+// set_bluetooth_handler() PUT /api/sensors/{id}/bluetooth {"enabled": false}, applied after a restart.
+async fn set_bluetooth_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<EnabledBody>,
+) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::SetBluetooth(body.enabled)).await
+}
+
+#[derive(Deserialize)]
+struct PasswordBody {
+    password: String,
+}
+
+// set_bluetooth_password_handler() PUT /api/sensors/{id}/bluetooth-password {"password": "HiLink"}
+async fn set_bluetooth_password_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<PasswordBody>,
+) -> Result<Json<Value>, ApiError> {
+    if body.password.len() != 6 {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            ld2410c::CommandError::InvalidPassword.to_string(),
+        ));
+    }
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::SetBluetoothPassword(body.password)).await
+}
+
+// restart_handler() POST /api/sensors/{id}/restart
+async fn restart_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::Restart).await
+}
+
+// factory_reset_handler() POST /api/sensors/{id}/factory-reset, applied after a restart.
+async fn factory_reset_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    send_ld2410c_command(&hub, &id, Ld2410cCommand::FactoryReset).await
+}
+
+// settings_handler() GET /api/sensors/{id}/settings: settings applied through the API since the start of the server.
+async fn settings_handler(State(hub): State<Hub>, Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    let sensor = hub.get(&id).ok_or_else(|| ApiError::unknown_sensor(&id))?;
+    Ok(Json(sensor.settings()))
+}
+
+#[derive(Deserialize)]
+struct FrequencyBody {
+    frequency_hz: u16,
+}
+
+// set_frequency_handler() PUT /api/sensors/{id}/frequency {"frequency_hz": 100}: TF-Luna output frequency.
+async fn set_frequency_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<FrequencyBody>,
+) -> Result<Json<Value>, ApiError> {
+    let frequency = tf_luna::OutputFrequency::from_hz(body.frequency_hz).ok_or_else(|| {
+        ApiError::new(StatusCode::BAD_REQUEST, format!("unsupported frequency {} Hz", body.frequency_hz))
+    })?;
+    send_command(&hub, &id, Command::Tfluna(TflunaCommand::SetOutputFrequency(frequency))).await
+}
+
+#[derive(Deserialize)]
+struct DistanceLimitBody {
+    dist_min: u16,
+    dist_max: u16,
+}
+
+// set_distance_limit_handler() PUT /api/sensors/{id}/distance-limit {"dist_min": 20, "dist_max": 800}:
+// TF-Luna measuring range (cm).
+async fn set_distance_limit_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<DistanceLimitBody>,
+) -> Result<Json<Value>, ApiError> {
+    if body.dist_min >= body.dist_max {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "dist_min must be lower than dist_max"));
+    }
+    let command = TflunaCommand::SetDistanceLimit {
+        dist_min: body.dist_min,
+        dist_max: body.dist_max,
+    };
+    send_command(&hub, &id, Command::Tfluna(command)).await
+}
+
+#[derive(Deserialize)]
+struct OutputFormatBody {
+    output_format: tf_luna::OutputFormat,
+}
+
+// set_output_format_handler() PUT /api/sensors/{id}/output-format {"output_format": "NineByteCm"}: TF-Luna
+// data frame format. Only the NineByteCm format is decoded, the others are rejected.
+async fn set_output_format_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<OutputFormatBody>,
+) -> Result<Json<Value>, ApiError> {
+    if !body.output_format.is_decoded() {
+        let message = format!("output format {:?} is not decoded by the driver", body.output_format);
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, message));
+    }
+    send_command(&hub, &id, Command::Tfluna(TflunaCommand::SetOutputFormat(body.output_format))).await
+}
+
+// set_multi_mode_handler() PUT /api/sensors/{id}/multi-mode {"enabled": true}: RD03D multi-target tracking.
+async fn set_multi_mode_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<EnabledBody>,
+) -> Result<Json<Value>, ApiError> {
+    send_command(&hub, &id, Command::Rd03d(Rd03dCommand::SetMultiMode(body.enabled))).await
+}
+
+#[derive(Deserialize)]
+struct ModeBody {
+    mode: u8,
+}
+
+// set_mode_handler() PUT /api/sensors/{id}/mode {"mode": 4}: RD03D working mode.
+async fn set_mode_handler(
+    State(hub): State<Hub>,
+    Path(id): Path<String>,
+    Json(body): Json<ModeBody>,
+) -> Result<Json<Value>, ApiError> {
+    send_command(&hub, &id, Command::Rd03d(Rd03dCommand::SetMode(body.mode))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    // router() Returns the API of the LD2410C "radar", the TF-Luna "lidar" and the RD03D "tracker", whose tasks
+    // are not started, with the `extra` TOML lines of the configuration.
+    fn router(extra: &str) -> (Router, Hub) {
+        let mut toml = format!("{extra}\n");
+        for (id, kind) in [("radar", "ld2410c"), ("lidar", "tfluna"), ("tracker", "rd03d")] {
+            toml += &format!("[[sensors]]\nid = \"{id}\"\nkind = \"{kind}\"\n");
+        }
+        let config: Config = toml::from_str(&toml).unwrap();
+        let hub = Hub::without_tasks(&config.sensors);
+        (api(hub.clone(), None, &config), hub)
+    }
+
+    // request() Sends a request with the JSON `body` and the `Authorization` header, if any, and returns the
+    // status and the JSON of the response.
+    async fn request(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        authorization: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = router.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn configuration_endpoints_are_disabled_without_token() {
+        let (router, _) = router("");
+        let (status, body) = request(&router, "GET", "/api/sensors/radar/firmware", Some("Bearer secret"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body["error"].as_str().unwrap().contains("api_token"));
+    }
+
+    #[tokio::test]
+    async fn configuration_endpoints_require_the_token() {
+        let (router, _) = router("api_token = \"secret\"");
+        for authorization in [None, Some("Bearer wrong"), Some("Bearer secre"), Some("secret"), Some("Basic secret")] {
+            let (status, _) = request(&router, "GET", "/api/sensors/radar/firmware", authorization, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{authorization:?}");
+        }
+        // Accepted: the sensor is not connected.
+        let (status, _) = request(&router, "GET", "/api/sensors/radar/firmware", Some("Bearer secret"), None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn send_error_status() {
+        for (error, status) in [
+            (SendError::Unsupported(SensorKind::Tfluna), StatusCode::BAD_REQUEST),
+            (SendError::NotConnected, StatusCode::SERVICE_UNAVAILABLE),
+            (SendError::Busy, StatusCode::SERVICE_UNAVAILABLE),
+            (SendError::Timeout, StatusCode::GATEWAY_TIMEOUT),
+            (SendError::Failed("failed ACK".into()), StatusCode::BAD_GATEWAY),
+        ] {
+            let message = error.to_string();
+            let api_error = ApiError::from(error);
+            assert_eq!(api_error.status, status);
+            assert_eq!(api_error.message, message);
+        }
+    }
+
+    #[tokio::test]
+    async fn command_runs_in_the_sensor_task() {
+        let (router, hub) = router("api_token = \"secret\"");
+        hub.get("radar").unwrap().fake_session(|command| match command {
+            Command::Ld2410c(Ld2410cCommand::SetEngineeringMode(enabled)) => Ok(json!({ "enabled": enabled })),
+            other => anyhow::bail!("unexpected command {other:?}"),
+        });
+        let token = Some("Bearer secret");
+        let uri = "/api/sensors/radar/engineering-mode";
+        let (status, body) = request(&router, "PUT", uri, token, Some(json!({ "enabled": false }))).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "enabled": false })));
+        // Error of the sensor task.
+        let (status, body) = request(&router, "POST", "/api/sensors/radar/restart", token, None).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(body["error"].as_str().unwrap().contains("Restart"));
+        // Command of another kind of sensor.
+        let body = Some(json!({ "enabled": true }));
+        let (status, _) = request(&router, "PUT", "/api/sensors/lidar/engineering-mode", token, body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = request(&router, "PUT", "/api/sensors/sonar/engineering-mode", token, body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn baud_rate_is_checked_for_the_kind_of_sensor() {
+        let (router, hub) = router("api_token = \"secret\"");
+        hub.get("radar").unwrap().fake_session(|command| match command {
+            Command::Ld2410c(Ld2410cCommand::SetBaudRate(rate)) => Ok(json!({ "baud_rate": rate.to_baud() })),
+            other => anyhow::bail!("unexpected command {other:?}"),
+        });
+        let token = Some("Bearer secret");
+        let put = |id: &str, baud_rate: u32| {
+            let uri = format!("/api/sensors/{id}/baud-rate");
+            let router = router.clone();
+            async move { request(&router, "PUT", &uri, token, Some(json!({ "baud_rate": baud_rate }))).await }
+        };
+        assert_eq!(put("radar", 115200).await, (StatusCode::OK, json!({ "baud_rate": 115200 })));
+        let (status, body) = put("radar", 9601).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("unsupported baud rate 9601")));
+        let (status, body) = put("lidar", 1234).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("unsupported baud rate 1234")));
+        let (status, body) = put("tracker", 115200).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "baud rate not configurable for rd03d");
+    }
+
+    #[tokio::test]
+    async fn tf_luna_settings_are_checked() {
+        let (router, hub) = router("api_token = \"secret\"");
+        hub.get("lidar").unwrap().fake_session(|command| Ok(json!(format!("{command:?}"))));
+        let token = Some("Bearer secret");
+        for (uri, body, status) in [
+            ("frequency", json!({ "frequency_hz": 100 }), StatusCode::OK),
+            ("frequency", json!({ "frequency_hz": 99 }), StatusCode::BAD_REQUEST),
+            ("distance-limit", json!({ "dist_min": 20, "dist_max": 800 }), StatusCode::OK),
+            ("distance-limit", json!({ "dist_min": 800, "dist_max": 800 }), StatusCode::BAD_REQUEST),
+            ("output-format", json!({ "output_format": "NineByteCm" }), StatusCode::OK),
+            ("output-format", json!({ "output_format": "PIX" }), StatusCode::BAD_REQUEST),
+        ] {
+            let uri = format!("/api/sensors/lidar/{uri}");
+            let (actual, response) = request(&router, "PUT", &uri, token, Some(body.clone())).await;
+            assert_eq!(actual, status, "{uri} {body} {response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rd03d_settings() {
+        let (router, hub) = router("api_token = \"secret\"");
+        hub.get("tracker").unwrap().fake_session(|command| match command {
+            Command::Rd03d(Rd03dCommand::SetMultiMode(enabled)) => Ok(json!({ "multi_mode": enabled })),
+            Command::Rd03d(Rd03dCommand::SetMode(mode)) => Ok(json!({ "mode": mode })),
+            other => anyhow::bail!("unexpected command {other:?}"),
         });
-    Sse::new(stream).keep_alive(KeepAlive::default())
-}
\ No newline at end of file
+        let token = Some("Bearer secret");
+        let body = Some(json!({ "enabled": false }));
+        let (status, body) = request(&router, "PUT", "/api/sensors/tracker/multi-mode", token, body).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "multi_mode": false })));
+        let body = Some(json!({ "mode": 4 }));
+        let (status, body) = request(&router, "PUT", "/api/sensors/tracker/mode", token, body).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "mode": 4 })));
+        // Only the configuration is known before a command is run by the sensor task.
+        let (status, body) = request(&router, "GET", "/api/sensors/tracker/settings", token, None).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "multi_mode": true })));
+        let (status, body) = request(&router, "GET", "/api/sensors/radar/settings", token, None).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "engineering_mode": true })));
+    }
+
+    // LD2410C report: stationary target at 0x51 cm.
+    const LD2410C_REPORT: [u8; 23] = [
+        0xF4, 0xF3, 0xF2, 0xF1, 0x0D, 0x00, 0x02, 0xAA, 0x02, 0x51, 0x00, 0x00, 0x00, 0x00, 0x3B, 0x00,
+        0x00, 0x55, 0x00, 0xF8, 0xF7, 0xF6, 0xF5,
+    ];
+
+    #[tokio::test]
+    async fn latest_reading_and_status() {
+        let (router, hub) = router("");
+        let (status, _) = request(&router, "GET", "/api/sensors/radar/latest", None, None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let data = ld2410c::Ld2410CData::from_frame(&LD2410C_REPORT).unwrap().unwrap();
+        hub.get("radar").unwrap().fake_reading(Reading::Ld2410c(data.clone()));
+
+        let (status, body) = request(&router, "GET", "/api/sensors/radar/latest", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], serde_json::to_value(&data).unwrap());
+        assert!(body["timestamp_ms"].as_u64().unwrap() > 0);
+
+        let (status, body) = request(&router, "GET", "/api/sensors/radar/status", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["id"].as_str(), body["frames"].as_u64()), (Some("radar"), Some(1)));
+        assert_eq!(body["connected"], false);
+
+        let (status, body) = request(&router, "GET", "/api/sensors", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let ids: Vec<&str> = body.as_array().unwrap().iter().map(|sensor| sensor["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["radar", "lidar", "tracker"]);
+
+        let (status, _) = request(&router, "GET", "/api/sensors/sonar/status", None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn dashboards_render() {
+        let (router, hub) = router("");
+        let data = ld2410c::Ld2410CData::from_frame(&LD2410C_REPORT).unwrap().unwrap();
+        hub.get("radar").unwrap().fake_reading(Reading::Ld2410c(data));
+        for uri in ["/ld2410c", "/tfluna", "/rd03d", "/room"] {
+            let (status, _) = request(&router, "GET", uri, None, None).await;
+            assert_eq!(status, StatusCode::OK, "{uri}");
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use sensor::tof200f;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "COM7".to_string());
+    let mut tof200f = tof200f::TOF200F::new(path);
+    if let Err(e) = tof200f.connect().await {
+        eprintln!("Error connecting to Tof200F: {}", e);
+        return;
+    }
+    else {
+        loop {
+            match tof200f.read_data().await {
+                Ok(data) => {
+                    println!("Distance: {:?} mm", data.distance());
+                }
+                Err(e) => {
+                    eprintln!("Error reading data from Tof200F: {}", e);
+                }
+                
+            }
+        }
+    }
+}
@@ -0,0 +1,420 @@
+// blocking/ld2410c.rs
+// Blocking version of the LD2410C driver. It sends the same command frames (Ld2410CFrame) and decodes
+// the same report frames (Ld2410CData) as the async driver in ld2410c.rs.
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use serialport::SerialPort;
+
+use crate::ld2410c::{
+    is_missing_ack, next_ack, next_report, AuxiliaryControl, BaudRate, BluetoothModule, DecodeError,
+    DistanceResolution, GateValue, Ld2410CData, Ld2410CFrame,
+};
+
+// Default number of times a command is sent again when its ACK is not received.
+pub const DEFAULT_RETRIES: u8 = 2;
+// Maximum time to wait for the ACK of a command during the baud rate detection.
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+// Time for the radar to restart, e.g. at its new baud rate.
+const RESTART_DELAY: Duration = Duration::from_millis(1500);
+
+pub struct Ld2410C {
+    path: String,
+    baud_rate: u32,
+    timeout: Duration,
+    retries: u8,
+    port: Option<Box<dyn SerialPort>>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
+}
+
+impl Ld2410C {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            // Set defaut baud rate to 256000
+            // This can be changed later using the `set_baud_rate` method
+            baud_rate: 256000,
+            timeout: super::DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            port: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+
+    // baud_rate() Returns the baud rate the port is opened with, e.g. after detect_baud_rate().
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    // set_timeout() Sets the read timeout of the port, which is also the deadline of the ACK of a command.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // set_retries() Sets the number of times a command is sent again when its ACK is not received.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    // connect() This method opens the serial port connection to the LD2410C radar module.
+    pub fn connect(&mut self) -> anyhow::Result<()> {
+        let port = serialport::new(&self.path, self.baud_rate)
+            .timeout(self.timeout)
+            .open()?;
+        self.port = Some(port);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn port(&mut self) -> anyhow::Result<&mut Box<dyn SerialPort>> {
+        self.port
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("LD2410C is not connected"))
+    }
+
+    // read_data() This method reads data from the LD2410C radar module. The received bytes are kept until a
+    // complete report frame is received, which is decoded. ACK frames are discarded. Fails if no report frame
+    // was received before the timeout (set_timeout()).
+    pub fn read_data(&mut self) -> anyhow::Result<Ld2410CData> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(data) = next_report(&mut self.buffer) {
+                return Ok(data?);
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!("no report from the LD2410C within {:?}", self.timeout);
+            }
+            let n = self.port()?.read(&mut buf)?;
+            self.buffer.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn write_data(&mut self, command: &[u8]) -> anyhow::Result<()> {
+        let port = self.port()?;
+        port.write_all(command)?;
+        port.flush()?;
+        Ok(())
+    }
+
+    // send_command() Sends a command frame between the enable and end configuration commands
+    // and returns its ACK frame.
+    fn send_command(&mut self, frame: Ld2410CFrame) -> anyhow::Result<Vec<u8>> {
+        self.command_with_retries(Ld2410CFrame::enabling_configuration())?;
+        let response = self.command_with_retries(frame)?;
+        self.command_with_retries(Ld2410CFrame::ending_configuration())?;
+        Ok(response)
+    }
+
+    pub fn read_firmware_version(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::read_firmware_version())
+    }
+
+    pub fn set_bluetooth_module(&mut self, module: BluetoothModule) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::bluetooth_module(module))
+    }
+
+    pub fn set_bluetooth_password(&mut self, password: &str) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::bluetooth_password(password)?)
+    }
+
+    pub fn obtaining_bluetooth_permissions(&mut self, password: &str) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::obtaining_bluetooth_permissions(password)?)
+    }
+
+    pub fn set_engineering_mode(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::engineering_mode())
+    }
+
+    pub fn set_engineering_mode_off(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::engineering_mode_off())
+    }
+
+    pub fn read_parameter(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::read_parameter())
+    }
+
+    pub fn set_restart_module(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::restart_module())
+    }
+
+    pub fn restore_factory_settings(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::restore_factory_settings())
+    }
+
+    pub fn set_distance_resolution_setting(
+        &mut self,
+        distance_resolution: DistanceResolution,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::distance_resolution_setting(distance_resolution))
+    }
+
+    pub fn query_distance_resolution_setting(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::query_distance_resolution_setting())
+    }
+
+    pub fn get_mac_adress(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::mac_address())
+    }
+
+    // read_ack() Reads until the ACK of the command `word` is received and returns the ACK frame. The report
+    // frames and the ACKs of other commands received in between are discarded. Fails with
+    // DecodeError::MissingAck after `timeout`, or if the radar reports a failure.
+    fn read_ack(&mut self, word: u16, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(ack) = next_ack(&mut self.buffer, word) {
+                return Ok(ack?);
+            }
+            if Instant::now() >= deadline {
+                return Err(DecodeError::MissingAck(word).into());
+            }
+            match self.port()?.read(&mut buf) {
+                Ok(n) => self.buffer.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn command_with_ack(&mut self, frame: &Ld2410CFrame, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        self.write_data(&frame.to_u8())?;
+        self.read_ack(frame.word(), timeout)
+    }
+
+    // command_with_retries() Sends a command frame and waits for its ACK, sending it again up to `retries`
+    // times when the ACK is not received before the timeout.
+    fn command_with_retries(&mut self, frame: Ld2410CFrame) -> anyhow::Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            match self.command_with_ack(&frame, self.timeout) {
+                Err(e) if attempt < self.retries && is_missing_ack(&e) => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    // check_communication() Checks that the radar answers at the current baud rate: sends the enable then
+    // end configuration commands and waits for their ACKs.
+    pub fn check_communication(&mut self) -> anyhow::Result<()> {
+        self.command_with_ack(&Ld2410CFrame::enabling_configuration(), ACK_TIMEOUT)?;
+        self.command_with_ack(&Ld2410CFrame::ending_configuration(), ACK_TIMEOUT)?;
+        Ok(())
+    }
+
+    // detect_baud_rate() Opens the port at each baud rate of the radar (BaudRate::ALL, the factory default
+    // first) until the radar acknowledges the enable configuration command. The port stays open at the
+    // detected baud rate, which is returned.
+    pub fn detect_baud_rate(&mut self) -> anyhow::Result<u32> {
+        let timeout = self.timeout;
+        // Short reads, so that read_ack() checks its deadline.
+        self.timeout = timeout.min(ACK_TIMEOUT / 5);
+        let mut detected = None;
+        for baud_rate in BaudRate::ALL {
+            self.port = None;
+            self.baud_rate = baud_rate.to_baud();
+            self.connect()?;
+            if self.check_communication().is_ok() {
+                detected = Some(self.baud_rate);
+                break;
+            }
+        }
+        self.timeout = timeout;
+        match detected {
+            Some(baud_rate) => {
+                self.port()?.set_timeout(timeout)?;
+                Ok(baud_rate)
+            }
+            None => {
+                self.port = None;
+                anyhow::bail!("no LD2410C answered on {} at any baud rate", self.path)
+            }
+        }
+    }
+
+    // change_baud_rate() Sets the baud rate of the radar, restarts it so that the rate takes effect and
+    // reopens the port at the new rate. Returns once the radar answers at the new rate. If it does not, the
+    // radar is looked for at every baud rate (detect_baud_rate()) so that the driver does not lose it, and
+    // an error is returned.
+    pub fn change_baud_rate(&mut self, baud_rate: BaudRate) -> anyhow::Result<()> {
+        let new_baud_rate = baud_rate.to_baud();
+        self.command_with_retries(Ld2410CFrame::enabling_configuration())?;
+        self.command_with_retries(Ld2410CFrame::serial_port_baud_rate(baud_rate))?;
+        // The radar does not send the ending configuration ACK after a restart.
+        self.command_with_retries(Ld2410CFrame::restart_module())?;
+        self.port = None;
+        std::thread::sleep(RESTART_DELAY);
+        self.baud_rate = new_baud_rate;
+        self.connect()?;
+        if let Err(e) = self.check_communication() {
+            match self.detect_baud_rate() {
+                Ok(found) => anyhow::bail!("the radar does not answer at {new_baud_rate} baud ({e}), it answers at {found} baud"),
+                Err(_) => anyhow::bail!("the radar does not answer at {new_baud_rate} baud ({e}) nor at any other rate"),
+            }
+        }
+        Ok(())
+    }
+
+    // set_serial_port_baud_rate() Takes effect after the radar restarts, see change_baud_rate() to change the
+    // baud rate without losing the radar.
+    pub fn set_serial_port_baud_rate(&mut self, baud_rate: BaudRate) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::serial_port_baud_rate(baud_rate))
+    }
+
+    pub fn set_distance_gate_sensitivity_configuration(
+        &mut self,
+        distance_gate: GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    ) -> anyhow::Result<Vec<u8>> {
+        let frame = Ld2410CFrame::distance_gate_sensitivity_configuration(
+            distance_gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        )?;
+        self.send_command(frame)
+    }
+
+    pub fn set_auxiliary_control(&mut self, auxiliary_control: AuxiliaryControl) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::auxiliary_control(auxiliary_control))
+    }
+
+    pub fn query_auxiliary_control(&mut self) -> anyhow::Result<AuxiliaryControl> {
+        let response = self.send_command(Ld2410CFrame::query_auxiliary_control())?;
+        Ok(AuxiliaryControl::from_response(&response)?)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::ld2410c::DataType;
+    use serialport::TTYPort;
+
+    const BASIC_REPORT: [u8; 23] = [
+        0xF4, 0xF3, 0xF2, 0xF1, 0x0D, 0x00, 0x02, 0xAA, 0x02, 0x51, 0x00, 0x00, 0x00, 0x00, 0x3B, 0x00,
+        0x00, 0x55, 0x00, 0xF8, 0xF7, 0xF6, 0xF5,
+    ];
+
+    // connected() Returns a driver connected to one end of a pseudo terminal, and the radar end.
+    fn connected(timeout: Duration) -> (Ld2410C, TTYPort) {
+        let (radar, mut port) = TTYPort::pair().unwrap();
+        port.set_timeout(timeout).unwrap();
+        let mut ld2410c = Ld2410C::new("test".to_string());
+        ld2410c.timeout = timeout;
+        ld2410c.port = Some(Box::new(port));
+        (ld2410c, radar)
+    }
+
+    #[test]
+    fn reads_report_split_across_reads() {
+        let (mut ld2410c, mut radar) = connected(Duration::from_millis(500));
+        radar.write_all(&[0x00, 0x13]).unwrap();
+        radar.write_all(&BASIC_REPORT[..10]).unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            radar.write_all(&BASIC_REPORT[10..]).unwrap();
+            radar
+        });
+        let data = ld2410c.read_data().unwrap();
+        writer.join().unwrap();
+        assert_eq!(data.data_type, DataType::TargetBasicInformation);
+        assert_eq!(data.target_data.movement_target_distance, 0x51);
+        assert_eq!(data.target_data.stationary_target_energy, 0x3B);
+    }
+
+    #[test]
+    fn keeps_reports_received_in_one_read() {
+        let (mut ld2410c, mut radar) = connected(Duration::from_millis(500));
+        radar.write_all(&[BASIC_REPORT, BASIC_REPORT].concat()).unwrap();
+        for _ in 0..2 {
+            assert_eq!(ld2410c.read_data().unwrap().data_type, DataType::TargetBasicInformation);
+        }
+    }
+
+    // ack() Returns the ACK frame of the command `word`, reporting a success.
+    fn ack(word: u16) -> Vec<u8> {
+        let mut ack = vec![0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00];
+        ack.extend_from_slice(&(word | 0x0100).to_le_bytes());
+        ack.extend_from_slice(&[0x00, 0x00, 0x04, 0x03, 0x02, 0x01]);
+        ack
+    }
+
+    #[test]
+    fn read_ack_skips_reports_and_other_acks() {
+        let (mut ld2410c, mut radar) = connected(Duration::from_millis(50));
+        radar.write_all(&[&BASIC_REPORT[..], &ack(0x00FE), &ack(0x00FF)].concat()).unwrap();
+        let ack = ld2410c.read_ack(0x00FF, Duration::from_millis(500)).unwrap();
+        assert_eq!(&ack[6..8], &[0xFF, 0x01]);
+    }
+
+    #[test]
+    fn read_ack_gives_up_at_the_deadline_while_reports_arrive() {
+        let (mut ld2410c, mut radar) = connected(Duration::from_millis(20));
+        // Reports keep arriving after the deadline, each read returning before the read timeout.
+        let writer = std::thread::spawn(move || {
+            for _ in 0..50 {
+                radar.write_all(&BASIC_REPORT).unwrap();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let started = Instant::now();
+        let error = ld2410c.read_ack(0x00FF, Duration::from_millis(100)).unwrap_err();
+        let elapsed = started.elapsed();
+        writer.join().unwrap();
+        assert!(is_missing_ack(&error), "{error}");
+        assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(300), "{elapsed:?}");
+    }
+
+    #[test]
+    fn read_ack_gives_up_at_the_deadline_of_a_silent_radar() {
+        let (mut ld2410c, _radar) = connected(Duration::from_millis(20));
+        let started = Instant::now();
+        let error = ld2410c.read_ack(0x00FF, Duration::from_millis(100)).unwrap_err();
+        assert!(is_missing_ack(&error), "{error}");
+        assert!(started.elapsed() < Duration::from_millis(300), "{:?}", started.elapsed());
+    }
+
+    // answer_after() Reads the command frames (12 bytes without a value) of the driver and acknowledges them
+    // from the `ignored`+1-th one. Returns the number of frames read, and the radar end kept open until then.
+    fn answer_after(mut radar: TTYPort, ignored: usize, frames: usize) -> std::thread::JoinHandle<(usize, TTYPort)> {
+        std::thread::spawn(move || {
+            radar.set_timeout(Duration::from_millis(500)).unwrap();
+            let mut read = 0;
+            let mut frame = [0u8; 12];
+            while read < frames && radar.read_exact(&mut frame).is_ok() {
+                read += 1;
+                if read > ignored {
+                    radar.write_all(&ack(u16::from_le_bytes([frame[6], frame[7]]))).unwrap();
+                }
+            }
+            (read, radar)
+        })
+    }
+
+    #[test]
+    fn command_with_retries_sends_the_command_again() {
+        let (mut ld2410c, radar) = connected(Duration::from_millis(50));
+        ld2410c.set_retries(1);
+        let radar = answer_after(radar, 1, 2);
+        let ack = ld2410c.command_with_retries(Ld2410CFrame::ending_configuration()).unwrap();
+        assert_eq!(&ack[6..8], &[0xFE, 0x01]);
+        assert_eq!(radar.join().unwrap().0, 2);
+    }
+
+    #[test]
+    fn command_with_retries_fails_without_ack() {
+        let (mut ld2410c, radar) = connected(Duration::from_millis(50));
+        ld2410c.set_retries(2);
+        let radar = answer_after(radar, 3, 3);
+        let error = ld2410c.command_with_retries(Ld2410CFrame::ending_configuration()).unwrap_err();
+        assert!(is_missing_ack(&error), "{error}");
+        assert_eq!(radar.join().unwrap().0, 3);
+    }
+}
@@ -0,0 +1,17 @@
+// blocking
+// Synchronous drivers built on the serialport crate, for small single-threaded programs that do not
+// want to depend on tokio. They use the same frame encoders/decoders as the async drivers.
+#[cfg(feature = "ld2410c")]
+pub mod ld2410c;
+#[cfg(feature = "rd03d")]
+pub mod rd03d;
+#[cfg(feature = "tf_luna")]
+pub mod tf_luna;
+#[cfg(feature = "tof200f")]
+pub mod tof200f;
+
+use std::time::Duration;
+
+// Default read timeout of the blocking drivers. serialport requires a timeout, a read
+// returns an error of kind TimedOut when no byte was received before it expires.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
@@ -0,0 +1,133 @@
+// blocking/rd03d.rs
+// Blocking version of the RD03D driver, sharing the frame encoders/decoders of rd03d.rs.
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use serialport::SerialPort;
+
+use crate::rd03d::{ack_frame, extract_latest_targets, target_mode_command, RD03DFrame, Target, DEFAULT_RETRIES};
+
+pub struct RD03D {
+    pub path: String,
+    pub baud_rate: u32,
+    pub timeout: Duration,
+    pub port: Option<Box<dyn SerialPort>>,
+    pub targets: Vec<Target>,
+    pub buffer: Vec<u8>,
+    pub multi_mode: bool,
+    // Number of times a command is sent again when its ACK is not received.
+    pub retries: u8,
+}
+
+impl RD03D {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            baud_rate: 256000,
+            timeout: super::DEFAULT_TIMEOUT,
+            port: None,
+            targets: Vec::new(),
+            buffer: Vec::new(),
+            multi_mode: true,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+
+    pub fn connect(&mut self) -> anyhow::Result<()> {
+        let port = serialport::new(&self.path, self.baud_rate)
+            .timeout(self.timeout)
+            .open()?;
+        self.port = Some(port);
+        std::thread::sleep(Duration::from_millis(200));
+        self.set_multi_mode(self.multi_mode)?;
+        Ok(())
+    }
+
+    fn port(&mut self) -> anyhow::Result<&mut Box<dyn SerialPort>> {
+        self.port
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("RD03D is not connected"))
+    }
+
+    pub fn set_multi_mode(&mut self, multi_mode: bool) -> anyhow::Result<()> {
+        let port = self.port()?;
+        port.write_all(target_mode_command(multi_mode))?;
+        port.flush()?;
+        std::thread::sleep(Duration::from_millis(200));
+        port.clear(serialport::ClearBuffer::Input)?;
+        self.buffer.clear();
+        self.multi_mode = multi_mode;
+        Ok(())
+    }
+
+    // update() Reads the available bytes and decodes the latest complete frame.
+    // Returns true if the targets were updated.
+    pub fn update(&mut self) -> anyhow::Result<bool> {
+        let mut buf = [0u8; 256];
+        let n = self.port()?.read(&mut buf)?;
+        self.buffer.extend_from_slice(&buf[..n]);
+        if let Some(targets) = extract_latest_targets(&mut self.buffer) {
+            self.targets = targets;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn get_target(&self, target_number: usize) -> Option<&Target> {
+        if target_number >= 1 && target_number <= self.targets.len() {
+            self.targets.get(target_number - 1)
+        } else {
+            None
+        }
+    }
+
+    // read_ack() Reads until the ACK of the command `word` is received and returns the ACK frame. The report
+    // frames received in between are discarded. Returns None if it was not received before the timeout.
+    fn read_ack(&mut self, word: u16) -> anyhow::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut response = Vec::new();
+        let mut buf = [0u8; 256];
+        while Instant::now() < deadline {
+            match self.port()?.read(&mut buf) {
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+            if let Some(ack) = ack_frame(&response, word)? {
+                return Ok(Some(ack.to_vec()));
+            }
+        }
+        Ok(None)
+    }
+
+    // send_command() Writes a command frame and returns its ACK frame, sending it again up to `retries` times
+    // when the ACK is not received before the timeout.
+    fn send_command(&mut self, frame: RD03DFrame) -> anyhow::Result<Vec<u8>> {
+        let word = frame.word();
+        for _ in 0..=self.retries {
+            let port = self.port()?;
+            port.write_all(&frame.to_u8())?;
+            port.flush()?;
+            if let Some(ack) = self.read_ack(word)? {
+                return Ok(ack);
+            }
+        }
+        anyhow::bail!("no ACK for RD03D command {word:#06X}")
+    }
+
+    pub fn open_command_mode(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(RD03DFrame::open_command_mode())
+    }
+
+    pub fn close_command_mode(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.send_command(RD03DFrame::close_command_mode())
+    }
+
+    pub fn set_mode(&mut self, mode: u8) -> anyhow::Result<Vec<u8>> {
+        self.send_command(RD03DFrame::set_mode(mode))
+    }
+}
@@ -0,0 +1,228 @@
+// blocking/tf_luna.rs
+// Blocking version of the TF-Luna driver, sharing the command layouts (TfLunaCommand) of tf_luna.rs.
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use serialport::SerialPort;
+
+use crate::tf_luna::{
+    find_data_frame, find_response, OutputFormat, OutputFrequency, OutputMode, TfLunaCommand, TfLunaData,
+    DATA_FRAME_LEN, DEFAULT_RETRIES,
+};
+
+pub struct TfLuna {
+    path: String,
+    baud_rate: u32,
+    timeout: Duration,
+    retries: u8,
+    port: Option<Box<dyn SerialPort>>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
+}
+
+impl TfLuna {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            // Set default baud rate to 115200
+            // This can be changed later using the `set_baud_rate` method
+            baud_rate: 115200,
+            timeout: super::DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            port: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+
+    // set_timeout() Sets the read timeout of the port, which is also the deadline of the response to a command.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // set_retries() Sets the number of times a command is sent again when its response is not received.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    pub fn connect(&mut self) -> anyhow::Result<()> {
+        let port = serialport::new(&self.path, self.baud_rate)
+            .timeout(self.timeout)
+            .open()?;
+        self.port = Some(port);
+        Ok(())
+    }
+
+    fn port(&mut self) -> anyhow::Result<&mut Box<dyn SerialPort>> {
+        self.port
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("TF-Luna is not connected"))
+    }
+
+    // read_data() Reads until a data frame with a valid checksum is received and decodes it. Fails if no data
+    // frame was received before the timeout.
+    pub fn read_data(&mut self) -> anyhow::Result<TfLunaData> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 32];
+        loop {
+            if let Some((frame, end)) = find_data_frame(&self.buffer) {
+                let data = TfLunaData::new(frame);
+                self.buffer.drain(..end);
+                return Ok(data);
+            }
+            // Keep the last bytes, they may be the beginning of a frame.
+            self.buffer.drain(..self.buffer.len().saturating_sub(DATA_FRAME_LEN - 1));
+            if Instant::now() >= deadline {
+                anyhow::bail!("no data frame from the TF-Luna within {:?}", self.timeout);
+            }
+            let n = self.port()?.read(&mut buf)?;
+            self.buffer.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    pub fn write_data(&mut self, command: &[u8]) -> anyhow::Result<()> {
+        let port = self.port()?;
+        port.write_all(command)?;
+        port.flush()?;
+        Ok(())
+    }
+
+    // read_response() Reads until the response to the command `id` is received and returns it. The data frames
+    // received in between are discarded. Returns None if it was not received before the timeout.
+    fn read_response(&mut self, id: u8) -> anyhow::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut response = Vec::new();
+        let mut buf = [0u8; 256];
+        while Instant::now() < deadline {
+            match self.port()?.read(&mut buf) {
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+            if let Some((frame, _)) = find_response(&response, id) {
+                return Ok(Some(frame.to_vec()));
+            }
+        }
+        Ok(None)
+    }
+
+    // send_command() Sends a command and returns the response of the TF-Luna, which repeats the command id,
+    // sending it again up to `retries` times when the response is not received before the timeout.
+    pub fn send_command(&mut self, command: TfLunaCommand) -> anyhow::Result<Vec<u8>> {
+        for _ in 0..=self.retries {
+            self.write_data(&command.to_u8())?;
+            if let Some(response) = self.read_response(command.id())? {
+                return Ok(response);
+            }
+        }
+        anyhow::bail!("no response from the TF-Luna to command {:#04X}", command.id())
+    }
+
+    pub fn set_output_format_setting(&mut self, format: OutputFormat) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::OutputFormat(format))?;
+        Ok(())
+    }
+
+    pub fn set_baud_rate_setting(&mut self, baud_rate: u32) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::BaudRate(baud_rate))?;
+        Ok(())
+    }
+
+    pub fn set_distance_limit_setting(&mut self, dist_min: u16, dist_max: u16) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::DistanceLimit { dist_min, dist_max })?;
+        Ok(())
+    }
+
+    pub fn set_output_frequency(&mut self, freq: OutputFrequency) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::OutputFrequency(freq))?;
+        Ok(())
+    }
+
+    pub fn get_configuration(&mut self, output_mode: OutputMode) -> anyhow::Result<TfLunaData> {
+        self.write_data(&TfLunaCommand::GetConfiguration(output_mode).to_u8())?;
+        self.read_data()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serialport::TTYPort;
+
+    // Distance 0x0123 cm, amplitude 0x0210.
+    const FRAME: [u8; 9] = [0x59, 0x59, 0x23, 0x01, 0x10, 0x02, 0x84, 0x09, 0x75];
+
+    // connected() Returns a driver connected to one end of a pseudo terminal, and the TF-Luna end.
+    fn connected(timeout: Duration) -> (TfLuna, TTYPort) {
+        let (lidar, mut port) = TTYPort::pair().unwrap();
+        port.set_timeout(timeout).unwrap();
+        let mut tf_luna = TfLuna::new("test".to_string());
+        tf_luna.timeout = timeout;
+        tf_luna.port = Some(Box::new(port));
+        (tf_luna, lidar)
+    }
+
+    // answer_after() Reads the commands of the driver and, from the `ignored`+1-th one, sends a data frame then
+    // repeats the command as its response. Returns the number of commands read, and the TF-Luna end kept open
+    // until then.
+    fn answer_after(mut lidar: TTYPort, ignored: usize, commands: usize) -> std::thread::JoinHandle<(usize, TTYPort)> {
+        std::thread::spawn(move || {
+            lidar.set_timeout(Duration::from_millis(500)).unwrap();
+            let mut read = 0;
+            let mut header = [0u8; 2];
+            while read < commands && lidar.read_exact(&mut header).is_ok() {
+                let mut command = header.to_vec();
+                command.resize(header[1] as usize, 0);
+                lidar.read_exact(&mut command[2..]).unwrap();
+                read += 1;
+                if read > ignored {
+                    lidar.write_all(&[&FRAME[..], &command].concat()).unwrap();
+                }
+            }
+            (read, lidar)
+        })
+    }
+
+    #[test]
+    fn read_data_skips_invalid_frames() {
+        let (mut tf_luna, mut lidar) = connected(Duration::from_millis(500));
+        let mut invalid = FRAME;
+        invalid[8] ^= 0xFF;
+        lidar.write_all(&[&[0x00, 0x59][..], &invalid, &FRAME].concat()).unwrap();
+        let data = tf_luna.read_data().unwrap();
+        assert_eq!(data.distance, 0x0123);
+        assert_eq!(data.amplitude, 0x0210);
+    }
+
+    #[test]
+    fn send_command_skips_data_frames() {
+        let (mut tf_luna, lidar) = connected(Duration::from_millis(100));
+        let lidar = answer_after(lidar, 0, 1);
+        let command = TfLunaCommand::OutputFrequency(OutputFrequency::Freq10Hz);
+        assert_eq!(tf_luna.send_command(command).unwrap(), command.to_u8().as_slice());
+        assert_eq!(lidar.join().unwrap().0, 1);
+    }
+
+    #[test]
+    fn send_command_sends_the_command_again() {
+        let (mut tf_luna, lidar) = connected(Duration::from_millis(100));
+        tf_luna.set_retries(1);
+        let lidar = answer_after(lidar, 1, 2);
+        let command = TfLunaCommand::DistanceLimit { dist_min: 20, dist_max: 800 };
+        assert_eq!(tf_luna.send_command(command).unwrap(), command.to_u8().as_slice());
+        assert_eq!(lidar.join().unwrap().0, 2);
+    }
+
+    #[test]
+    fn send_command_fails_without_response() {
+        let (mut tf_luna, lidar) = connected(Duration::from_millis(100));
+        tf_luna.set_retries(1);
+        let lidar = answer_after(lidar, 2, 2);
+        let error = tf_luna.send_command(TfLunaCommand::OutputFrequency(OutputFrequency::Freq10Hz)).unwrap_err();
+        assert_eq!(error.to_string(), "no response from the TF-Luna to command 0x03");
+        assert_eq!(lidar.join().unwrap().0, 2);
+    }
+}
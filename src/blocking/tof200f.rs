@@ -0,0 +1,70 @@
+// blocking/tof200f.rs
+// Blocking version of the TOF200F driver, sharing the decoder (Tof200fData) of tof200f.rs.
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use serialport::SerialPort;
+
+use crate::tof200f::{find_response, Tof200fData, RESPONSE_LEN};
+
+pub struct TOF200F {
+    path: String,
+    baud_rate: u32,
+    timeout: Duration,
+    port: Option<Box<dyn SerialPort>>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
+}
+
+impl TOF200F {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            baud_rate: 115200,
+            timeout: super::DEFAULT_TIMEOUT,
+            port: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn connect(&mut self) -> anyhow::Result<()> {
+        let port = serialport::new(&self.path, self.baud_rate)
+            .timeout(self.timeout)
+            .open()?;
+        self.port = Some(port);
+        Ok(())
+    }
+
+    // read_data() Reads until a response with a valid CRC is received and decodes the distance. Fails if no
+    // response was received before the timeout.
+    pub fn read_data(&mut self) -> anyhow::Result<Tof200fData> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some((frame, end)) = find_response(&self.buffer) {
+                let data = Tof200fData::from_frame(frame);
+                self.buffer.drain(..end);
+                return data.ok_or_else(|| anyhow::anyhow!("invalid TOF200F response"));
+            }
+            // Keep the last bytes, they may be the beginning of a response.
+            self.buffer.drain(..self.buffer.len().saturating_sub(RESPONSE_LEN - 1));
+            if Instant::now() >= deadline {
+                anyhow::bail!("no response from the TOF200F within {:?}", self.timeout);
+            }
+            let port = self
+                .port
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("TOF200F is not connected"))?;
+            let n = port.read(&mut buf)?;
+            self.buffer.extend_from_slice(&buf[..n]);
+        }
+    }
+}
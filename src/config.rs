@@ -0,0 +1,536 @@
+// config.rs
+// Configuration of the web server: listening address and the sensors to open, loaded from a TOML file.
+//
+// bind = "127.0.0.1:3000"
+// api_token = "change-me"
+// allowed_origins = ["https://dashboard.example.com"]
+//
+// [[sensors]]
+// id = "radar"
+// kind = "ld2410c"
+// port = "COM7"
+// engineering_mode = true
+//
+// [[sensors]]
+// id = "lidar"
+// kind = "tfluna"
+// usb = { vid = 0x10c4, pid = 0xea60, serial_number = "0001" }
+//
+// [[sensors]]
+// id = "entrance"
+// kind = "presence"
+// presence = { radar = "radar", lidar = "lidar", lidar_offset_cm = -3 }
+//
+// [[sensors]]
+// id = "living_room"
+// kind = "room"
+// room = { radars = [
+//     { id = "rd03d_door", x_mm = 0, y_mm = 0, rotation_deg = 0 },
+//     { id = "rd03d_window", x_mm = 4000, y_mm = 3000, rotation_deg = 90 },
+// ] }
+//
+// [stream]
+// max_hz = 20.0
+//
+// [mqtt]
+// host = "localhost"
+// prefix = "sensor"
+//
+// [history]
+// dir = "history"
+//
+// The configuration endpoints (/api/sensors/{id}/firmware, ...) require the header
+// `Authorization: Bearer <api_token>`; they are disabled if no api_token is set.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorKind {
+    Ld2410c,
+    Rd03d,
+    Tfluna,
+    Tof200f,
+    // Virtual sensor fusing an LD2410C and a lidar (fusion/presence.rs).
+    Presence,
+    // Virtual sensor merging the targets of several RD03D into one room map (fusion/room.rs).
+    Room,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorConfig {
+    // Identifier used in the API routes (/api/sensors/{id}/...).
+    pub id: String,
+    pub kind: SensorKind,
+    // Path of the serial port. May be omitted if `usb` is set, and for the virtual sensors.
+    #[serde(default)]
+    pub port: String,
+    // USB adapter of the sensor: the port is looked up by these identifiers on each connection instead of
+    // using `port`, so that the sensor is found again if the adapter is plugged back in under another name.
+    #[serde(default)]
+    pub usb: Option<UsbDevice>,
+    // Defaults to the baud rate of the driver.
+    #[serde(default)]
+    pub baud_rate: Option<u32>,
+    // Deadline of a read and of the answer to a command, in milliseconds. Defaults to the one of the driver;
+    // a missed deadline is counted as an error and the sensor is reopened after 3 in a row.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    // Number of times a command is sent again when the sensor does not answer. Defaults to the one of the driver.
+    #[serde(default)]
+    pub retries: Option<u8>,
+    // LD2410C only: turns the engineering mode on after connecting.
+    #[serde(default = "default_engineering_mode")]
+    pub engineering_mode: bool,
+    // RD03D only: multi-target tracking (single target if false), applied when (re)connecting.
+    #[serde(default = "default_multi_mode")]
+    pub multi_mode: bool,
+    // MQTT topic of the readings, <prefix>/<id>/state by default.
+    #[serde(default)]
+    pub mqtt_topic: Option<String>,
+    // Presence sensors only: the fused sensors and the fusion rules.
+    #[serde(default)]
+    pub presence: Option<PresenceConfig>,
+    // Room sensors only: the poses of the RD03D and the tracking parameters.
+    #[serde(default)]
+    pub room: Option<RoomConfig>,
+}
+
+fn default_engineering_mode() -> bool {
+    true
+}
+
+fn default_multi_mode() -> bool {
+    true
+}
+
+// PresenceConfig is a presence sensor fusing an LD2410C and a lidar (TF-Luna or TOF200F) mounted on the same
+// bracket (fusion/presence.rs). The offsets are added to the distances of each sensor so that both are
+// measured from the same reference, e.g. the front of the bracket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceConfig {
+    // Ids of the LD2410C and of the TF-Luna or TOF200F.
+    pub radar: String,
+    pub lidar: String,
+    #[serde(default)]
+    pub radar_offset_cm: i32,
+    #[serde(default)]
+    pub lidar_offset_cm: i32,
+    // Largest difference between the distances of the radar and of the lidar for them to see the same target.
+    // The radar distance is only known to a distance gate (75 cm by default).
+    #[serde(default = "default_agreement_cm")]
+    pub agreement_cm: u32,
+    // TF-Luna readings with a lower signal strength are ignored.
+    #[serde(default = "default_min_amplitude")]
+    pub min_amplitude: u16,
+    // Lidar distances beyond this range are ignored (no return).
+    #[serde(default = "default_lidar_range_cm")]
+    pub lidar_range_cm: u32,
+    // Distance of the wall or furniture seen by the lidar when nobody is there. When set, a lidar distance
+    // at the background means the person is out of the beam, and a shorter one is a still person even if
+    // the radar misses it.
+    #[serde(default)]
+    pub background_cm: Option<u32>,
+    // Rule applied when the radar and the lidar see targets at different distances.
+    #[serde(default)]
+    pub on_disagreement: Disagreement,
+    // Readings older than this are ignored.
+    #[serde(default = "default_max_age_ms")]
+    pub max_age_ms: u64,
+}
+
+fn default_agreement_cm() -> u32 {
+    75
+}
+
+fn default_min_amplitude() -> u16 {
+    100
+}
+
+fn default_lidar_range_cm() -> u32 {
+    800
+}
+
+fn default_max_age_ms() -> u64 {
+    1000
+}
+
+// Disagreement is the rule applied when the radar and the lidar see targets at different distances.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disagreement {
+    // The lidar distance: the radar reports the gate of the strongest reflection, the lidar the person in
+    // its beam.
+    #[default]
+    Lidar,
+    // The radar distance: something stands between the lidar and the person.
+    Radar,
+    // No person: the radar is likely seeing a reflection or a target beside the lidar beam.
+    Absent,
+}
+
+// RoomConfig is a room sensor merging the targets of several RD03D covering the same room (fusion/room.rs).
+// The targets are placed in the frame of the room with the pose of each radar, the detections of the same
+// person by several radars are merged and the merged targets are tracked with ids stable across readings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomConfig {
+    pub radars: Vec<RadarPose>,
+    // Targets of different radars closer than this are the same person.
+    #[serde(default = "default_merge_mm")]
+    pub merge_mm: u32,
+    // Farthest a track may move between two readings to keep its id.
+    #[serde(default = "default_gate_mm")]
+    pub gate_mm: u32,
+    // Weight of a new position in the smoothed position of a track, 0 to 1 (1: no smoothing).
+    #[serde(default = "default_smoothing")]
+    pub smoothing: f64,
+    // A track not seen for this long is dropped; until then it keeps its last position.
+    #[serde(default = "default_track_timeout_ms")]
+    pub track_timeout_ms: u64,
+    // Readings older than this are ignored.
+    #[serde(default = "default_max_age_ms")]
+    pub max_age_ms: u64,
+}
+
+// RadarPose is the position of an RD03D in the room frame (mm) and the direction it faces, in degrees
+// counterclockwise from the y axis of the room: a radar with rotation_deg = 0 looks towards +y, and the
+// targets on the right of its dashboard (/rd03d) are towards +x.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadarPose {
+    // Id of the RD03D sensor.
+    pub id: String,
+    #[serde(default)]
+    pub x_mm: i32,
+    #[serde(default)]
+    pub y_mm: i32,
+    #[serde(default)]
+    pub rotation_deg: f64,
+}
+
+fn default_merge_mm() -> u32 {
+    500
+}
+
+fn default_gate_mm() -> u32 {
+    1000
+}
+
+fn default_smoothing() -> f64 {
+    0.5
+}
+
+fn default_track_timeout_ms() -> u64 {
+    1500
+}
+
+// UsbDevice identifies the USB-UART adapter of a sensor, e.g. vid = 0x10c4, pid = 0xea60 for a CP2102. The
+// serial number tells apart several adapters of the same model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsbDevice {
+    pub vid: u16,
+    pub pid: u16,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    #[serde(default)]
+    pub api_token: Option<String>,
+    // Origins of the web pages allowed to call the API from another site (CORS), e.g.
+    // "https://dashboard.example.com", or "*" for any. Only the pages served by the server itself if empty.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub sensors: Vec<SensorConfig>,
+    #[serde(default)]
+    pub stream: StreamConfig,
+    // Publishes the readings to an MQTT broker if set.
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    // Records the readings if set.
+    #[serde(default)]
+    pub history: Option<HistoryConfig>,
+}
+
+// HistoryConfig is the storage of the readings (history.rs). The readings are recorded at `record_hz`
+// (averaged) in segment files of `segment_minutes`; after `raw_retention_hours` they are downsampled to one
+// average every `downsampled_secs`, and deleted after `retention_days`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    #[serde(default = "default_history_dir")]
+    pub dir: String,
+    #[serde(default = "default_record_hz")]
+    pub record_hz: f64,
+    #[serde(default = "default_segment_minutes")]
+    pub segment_minutes: u64,
+    #[serde(default = "default_raw_retention_hours")]
+    pub raw_retention_hours: u64,
+    #[serde(default = "default_downsampled_secs")]
+    pub downsampled_secs: u64,
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+}
+
+fn default_history_dir() -> String {
+    "history".to_string()
+}
+
+fn default_record_hz() -> f64 {
+    2.0
+}
+
+fn default_segment_minutes() -> u64 {
+    60
+}
+
+fn default_raw_retention_hours() -> u64 {
+    24
+}
+
+fn default_downsampled_secs() -> u64 {
+    60
+}
+
+fn default_retention_days() -> u64 {
+    30
+}
+
+// StreamConfig is the rate of the live streams (SSE and WebSocket).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamConfig {
+    // Rate used when a client does not ask for one, in messages per second.
+    #[serde(default = "default_hz")]
+    pub default_hz: f64,
+    // Highest rate a client may ask for, "every frame" included. No limit if missing.
+    #[serde(default)]
+    pub max_hz: Option<f64>,
+}
+
+fn default_hz() -> f64 {
+    1.0
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            default_hz: default_hz(),
+            max_hz: None,
+        }
+    }
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:3000".to_string()
+}
+
+// MqttConfig is the MQTT broker the readings are published to. Topics:
+//   <prefix>/status                 "online" / "offline" (retained, last will)
+//   <prefix>/<id>/availability      "online" while the sensor is connected (retained)
+//   <prefix>/<id>/state             JSON of the readings, as the SSE streams
+//   <prefix>/<id>/event             {"event": "connected"} / {"event": "disconnected", "error": ...}
+//   <prefix>/<id>/set/<setting>     configuration commands, if `commands` is true (mqtt/commands.rs)
+//   <prefix>/<id>/response          results of the commands
+// and the Home Assistant discovery payloads under <discovery_prefix>/.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttConfig {
+    #[serde(default = "default_mqtt_host")]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_prefix")]
+    pub prefix: String,
+    // Rate of the state messages of each sensor, in messages per second.
+    #[serde(default = "default_hz")]
+    pub hz: f64,
+    // Publishes the Home Assistant discovery payloads.
+    #[serde(default = "default_discovery")]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    // Accepts the configuration commands on <prefix>/<id>/set/<setting>. Anyone allowed to publish on
+    // these topics by the broker can reconfigure the sensors.
+    #[serde(default)]
+    pub commands: bool,
+}
+
+fn default_mqtt_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "sensor".to_string()
+}
+
+fn default_mqtt_prefix() -> String {
+    "sensor".to_string()
+}
+
+fn default_discovery() -> bool {
+    true
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+impl Config {
+    // load() Reads the configuration from a TOML file and checks that the sensor ids are unique.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {path}: {e}"))?;
+        let config: Config = toml::from_str(&content)?;
+        let mqtt_hz = config.mqtt.as_ref().map(|mqtt| mqtt.hz);
+        let record_hz = config.history.as_ref().map(|history| history.record_hz);
+        let rates = [Some(config.stream.default_hz), config.stream.max_hz, mqtt_hz, record_hz];
+        if let Some(hz) = rates.into_iter().flatten().find(|hz| !(*hz > 0.0 && hz.is_finite())) {
+            anyhow::bail!("invalid stream rate {hz} Hz in {path}");
+        }
+        if let Some(history) = &config.history {
+            if history.segment_minutes == 0 || history.downsampled_secs == 0 {
+                anyhow::bail!("segment_minutes and downsampled_secs of [history] must be positive in {path}");
+            }
+        }
+        if let Some(origin) = config
+            .allowed_origins
+            .iter()
+            .find(|origin| origin.parse::<axum::http::HeaderValue>().is_err())
+        {
+            anyhow::bail!("invalid allowed origin {origin:?} in {path}");
+        }
+        for (i, sensor) in config.sensors.iter().enumerate() {
+            if config.sensors[..i].iter().any(|other| other.id == sensor.id) {
+                anyhow::bail!("duplicate sensor id {:?} in {path}", sensor.id);
+            }
+            if sensor.kind.is_virtual() {
+                config.check_sources(sensor).map_err(|e| anyhow::anyhow!("{e} in {path}"))?;
+            } else if sensor.port.is_empty() && sensor.usb.is_none() {
+                anyhow::bail!("sensor {:?} needs a port or a usb device in {path}", sensor.id);
+            }
+        }
+        Ok(config)
+    }
+
+    // check_sources() Checks that the sensors fused by a virtual sensor are configured and of the right kinds.
+    fn check_sources(&self, sensor: &SensorConfig) -> anyhow::Result<()> {
+        let source = |id: &str, kinds: &[SensorKind]| -> anyhow::Result<()> {
+            match self.sensors.iter().find(|other| other.id == id) {
+                Some(other) if kinds.contains(&other.kind) => Ok(()),
+                Some(other) => anyhow::bail!("sensor {:?} of {:?} is a {}", id, sensor.id, other.kind.name()),
+                None => anyhow::bail!("unknown sensor {:?} in {:?}", id, sensor.id),
+            }
+        };
+        match sensor.kind {
+            SensorKind::Presence => {
+                let presence = sensor
+                    .presence
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("presence sensor {:?} needs a presence table", sensor.id))?;
+                source(&presence.radar, &[SensorKind::Ld2410c])?;
+                source(&presence.lidar, &[SensorKind::Tfluna, SensorKind::Tof200f])
+            }
+            SensorKind::Room => {
+                let room = sensor
+                    .room
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("room sensor {:?} needs a room table", sensor.id))?;
+                if room.radars.is_empty() {
+                    anyhow::bail!("room sensor {:?} has no radars", sensor.id);
+                }
+                if !(room.smoothing > 0.0 && room.smoothing <= 1.0) {
+                    anyhow::bail!("smoothing of room sensor {:?} must be in ]0, 1]", sensor.id);
+                }
+                for (i, radar) in room.radars.iter().enumerate() {
+                    if room.radars[..i].iter().any(|other| other.id == radar.id) {
+                        anyhow::bail!("radar {:?} listed twice in {:?}", radar.id, sensor.id);
+                    }
+                    source(&radar.id, &[SensorKind::Rd03d])?;
+                }
+                Ok(())
+            }
+            SensorKind::Ld2410c | SensorKind::Rd03d | SensorKind::Tfluna | SensorKind::Tof200f => Ok(()),
+        }
+    }
+
+    // single_sensor() Configuration used when only a port (and optionally the sensor kind) is given on the
+    // command line. The sensor id is the name of its kind, e.g. "ld2410c".
+    pub fn single_sensor(port: String, kind: SensorKind) -> Self {
+        Self {
+            bind: default_bind(),
+            api_token: None,
+            sensors: vec![SensorConfig {
+                id: kind.name().to_string(),
+                kind,
+                port,
+                usb: None,
+                baud_rate: None,
+                timeout_ms: None,
+                retries: None,
+                engineering_mode: true,
+                multi_mode: true,
+                mqtt_topic: None,
+                presence: None,
+                room: None,
+            }],
+            allowed_origins: Vec::new(),
+            stream: StreamConfig::default(),
+            mqtt: None,
+            history: None,
+        }
+    }
+}
+
+impl SensorConfig {
+    // timeout() Returns the configured deadline of a read and of the answer to a command.
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_ms.map(std::time::Duration::from_millis)
+    }
+}
+
+impl SensorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SensorKind::Ld2410c => "ld2410c",
+            SensorKind::Rd03d => "rd03d",
+            SensorKind::Tfluna => "tfluna",
+            SensorKind::Tof200f => "tof200f",
+            SensorKind::Presence => "presence",
+            SensorKind::Room => "room",
+        }
+    }
+
+    // is_virtual() Returns whether the sensor fuses the readings of other sensors instead of opening a port.
+    pub fn is_virtual(&self) -> bool {
+        match self {
+            SensorKind::Presence | SensorKind::Room => true,
+            SensorKind::Ld2410c | SensorKind::Rd03d | SensorKind::Tfluna | SensorKind::Tof200f => false,
+        }
+    }
+}
+
+impl std::str::FromStr for SensorKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ld2410c" => Ok(SensorKind::Ld2410c),
+            "rd03d" => Ok(SensorKind::Rd03d),
+            "tfluna" => Ok(SensorKind::Tfluna),
+            "tof200f" => Ok(SensorKind::Tof200f),
+            "presence" => Ok(SensorKind::Presence),
+            "room" => Ok(SensorKind::Room),
+            other => Err(anyhow::anyhow!("unknown sensor kind {other:?}")),
+        }
+    }
+}
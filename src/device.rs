@@ -0,0 +1,303 @@
+// device.rs
+// Serial devices of the sensors. A sensor is found by its port path, or by the USB identifiers of its
+// adapter (vendor id, product id and optionally serial number) so that it is found again when the adapter
+// is plugged back in under another name (/dev/ttyUSB0 then /dev/ttyUSB1, COM7 then COM9).
+//
+// The serial ports can be listed with their USB identifiers, and probed to tell which sensor is on which
+// port: each port is opened at the baud rates of the drivers and the received bytes are matched against
+// the frames of each sensor (`sensor ports --probe`, GET /api/ports, POST /api/ports/probe).
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+use tokio_serial::{SerialPortBuilderExt, SerialPortInfo, SerialPortType};
+
+use crate::config::{SensorConfig, SensorKind, UsbDevice};
+use crate::protocol::{self, ld2410c, rd03d, tf_luna, tof200f};
+
+// Baud rates tried by the probe, the default ones of the drivers first (LD2410C and RD03D 256000, TF-Luna and
+// TOF200F 115200), then the other rates supported by the LD2410C and the TF-Luna.
+const PROBE_BAUD_RATES: [u32; 11] = [256000, 115200, 9600, 57600, 38400, 19200, 230400, 460800, 921600, 14400, 56000];
+// Time listening to a port at each baud rate. The sensors send at least 10 frames per second by default.
+const PROBE_WINDOW: Duration = Duration::from_millis(500);
+// Bytes kept from a port at each baud rate.
+const PROBE_BUFFER_LEN: usize = 4096;
+// RD03D report frame: AA FF 03 00 | 3 targets of 8 bytes | 55 CC.
+const RD03D_FRAME_LEN: usize = 30;
+
+// PortInfo is a serial port of the system.
+#[derive(Debug, Clone, Serialize)]
+pub struct PortInfo {
+    pub port: String,
+    // Identifiers of the USB adapter, usable as the `usb` setting of a sensor.
+    pub usb: Option<UsbDevice>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    // Configured sensor using the port.
+    pub sensor: Option<String>,
+}
+
+// Probe is the result of the probe of a port.
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub port: String,
+    // None if no sensor was recognized.
+    pub kind: Option<SensorKind>,
+    pub baud_rate: Option<u32>,
+    // Error opening the port, e.g. used by another program.
+    pub error: Option<String>,
+}
+
+// find() Returns the path of the serial port of a sensor, None if its device is not plugged in.
+pub fn find(config: &SensorConfig) -> Option<String> {
+    match &config.usb {
+        Some(usb) => ports()?
+            .into_iter()
+            .find(|port| matches(usb, port))
+            .map(|port| port.port_name),
+        None => present(&config.port).then(|| config.port.clone()),
+    }
+}
+
+// present() Returns whether a port exists: a device file on Unix (including the /dev/serial/by-id links),
+// a port listed by the system on Windows (assumed present if the ports cannot be listed).
+#[cfg(unix)]
+fn present(port: &str) -> bool {
+    Path::new(port).exists()
+}
+
+#[cfg(not(unix))]
+fn present(port: &str) -> bool {
+    match ports() {
+        Some(ports) => ports.iter().any(|info| info.port_name == port),
+        None => true,
+    }
+}
+
+fn ports() -> Option<Vec<SerialPortInfo>> {
+    tokio_serial::available_ports().ok()
+}
+
+// matches() Returns whether a port belongs to the USB adapter `usb`.
+fn matches(usb: &UsbDevice, port: &SerialPortInfo) -> bool {
+    let SerialPortType::UsbPort(info) = &port.port_type else {
+        return false;
+    };
+    info.vid == usb.vid
+        && info.pid == usb.pid
+        && usb
+            .serial_number
+            .as_ref()
+            .is_none_or(|serial_number| info.serial_number.as_ref() == Some(serial_number))
+}
+
+// list() Returns the serial ports of the system, with the configured sensor using each of them.
+pub fn list(sensors: &[SensorConfig]) -> Vec<PortInfo> {
+    let mut ports: Vec<PortInfo> = ports()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|info| {
+            let sensor = sensors.iter().find(|sensor| match &sensor.usb {
+                Some(usb) => matches(usb, &info),
+                None => sensor.port == info.port_name,
+            });
+            let (usb, manufacturer, product) = match info.port_type {
+                SerialPortType::UsbPort(usb) => (
+                    Some(UsbDevice {
+                        vid: usb.vid,
+                        pid: usb.pid,
+                        serial_number: usb.serial_number,
+                    }),
+                    usb.manufacturer,
+                    usb.product,
+                ),
+                _ => (None, None, None),
+            };
+            PortInfo {
+                port: info.port_name,
+                usb,
+                manufacturer,
+                product,
+                sensor: sensor.map(|sensor| sensor.id.clone()),
+            }
+        })
+        .collect();
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    ports
+}
+
+// probe_all() Probes the ports of the system not used by a configured sensor, all at the same time.
+pub async fn probe_all(sensors: &[SensorConfig]) -> Vec<Probe> {
+    let ports = list(sensors).into_iter().filter(|port| port.sensor.is_none());
+    futures::future::join_all(ports.map(|port| probe(port.port))).await
+}
+
+// probe() Identifies the sensor on a port, trying each baud rate until frames of a sensor are received.
+pub async fn probe(port: String) -> Probe {
+    let mut probe = Probe {
+        port,
+        kind: None,
+        baud_rate: None,
+        error: None,
+    };
+    for baud_rate in PROBE_BAUD_RATES {
+        let mut stream = match tokio_serial::new(&probe.port, baud_rate).open_native_async() {
+            Ok(stream) => stream,
+            Err(e) => {
+                probe.error = Some(e.to_string());
+                return probe;
+            }
+        };
+        let mut received = Vec::new();
+        let mut buf = [0u8; 256];
+        let listen = async {
+            while received.len() < PROBE_BUFFER_LEN {
+                match stream.read(&mut buf).await {
+                    Ok(n) if n > 0 => received.extend_from_slice(&buf[..n]),
+                    _ => return None,
+                }
+                if let Some(kind) = identify(&received) {
+                    return Some(kind);
+                }
+            }
+            None
+        };
+        if let Ok(Some(kind)) = tokio::time::timeout(PROBE_WINDOW, listen).await {
+            probe.kind = Some(kind);
+            probe.baud_rate = Some(baud_rate);
+            return probe;
+        }
+    }
+    probe
+}
+
+// identify() Returns the sensor whose frames are found in the received bytes.
+pub fn identify(data: &[u8]) -> Option<SensorKind> {
+    if is_ld2410c(data) {
+        Some(SensorKind::Ld2410c)
+    } else if is_rd03d(data) {
+        Some(SensorKind::Rd03d)
+    } else if is_tf_luna(data) {
+        Some(SensorKind::Tfluna)
+    } else if is_tof200f(data) {
+        Some(SensorKind::Tof200f)
+    } else {
+        None
+    }
+}
+
+// is_ld2410c() Looks for a report frame F4 F3 F2 F1 | length | data | F8 F7 F6 F5.
+fn is_ld2410c(data: &[u8]) -> bool {
+    let mut offset = 0;
+    while let protocol::FrameSearch::Complete { start, end } = protocol::find_frame(&data[offset..], ld2410c::REPORT_FRAME_HEADER) {
+        if data[offset + end - 4..offset + end] == ld2410c::REPORT_FRAME_END {
+            return true;
+        }
+        offset += start + 1;
+    }
+    false
+}
+
+// is_rd03d() Looks for a report frame AA FF ... 55 CC.
+fn is_rd03d(data: &[u8]) -> bool {
+    data.windows(RD03D_FRAME_LEN).any(|frame| {
+        frame[..2] == rd03d::REPORT_FRAME_HEADER && frame[RD03D_FRAME_LEN - 2..] == rd03d::REPORT_FRAME_END
+    })
+}
+
+// is_tf_luna() Looks for two consecutive data frames 59 59 ... with valid checksums, a single one being
+// too likely in random bytes.
+fn is_tf_luna(data: &[u8]) -> bool {
+    let Some((_, end)) = tf_luna::find_data_frame(data) else {
+        return false;
+    };
+    data.get(end..end + tf_luna::DATA_FRAME_LEN)
+        .and_then(tf_luna::find_data_frame)
+        .is_some_and(|(_, len)| len == tf_luna::DATA_FRAME_LEN)
+}
+
+// is_tof200f() Looks for a Modbus response to a read of one register with a valid CRC.
+fn is_tof200f(data: &[u8]) -> bool {
+    tof200f::find_response(data).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames captured from each sensor.
+    const LD2410C_REPORT: [u8; 23] = [
+        0xF4, 0xF3, 0xF2, 0xF1, 0x0D, 0x00, 0x02, 0xAA, 0x02, 0x51, 0x00, 0x00, 0x00, 0x00, 0x3B, 0x00,
+        0x00, 0x55, 0x00, 0xF8, 0xF7, 0xF6, 0xF5,
+    ];
+    const RD03D_REPORT: [u8; 30] = [
+        0xAA, 0xFF, 0x03, 0x00, 0x0E, 0x03, 0xB1, 0x86, 0x10, 0x00, 0x40, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x55, 0xCC,
+    ];
+    // 291 cm, amplitude 528, 48.5 °C
+    const TF_LUNA_FRAME: [u8; 9] = [0x59, 0x59, 0x23, 0x01, 0x10, 0x02, 0x84, 0x09, 0x75];
+    // Address 1, 300 mm
+    const TOF200F_RESPONSE: [u8; 7] = [0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x09];
+
+    // random_bytes() Returns `len` pseudo-random bytes (xorshift), the same on every run.
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x2545_F491;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    // with_noise() Returns `frames` between random bytes, like the start and the end of a probe window.
+    fn with_noise(frames: &[u8]) -> Vec<u8> {
+        [&random_bytes(37)[..], frames, &random_bytes(11)].concat()
+    }
+
+    #[test]
+    fn identifies_each_sensor() {
+        let tf_luna = [TF_LUNA_FRAME, TF_LUNA_FRAME].concat();
+        for (frames, kind) in [
+            (&LD2410C_REPORT[..], SensorKind::Ld2410c),
+            (&RD03D_REPORT, SensorKind::Rd03d),
+            (&tf_luna, SensorKind::Tfluna),
+            (&TOF200F_RESPONSE, SensorKind::Tof200f),
+        ] {
+            assert_eq!(identify(frames), Some(kind));
+            assert_eq!(identify(&with_noise(frames)), Some(kind));
+        }
+    }
+
+    #[test]
+    fn random_bytes_are_not_a_sensor() {
+        assert_eq!(identify(&random_bytes(PROBE_BUFFER_LEN)), None);
+        assert_eq!(identify(&[]), None);
+    }
+
+    #[test]
+    fn single_tf_luna_frame_is_not_enough() {
+        assert!(!is_tf_luna(&TF_LUNA_FRAME));
+        assert_eq!(identify(&with_noise(&TF_LUNA_FRAME)), None);
+        // Two frames but the second one with an invalid checksum.
+        let mut frames = [TF_LUNA_FRAME, TF_LUNA_FRAME].concat();
+        frames[17] ^= 0xFF;
+        assert!(!is_tf_luna(&frames));
+    }
+
+    #[test]
+    fn incomplete_frames_are_not_a_sensor() {
+        assert!(!is_ld2410c(&LD2410C_REPORT[..LD2410C_REPORT.len() - 1]));
+        let mut report = LD2410C_REPORT;
+        report[20] = 0x00;
+        assert!(!is_ld2410c(&report));
+        assert!(!is_rd03d(&RD03D_REPORT[..RD03D_REPORT.len() - 1]));
+        let mut response = TOF200F_RESPONSE;
+        response[6] ^= 0xFF;
+        assert!(!is_tof200f(&response));
+    }
+}
@@ -0,0 +1,330 @@
+// embedded/ld2410c.rs
+// LD2410C driver generic over embedded_io (Ld2410C) and embedded_io_async (Ld2410CAsync).
+// Command ACKs are matched against the sent command word, report frames received in between are discarded.
+use heapless::Vec;
+
+use super::{Error, RxBuffer};
+use crate::protocol::find_frame;
+use crate::protocol::FrameSearch;
+use crate::protocol::ld2410c::{
+    ack_word, AuxiliaryControl, BaudRate, BluetoothModule, DecodeError,
+    DistanceResolution, GateValue, Ld2410CData, Ld2410CFrame, COMMAND_FRAME_HEADER,
+    MAX_ACK_FRAME_LEN, REPORT_FRAME_HEADER,
+};
+
+// Two engineering mode report frames (45 bytes each) fit in the reception buffer.
+const BUFFER_LEN: usize = 128;
+
+pub type Ack = Vec<u8, MAX_ACK_FRAME_LEN>;
+
+// Frame extracted from the reception buffer by next_frame().
+enum Received {
+    Report(Result<Ld2410CData, DecodeError>),
+    Ack(Ack),
+}
+
+// next_frame() Takes the first complete report or ACK frame out of the reception buffer.
+fn next_frame(buffer: &mut RxBuffer<BUFFER_LEN>) -> Option<Received> {
+    let report = find_frame(buffer.data(), REPORT_FRAME_HEADER);
+    let ack = find_frame(buffer.data(), COMMAND_FRAME_HEADER);
+    let (start, end, is_report) = match (report, ack) {
+        (FrameSearch::Complete { start, end }, FrameSearch::Complete { start: ack_start, .. })
+            if start < ack_start =>
+        {
+            (start, end, true)
+        }
+        (_, FrameSearch::Complete { start, end }) => (start, end, false),
+        (FrameSearch::Complete { start, end }, _) => (start, end, true),
+        (FrameSearch::Incomplete { start }, _) | (_, FrameSearch::Incomplete { start }) => {
+            // Drop the garbage before the incomplete frame.
+            buffer.discard(start);
+            return None;
+        }
+        (FrameSearch::NotFound, FrameSearch::NotFound) => {
+            // Keep the last bytes, they may be the beginning of a header.
+            buffer.discard(buffer.data().len().saturating_sub(3));
+            return None;
+        }
+    };
+    let frame = &buffer.data()[start..end];
+    let received = if is_report {
+        Received::Report(Ld2410CData::from_frame(frame).unwrap_or(Err(DecodeError::InvalidFrameEnd)))
+    } else {
+        Received::Ack(frame.iter().copied().take(MAX_ACK_FRAME_LEN).collect())
+    };
+    buffer.discard(end);
+    Some(received)
+}
+
+// check_ack() Returns true if `ack` answers the command `word`, an error if the radar reported a failure.
+fn check_ack(ack: &Ack, word: u16) -> Result<bool, DecodeError> {
+    if ack.len() < 10 || u16::from_le_bytes([ack[6], ack[7]]) != ack_word(word) {
+        return Ok(false);
+    }
+    if ack[8] != 0x00 || ack[9] != 0x00 {
+        return Err(DecodeError::AckFailed(word));
+    }
+    Ok(true)
+}
+
+pub struct Ld2410C<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+}
+
+impl<S: embedded_io::Read + embedded_io::Write> Ld2410C<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    fn fill(&mut self) -> Result<(), Error<S::Error>> {
+        let n = self.serial.read(self.buffer.spare()).map_err(Error::Serial)?;
+        if n == 0 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.buffer.filled(n);
+        Ok(())
+    }
+
+    // read_data() Reads until a report frame is received and decodes it. ACK frames are discarded.
+    pub fn read_data(&mut self) -> Result<Ld2410CData, Error<S::Error>> {
+        loop {
+            match next_frame(&mut self.buffer) {
+                Some(Received::Report(data)) => return Ok(data?),
+                Some(Received::Ack(_)) => {}
+                None => self.fill()?,
+            }
+        }
+    }
+
+    fn write_frame(&mut self, frame: &Ld2410CFrame) -> Result<Ack, Error<S::Error>> {
+        self.serial.write_all(&frame.to_u8()).map_err(Error::Serial)?;
+        self.serial.flush().map_err(Error::Serial)?;
+        loop {
+            match next_frame(&mut self.buffer) {
+                Some(Received::Ack(ack)) => {
+                    if check_ack(&ack, frame.word())? {
+                        return Ok(ack);
+                    }
+                }
+                Some(Received::Report(_)) => {}
+                None => self.fill()?,
+            }
+        }
+    }
+
+    // send_command() Sends a command frame between the enable and end configuration commands
+    // and returns its ACK.
+    pub fn send_command(&mut self, frame: Ld2410CFrame) -> Result<Ack, Error<S::Error>> {
+        self.write_frame(&Ld2410CFrame::enabling_configuration())?;
+        let ack = self.write_frame(&frame)?;
+        self.write_frame(&Ld2410CFrame::ending_configuration())?;
+        Ok(ack)
+    }
+
+    pub fn read_firmware_version(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::read_firmware_version())
+    }
+
+    pub fn set_bluetooth_module(&mut self, module: BluetoothModule) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::bluetooth_module(module))
+    }
+
+    pub fn set_engineering_mode(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::engineering_mode())
+    }
+
+    pub fn set_engineering_mode_off(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::engineering_mode_off())
+    }
+
+    pub fn read_parameter(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::read_parameter())
+    }
+
+    pub fn set_restart_module(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::restart_module())
+    }
+
+    pub fn restore_factory_settings(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::restore_factory_settings())
+    }
+
+    pub fn set_distance_resolution_setting(
+        &mut self,
+        distance_resolution: DistanceResolution,
+    ) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::distance_resolution_setting(distance_resolution))
+    }
+
+    pub fn get_mac_adress(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::mac_address())
+    }
+
+    pub fn set_serial_port_baud_rate(&mut self, baud_rate: BaudRate) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::serial_port_baud_rate(baud_rate))
+    }
+
+    pub fn set_distance_gate_sensitivity_configuration(
+        &mut self,
+        distance_gate: GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    ) -> Result<Ack, Error<S::Error>> {
+        let frame = Ld2410CFrame::distance_gate_sensitivity_configuration(
+            distance_gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        )?;
+        self.send_command(frame)
+    }
+
+    pub fn set_auxiliary_control(&mut self, auxiliary_control: AuxiliaryControl) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::auxiliary_control(auxiliary_control))
+    }
+
+    pub fn query_auxiliary_control(&mut self) -> Result<AuxiliaryControl, Error<S::Error>> {
+        let ack = self.send_command(Ld2410CFrame::query_auxiliary_control())?;
+        Ok(AuxiliaryControl::from_response(&ack)?)
+    }
+}
+
+#[cfg(feature = "embedded-async")]
+pub struct Ld2410CAsync<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+}
+
+#[cfg(feature = "embedded-async")]
+impl<S: embedded_io_async::Read + embedded_io_async::Write> Ld2410CAsync<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    async fn fill(&mut self) -> Result<(), Error<S::Error>> {
+        let n = self.serial.read(self.buffer.spare()).await.map_err(Error::Serial)?;
+        if n == 0 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.buffer.filled(n);
+        Ok(())
+    }
+
+    // read_data() Reads until a report frame is received and decodes it. ACK frames are discarded.
+    pub async fn read_data(&mut self) -> Result<Ld2410CData, Error<S::Error>> {
+        loop {
+            match next_frame(&mut self.buffer) {
+                Some(Received::Report(data)) => return Ok(data?),
+                Some(Received::Ack(_)) => {}
+                None => self.fill().await?,
+            }
+        }
+    }
+
+    async fn write_frame(&mut self, frame: &Ld2410CFrame) -> Result<Ack, Error<S::Error>> {
+        self.serial.write_all(&frame.to_u8()).await.map_err(Error::Serial)?;
+        self.serial.flush().await.map_err(Error::Serial)?;
+        loop {
+            match next_frame(&mut self.buffer) {
+                Some(Received::Ack(ack)) => {
+                    if check_ack(&ack, frame.word())? {
+                        return Ok(ack);
+                    }
+                }
+                Some(Received::Report(_)) => {}
+                None => self.fill().await?,
+            }
+        }
+    }
+
+    // send_command() Sends a command frame between the enable and end configuration commands
+    // and returns its ACK.
+    pub async fn send_command(&mut self, frame: Ld2410CFrame) -> Result<Ack, Error<S::Error>> {
+        self.write_frame(&Ld2410CFrame::enabling_configuration()).await?;
+        let ack = self.write_frame(&frame).await?;
+        self.write_frame(&Ld2410CFrame::ending_configuration()).await?;
+        Ok(ack)
+    }
+
+    pub async fn read_firmware_version(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::read_firmware_version()).await
+    }
+
+    pub async fn set_bluetooth_module(&mut self, module: BluetoothModule) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::bluetooth_module(module)).await
+    }
+
+    pub async fn set_engineering_mode(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::engineering_mode()).await
+    }
+
+    pub async fn set_engineering_mode_off(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::engineering_mode_off()).await
+    }
+
+    pub async fn read_parameter(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::read_parameter()).await
+    }
+
+    pub async fn set_restart_module(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::restart_module()).await
+    }
+
+    pub async fn restore_factory_settings(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::restore_factory_settings()).await
+    }
+
+    pub async fn set_distance_resolution_setting(
+        &mut self,
+        distance_resolution: DistanceResolution,
+    ) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::distance_resolution_setting(distance_resolution)).await
+    }
+
+    pub async fn get_mac_adress(&mut self) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::mac_address()).await
+    }
+
+    pub async fn set_serial_port_baud_rate(&mut self, baud_rate: BaudRate) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::serial_port_baud_rate(baud_rate)).await
+    }
+
+    pub async fn set_distance_gate_sensitivity_configuration(
+        &mut self,
+        distance_gate: GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    ) -> Result<Ack, Error<S::Error>> {
+        let frame = Ld2410CFrame::distance_gate_sensitivity_configuration(
+            distance_gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        )?;
+        self.send_command(frame).await
+    }
+
+    pub async fn set_auxiliary_control(&mut self, auxiliary_control: AuxiliaryControl) -> Result<Ack, Error<S::Error>> {
+        self.send_command(Ld2410CFrame::auxiliary_control(auxiliary_control)).await
+    }
+
+    pub async fn query_auxiliary_control(&mut self) -> Result<AuxiliaryControl, Error<S::Error>> {
+        let ack = self.send_command(Ld2410CFrame::query_auxiliary_control()).await?;
+        Ok(AuxiliaryControl::from_response(&ack)?)
+    }
+}
@@ -0,0 +1,103 @@
+// embedded
+// Drivers generic over embedded_io::{Read, Write} (and embedded_io_async::{Read, Write} for the
+// *Async versions), so the same logic runs on an ESP32, a RP2040 or any UART exposing these traits.
+// They are no_std and allocation-free, built on the protocol module.
+#[cfg(feature = "ld2410c")]
+pub mod ld2410c;
+#[cfg(feature = "rd03d")]
+pub mod rd03d;
+#[cfg(feature = "tf_luna")]
+pub mod tf_luna;
+
+use core::fmt;
+
+#[cfg(feature = "ld2410c")]
+use crate::protocol::ld2410c::{CommandError, DecodeError};
+
+// Error returned by the embedded drivers, `E` being the error type of the serial port.
+#[derive(Debug)]
+pub enum Error<E> {
+    Serial(E),
+    #[cfg(feature = "ld2410c")]
+    Decode(DecodeError),
+    #[cfg(feature = "ld2410c")]
+    Command(CommandError),
+    // The serial port returned 0 bytes (end of stream).
+    UnexpectedEof,
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serial(e) => write!(f, "serial error: {e:?}"),
+            #[cfg(feature = "ld2410c")]
+            Error::Decode(e) => write!(f, "{e}"),
+            #[cfg(feature = "ld2410c")]
+            Error::Command(e) => write!(f, "{e}"),
+            Error::UnexpectedEof => write!(f, "unexpected end of stream"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+#[cfg(feature = "ld2410c")]
+impl<E> From<DecodeError> for Error<E> {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+#[cfg(feature = "ld2410c")]
+impl<E> From<CommandError> for Error<E> {
+    fn from(e: CommandError) -> Self {
+        Error::Command(e)
+    }
+}
+
+// RxBuffer accumulates the received bytes until a complete frame is available.
+// When it is full, the oldest half is dropped.
+#[cfg(any(feature = "ld2410c", feature = "rd03d", feature = "tf_luna"))]
+pub(crate) struct RxBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+#[cfg(any(feature = "ld2410c", feature = "rd03d", feature = "tf_luna"))]
+impl<const N: usize> RxBuffer<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    // spare() Returns the free space to read into, making room if the buffer is full.
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        if self.len == N {
+            self.discard(N / 2);
+        }
+        &mut self.bytes[self.len..]
+    }
+
+    // filled() Marks `n` bytes of the spare space as received.
+    pub(crate) fn filled(&mut self, n: usize) {
+        self.len = (self.len + n).min(N);
+    }
+
+    // discard() Drops the first `n` bytes.
+    pub(crate) fn discard(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.bytes.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+
+    #[cfg(feature = "rd03d")]
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+}
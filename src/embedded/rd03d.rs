@@ -0,0 +1,154 @@
+// embedded/rd03d.rs
+// RD03D driver generic over embedded_io (RD03D) and embedded_io_async (RD03DAsync).
+use heapless::Vec;
+
+use super::{Error, RxBuffer};
+use crate::protocol::rd03d::{decode_frame, latest_frame, target_mode_command, RD03DFrame, Target, MAX_TARGETS};
+
+// About four report frames (30 bytes each) fit in the reception buffer.
+const BUFFER_LEN: usize = 128;
+
+// extract_latest_targets() Consumes the complete frames of `buffer` and decodes the latest one into `targets`.
+// Returns false if no complete frame with targets was found.
+fn extract_latest_targets(buffer: &mut RxBuffer<BUFFER_LEN>, targets: &mut Vec<Target, MAX_TARGETS>) -> bool {
+    let Some((frame, consumed)) = latest_frame(buffer.data()) else {
+        return false;
+    };
+    let decoded = decode_frame(frame);
+    buffer.discard(consumed);
+    if decoded.is_empty() {
+        return false;
+    }
+    *targets = decoded;
+    true
+}
+
+pub struct RD03D<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+    pub targets: Vec<Target, MAX_TARGETS>,
+    pub multi_mode: bool,
+}
+
+impl<S: embedded_io::Read + embedded_io::Write> RD03D<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+            targets: Vec::new(),
+            multi_mode: true,
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    // set_multi_mode() Switches between single and multi target detection. The bytes already received
+    // are dropped; the radar needs about 200ms before reporting in the new mode.
+    pub fn set_multi_mode(&mut self, multi_mode: bool) -> Result<(), Error<S::Error>> {
+        self.write_data(target_mode_command(multi_mode))?;
+        self.buffer.clear();
+        self.multi_mode = multi_mode;
+        Ok(())
+    }
+
+    // update() Reads the available bytes and decodes the latest complete report frame.
+    // Returns true if the targets were updated.
+    pub fn update(&mut self) -> Result<bool, Error<S::Error>> {
+        let n = self.serial.read(self.buffer.spare()).map_err(Error::Serial)?;
+        if n == 0 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.buffer.filled(n);
+        Ok(extract_latest_targets(&mut self.buffer, &mut self.targets))
+    }
+
+    pub fn get_target(&self, target_number: usize) -> Option<&Target> {
+        target_number.checked_sub(1).and_then(|i| self.targets.get(i))
+    }
+
+    pub fn write_data(&mut self, data: &[u8]) -> Result<(), Error<S::Error>> {
+        self.serial.write_all(data).map_err(Error::Serial)?;
+        self.serial.flush().map_err(Error::Serial)
+    }
+
+    pub fn open_command_mode(&mut self) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::open_command_mode().to_u8())
+    }
+
+    pub fn close_command_mode(&mut self) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::close_command_mode().to_u8())
+    }
+
+    pub fn set_mode(&mut self, mode: u8) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::set_mode(mode).to_u8())
+    }
+}
+
+#[cfg(feature = "embedded-async")]
+pub struct RD03DAsync<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+    pub targets: Vec<Target, MAX_TARGETS>,
+    pub multi_mode: bool,
+}
+
+#[cfg(feature = "embedded-async")]
+impl<S: embedded_io_async::Read + embedded_io_async::Write> RD03DAsync<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+            targets: Vec::new(),
+            multi_mode: true,
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    // set_multi_mode() Switches between single and multi target detection. The bytes already received
+    // are dropped; the radar needs about 200ms before reporting in the new mode.
+    pub async fn set_multi_mode(&mut self, multi_mode: bool) -> Result<(), Error<S::Error>> {
+        self.write_data(target_mode_command(multi_mode)).await?;
+        self.buffer.clear();
+        self.multi_mode = multi_mode;
+        Ok(())
+    }
+
+    // update() Reads the available bytes and decodes the latest complete report frame.
+    // Returns true if the targets were updated.
+    pub async fn update(&mut self) -> Result<bool, Error<S::Error>> {
+        let n = self.serial.read(self.buffer.spare()).await.map_err(Error::Serial)?;
+        if n == 0 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.buffer.filled(n);
+        Ok(extract_latest_targets(&mut self.buffer, &mut self.targets))
+    }
+
+    pub fn get_target(&self, target_number: usize) -> Option<&Target> {
+        target_number.checked_sub(1).and_then(|i| self.targets.get(i))
+    }
+
+    pub async fn write_data(&mut self, data: &[u8]) -> Result<(), Error<S::Error>> {
+        self.serial.write_all(data).await.map_err(Error::Serial)?;
+        self.serial.flush().await.map_err(Error::Serial)
+    }
+
+    pub async fn open_command_mode(&mut self) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::open_command_mode().to_u8()).await
+    }
+
+    pub async fn close_command_mode(&mut self) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::close_command_mode().to_u8()).await
+    }
+
+    pub async fn set_mode(&mut self, mode: u8) -> Result<(), Error<S::Error>> {
+        self.write_data(&RD03DFrame::set_mode(mode).to_u8()).await
+    }
+}
@@ -0,0 +1,143 @@
+// embedded/tf_luna.rs
+// TF-Luna driver generic over embedded_io (TfLuna) and embedded_io_async (TfLunaAsync).
+use super::{Error, RxBuffer};
+use crate::protocol::tf_luna::{
+    find_data_frame, OutputFormat, OutputFrequency, OutputMode, TfLunaCommand, TfLunaData, DATA_FRAME_LEN,
+};
+
+// A few data frames (9 bytes each) fit in the reception buffer.
+const BUFFER_LEN: usize = 64;
+
+// next_data() Takes the first valid data frame out of the reception buffer.
+fn next_data(buffer: &mut RxBuffer<BUFFER_LEN>) -> Option<TfLunaData> {
+    match find_data_frame(buffer.data()) {
+        Some((frame, consumed)) => {
+            let data = TfLunaData::new(frame);
+            buffer.discard(consumed);
+            Some(data)
+        }
+        None => {
+            // Keep the last bytes, they may be the beginning of a frame.
+            buffer.discard(buffer.data().len().saturating_sub(DATA_FRAME_LEN - 1));
+            None
+        }
+    }
+}
+
+pub struct TfLuna<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+}
+
+impl<S: embedded_io::Read + embedded_io::Write> TfLuna<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    // read_data() Reads until a data frame with a valid checksum is received.
+    pub fn read_data(&mut self) -> Result<TfLunaData, Error<S::Error>> {
+        loop {
+            if let Some(data) = next_data(&mut self.buffer) {
+                return Ok(data);
+            }
+            let n = self.serial.read(self.buffer.spare()).map_err(Error::Serial)?;
+            if n == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+            self.buffer.filled(n);
+        }
+    }
+
+    pub fn write_data(&mut self, command: &[u8]) -> Result<(), Error<S::Error>> {
+        self.serial.write_all(command).map_err(Error::Serial)?;
+        self.serial.flush().map_err(Error::Serial)
+    }
+
+    pub fn set_output_format_setting(&mut self, format: OutputFormat) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::OutputFormat(format).to_u8())
+    }
+
+    pub fn set_baud_rate_setting(&mut self, baud_rate: u32) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::BaudRate(baud_rate).to_u8())
+    }
+
+    pub fn set_distance_limit_setting(&mut self, dist_min: u16, dist_max: u16) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::DistanceLimit { dist_min, dist_max }.to_u8())
+    }
+
+    pub fn set_output_frequency(&mut self, freq: OutputFrequency) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::OutputFrequency(freq).to_u8())
+    }
+
+    pub fn get_configuration(&mut self, output_mode: OutputMode) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::GetConfiguration(output_mode).to_u8())
+    }
+}
+
+#[cfg(feature = "embedded-async")]
+pub struct TfLunaAsync<S> {
+    serial: S,
+    buffer: RxBuffer<BUFFER_LEN>,
+}
+
+#[cfg(feature = "embedded-async")]
+impl<S: embedded_io_async::Read + embedded_io_async::Write> TfLunaAsync<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buffer: RxBuffer::new(),
+        }
+    }
+
+    // release() Returns the serial port.
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    // read_data() Reads until a data frame with a valid checksum is received.
+    pub async fn read_data(&mut self) -> Result<TfLunaData, Error<S::Error>> {
+        loop {
+            if let Some(data) = next_data(&mut self.buffer) {
+                return Ok(data);
+            }
+            let n = self.serial.read(self.buffer.spare()).await.map_err(Error::Serial)?;
+            if n == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+            self.buffer.filled(n);
+        }
+    }
+
+    pub async fn write_data(&mut self, command: &[u8]) -> Result<(), Error<S::Error>> {
+        self.serial.write_all(command).await.map_err(Error::Serial)?;
+        self.serial.flush().await.map_err(Error::Serial)
+    }
+
+    pub async fn set_output_format_setting(&mut self, format: OutputFormat) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::OutputFormat(format).to_u8()).await
+    }
+
+    pub async fn set_baud_rate_setting(&mut self, baud_rate: u32) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::BaudRate(baud_rate).to_u8()).await
+    }
+
+    pub async fn set_distance_limit_setting(&mut self, dist_min: u16, dist_max: u16) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::DistanceLimit { dist_min, dist_max }.to_u8()).await
+    }
+
+    pub async fn set_output_frequency(&mut self, freq: OutputFrequency) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::OutputFrequency(freq).to_u8()).await
+    }
+
+    pub async fn get_configuration(&mut self, output_mode: OutputMode) -> Result<(), Error<S::Error>> {
+        self.write_data(&TfLunaCommand::GetConfiguration(output_mode).to_u8()).await
+    }
+}
@@ -0,0 +1,427 @@
+// export/mod.rs
+// Export of the recorded readings (history.rs) for offline analysis, as CSV, JSON lines or Parquet. Used by
+// GET /api/export and by `sensor export` (main.rs).
+//
+// JSON lines: one {"sensor": ..., "timestamp_ms": ..., "data": ...} per reading, the sensors may be of any kind.
+// CSV and Parquet: one row per reading (per target for the RD03D and the room) with the columns of the sensor kind, the
+// sensors must all be of the same kind:
+//   all        timestamp_ms, sensor
+//   TF-Luna    distance_cm, amplitude, temperature_c
+//   TOF200F    distance_mm
+//   RD03D      target (1 to 3), x_mm, y_mm, speed_cm_s, distance_mm, angle_deg
+//   LD2410C    target_status, moving_distance_cm, moving_energy, stationary_distance_cm, stationary_energy,
+//              detection_distance_cm, moving_gate_0..8, static_gate_0..8 (engineering mode, empty otherwise)
+//   presence   present (0/1), distance_cm, motion, confidence, basis (distance and motion empty if absent)
+//   room       one row per tracked person: target (track id), x_mm, y_mm, vx_mm_s, vy_mm_s, radars (ids
+//              separated by "+", empty while not seen)
+mod parquet;
+
+use std::fmt::Write;
+
+use crate::config::SensorKind;
+use crate::history::{self, History};
+use crate::hub::{Reading, Sample};
+use crate::ld2410c;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Jsonl => "application/x-ndjson",
+            Format::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            other => Err(anyhow::anyhow!("unknown export format {other:?}, expected csv, jsonl or parquet")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Int,
+    Float,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Null,
+}
+
+// export() Returns the readings of `sensors` (id and kind) between `from` and `to` (ms) in the given format,
+// averaged over `resolution_ms` if given.
+pub fn export(
+    history: &History,
+    sensors: &[(String, SensorKind)],
+    from: u64,
+    to: u64,
+    resolution_ms: Option<u64>,
+    format: Format,
+) -> anyhow::Result<Vec<u8>> {
+    let mut readings = Vec::new();
+    for (id, kind) in sensors {
+        let samples = history.read(id, *kind, from, to)?;
+        let samples = match resolution_ms {
+            Some(resolution) => history::downsample(&samples, resolution),
+            None => samples,
+        };
+        readings.push((id.as_str(), *kind, samples));
+    }
+    if format == Format::Jsonl {
+        let mut lines = Vec::new();
+        for (id, _, samples) in &readings {
+            for sample in samples {
+                let line = serde_json::json!({ "sensor": id, "timestamp_ms": sample.timestamp_ms, "data": sample.data });
+                serde_json::to_writer(&mut lines, &line)?;
+                lines.push(b'\n');
+            }
+        }
+        return Ok(lines);
+    }
+    let Some((_, kind, _)) = readings.first() else {
+        anyhow::bail!("no sensor to export");
+    };
+    if readings.iter().any(|(_, other, _)| other != kind) {
+        anyhow::bail!("the {} format needs sensors of the same kind, use jsonl", format.extension());
+    }
+    let columns = columns(*kind);
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+    for (id, _, samples) in &readings {
+        for sample in samples {
+            rows.extend(sample_rows(id, sample));
+        }
+    }
+    rows.sort_by_key(|row| match row[0] {
+        Cell::Int(timestamp) => timestamp,
+        _ => 0,
+    });
+    Ok(match format {
+        Format::Csv => csv(&columns, &rows).into_bytes(),
+        _ => parquet::write(&columns, &rows),
+    })
+}
+
+// columns() Returns the columns of the CSV and Parquet exports of a sensor kind.
+fn columns(kind: SensorKind) -> Vec<(String, ColumnType)> {
+    let mut columns = vec![
+        ("timestamp_ms".to_string(), ColumnType::Int),
+        ("sensor".to_string(), ColumnType::Text),
+    ];
+    let named = |names: &[(&str, ColumnType)]| names.iter().map(|(name, kind)| (name.to_string(), *kind)).collect::<Vec<_>>();
+    match kind {
+        SensorKind::Tfluna => columns.extend(named(&[
+            ("distance_cm", ColumnType::Int),
+            ("amplitude", ColumnType::Int),
+            ("temperature_c", ColumnType::Float),
+        ])),
+        SensorKind::Tof200f => columns.extend(named(&[("distance_mm", ColumnType::Int)])),
+        SensorKind::Presence => columns.extend(named(&[
+            ("present", ColumnType::Int),
+            ("distance_cm", ColumnType::Int),
+            ("motion", ColumnType::Text),
+            ("confidence", ColumnType::Float),
+            ("basis", ColumnType::Text),
+        ])),
+        SensorKind::Rd03d => columns.extend(named(&[
+            ("target", ColumnType::Int),
+            ("x_mm", ColumnType::Int),
+            ("y_mm", ColumnType::Int),
+            ("speed_cm_s", ColumnType::Int),
+            ("distance_mm", ColumnType::Float),
+            ("angle_deg", ColumnType::Float),
+        ])),
+        SensorKind::Room => columns.extend(named(&[
+            ("target", ColumnType::Int),
+            ("x_mm", ColumnType::Int),
+            ("y_mm", ColumnType::Int),
+            ("vx_mm_s", ColumnType::Int),
+            ("vy_mm_s", ColumnType::Int),
+            ("radars", ColumnType::Text),
+        ])),
+        SensorKind::Ld2410c => {
+            columns.extend(named(&[
+                ("target_status", ColumnType::Text),
+                ("moving_distance_cm", ColumnType::Int),
+                ("moving_energy", ColumnType::Int),
+                ("stationary_distance_cm", ColumnType::Int),
+                ("stationary_energy", ColumnType::Int),
+                ("detection_distance_cm", ColumnType::Int),
+            ]));
+            for prefix in ["moving_gate", "static_gate"] {
+                columns.extend((0..ld2410c::MAX_GATES).map(|gate| (format!("{prefix}_{gate}"), ColumnType::Int)));
+            }
+        }
+    }
+    columns
+}
+
+// sample_rows() Returns the rows of a reading, in the order of columns().
+fn sample_rows(id: &str, sample: &Sample) -> Vec<Vec<Cell>> {
+    let row = |cells: Vec<Cell>| {
+        let mut row = vec![Cell::Int(sample.timestamp_ms as i64), Cell::Text(id.to_string())];
+        row.extend(cells);
+        row
+    };
+    match &sample.data {
+        Reading::Tfluna(data) => vec![row(vec![
+            Cell::Int(data.distance() as i64),
+            Cell::Int(data.amplitude() as i64),
+            Cell::Float(data.temperature() as f64),
+        ])],
+        Reading::Tof200f(data) => vec![row(vec![Cell::Int(data.distance() as i64)])],
+        Reading::Presence(presence) => {
+            let text = |value: Value| value.as_str().map(|text| Cell::Text(text.to_string())).unwrap_or(Cell::Null);
+            vec![row(vec![
+                Cell::Int(presence.present as i64),
+                presence.distance_cm.map(|distance| Cell::Int(distance as i64)).unwrap_or(Cell::Null),
+                text(serde_json::json!(presence.motion)),
+                Cell::Float(presence.confidence as f64),
+                text(serde_json::json!(presence.basis)),
+            ])]
+        }
+        Reading::Room(room) => room
+            .targets
+            .iter()
+            .map(|target| {
+                row(vec![
+                    Cell::Int(target.id as i64),
+                    Cell::Int(target.x_mm as i64),
+                    Cell::Int(target.y_mm as i64),
+                    Cell::Int(target.vx_mm_s as i64),
+                    Cell::Int(target.vy_mm_s as i64),
+                    Cell::Text(target.radars.join("+")),
+                ])
+            })
+            .collect(),
+        Reading::Rd03d(targets) => targets
+            .iter()
+            .enumerate()
+            // Empty slots have a zero distance.
+            .filter(|(_, target)| target.distance > 0.0)
+            .map(|(slot, target)| {
+                row(vec![
+                    Cell::Int(slot as i64 + 1),
+                    Cell::Int(target.x as i64),
+                    Cell::Int(target.y as i64),
+                    Cell::Int(target.speed as i64),
+                    Cell::Float(target.distance),
+                    Cell::Float(target.angle),
+                ])
+            })
+            .collect(),
+        Reading::Ld2410c(data) => {
+            let target = &data.target_data;
+            let mut cells = vec![
+                Cell::Text(format!("{:?}", target.target_status)),
+                Cell::Int(target.movement_target_distance as i64),
+                Cell::Int(target.movement_target_energy as i64),
+                Cell::Int(target.stationary_target_distance as i64),
+                Cell::Int(target.stationary_target_energy as i64),
+                Cell::Int(target.detection_distance as i64),
+            ];
+            let model = target.engineering_model.as_ref();
+            for gates in [model.map(|model| &model.moving_distance_gates), model.map(|model| &model.static_distance_gates)] {
+                cells.extend((0..ld2410c::MAX_GATES).map(|gate| {
+                    match gates.and_then(|gates| gates.get(gate)) {
+                        Some(energy) => Cell::Int(*energy as i64),
+                        None => Cell::Null,
+                    }
+                }));
+            }
+            vec![row(cells)]
+        }
+    }
+}
+
+// csv() Writes the rows as CSV with a header line.
+fn csv(columns: &[(String, ColumnType)], rows: &[Vec<Cell>]) -> String {
+    let mut text = String::new();
+    let header: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+    let _ = writeln!(text, "{}", header.join(","));
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|cell| match cell {
+                Cell::Int(value) => value.to_string(),
+                Cell::Float(value) => value.to_string(),
+                Cell::Text(value) if value.contains([',', '"', '\n']) => format!("\"{}\"", value.replace('"', "\"\"")),
+                Cell::Text(value) => value.clone(),
+                Cell::Null => String::new(),
+            })
+            .collect();
+        let _ = writeln!(text, "{}", cells.join(","));
+    }
+    text
+}
+
+// parse_time() Parses a time given as milliseconds since the Unix epoch, or as a duration before `now`
+// such as "24h" (see history::parse_duration()).
+pub fn parse_time(text: &str, now: u64) -> Option<u64> {
+    if text.chars().all(|c| c.is_ascii_digit()) {
+        return text.parse().ok();
+    }
+    history::parse_duration(text).map(|ago| now.saturating_sub(ago))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HistoryConfig;
+    use crate::fusion::room::{Room, RoomTarget};
+    use crate::tof200f::Tof200fData;
+
+    // A multiple of the segment length (1 minute).
+    const BASE: u64 = 1_700_000_040_000;
+
+    // history() Returns a history in a new temporary directory, with 1 minute segments, and the directory.
+    fn history(name: &str) -> (History, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("sensor-export-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let history = History::new(HistoryConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            record_hz: 2.0,
+            segment_minutes: 1,
+            raw_retention_hours: 1,
+            downsampled_secs: 10,
+            retention_days: 1,
+        });
+        (history, dir)
+    }
+
+    fn tof(timestamp_ms: u64, distance: u16) -> Sample {
+        Sample {
+            timestamp_ms,
+            data: Reading::Tof200f(Tof200fData::new(distance)),
+        }
+    }
+
+    fn room(timestamp_ms: u64, radars: &[&str]) -> Sample {
+        let target = RoomTarget {
+            id: 4,
+            x_mm: 1200,
+            y_mm: -300,
+            vx_mm_s: 50,
+            vy_mm_s: 0,
+            radars: radars.iter().map(|radar| radar.to_string()).collect(),
+        };
+        Sample {
+            timestamp_ms,
+            data: Reading::Room(Room { targets: vec![target] }),
+        }
+    }
+
+    fn sensors(sensors: &[(&str, SensorKind)]) -> Vec<(String, SensorKind)> {
+        sensors.iter().map(|(id, kind)| (id.to_string(), *kind)).collect()
+    }
+
+    #[test]
+    fn csv_quotes_the_sensor_ids_and_sorts_the_rows() {
+        let (history, dir) = history("csv-ids");
+        history.append("tof,\"a\"", history::RAW, &[tof(BASE, 100), tof(BASE + 2_000, 300)]).unwrap();
+        history.append("tof", history::RAW, &[tof(BASE + 1_000, 200)]).unwrap();
+        let sensors = sensors(&[("tof,\"a\"", SensorKind::Tof200f), ("tof", SensorKind::Tof200f)]);
+        let csv = export(&history, &sensors, BASE, BASE + 60_000, None, Format::Csv).unwrap();
+        let expected = [
+            "timestamp_ms,sensor,distance_mm".to_string(),
+            format!("{BASE},\"tof,\"\"a\"\"\",100"),
+            format!("{},tof,200", BASE + 1_000),
+            format!("{},\"tof,\"\"a\"\"\",300", BASE + 2_000),
+        ];
+        assert_eq!(String::from_utf8(csv).unwrap(), expected.join("\n") + "\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn csv_joins_and_quotes_the_radars_of_the_room() {
+        let (history, dir) = history("csv-room");
+        let samples = [room(BASE, &["left", "right"]), room(BASE + 1_000, &[]), room(BASE + 2_000, &["a,b", "c"])];
+        history.append("room", history::RAW, &samples).unwrap();
+        let sensors = sensors(&[("room", SensorKind::Room)]);
+        let csv = export(&history, &sensors, BASE, BASE + 60_000, None, Format::Csv).unwrap();
+        let expected = [
+            "timestamp_ms,sensor,target,x_mm,y_mm,vx_mm_s,vy_mm_s,radars".to_string(),
+            format!("{BASE},room,4,1200,-300,50,0,left+right"),
+            format!("{},room,4,1200,-300,50,0,", BASE + 1_000),
+            format!("{},room,4,1200,-300,50,0,\"a,b+c\"", BASE + 2_000),
+        ];
+        assert_eq!(String::from_utf8(csv).unwrap(), expected.join("\n") + "\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn csv_quotes_quotes_and_line_breaks() {
+        let columns = vec![("text".to_string(), ColumnType::Text)];
+        let rows: Vec<Vec<Cell>> = ["plain", "say \"hi\"", "two\nlines", ""]
+            .into_iter()
+            .map(|text| vec![Cell::Text(text.to_string())])
+            .chain([vec![Cell::Null]])
+            .collect();
+        assert_eq!(csv(&columns, &rows), "text\nplain\n\"say \"\"hi\"\"\"\n\"two\nlines\"\n\n\n");
+    }
+
+    #[test]
+    fn csv_and_parquet_need_sensors_of_the_same_kind() {
+        let (history, _) = history("mixed");
+        let sensors = sensors(&[("tof", SensorKind::Tof200f), ("room", SensorKind::Room)]);
+        for format in [Format::Csv, Format::Parquet] {
+            let error = export(&history, &sensors, BASE, BASE + 60_000, None, format).unwrap_err();
+            let expected = format!("the {} format needs sensors of the same kind, use jsonl", format.extension());
+            assert_eq!(error.to_string(), expected);
+        }
+        let error = export(&history, &[], BASE, BASE + 60_000, None, Format::Csv).unwrap_err();
+        assert_eq!(error.to_string(), "no sensor to export");
+    }
+
+    #[test]
+    fn jsonl_writes_one_line_per_reading_of_any_kind() {
+        let (history, dir) = history("jsonl");
+        history.append("tof", history::RAW, &[tof(BASE, 100), tof(BASE + 1_000, 200)]).unwrap();
+        history.append("room", history::RAW, &[room(BASE + 500, &["left"])]).unwrap();
+        let sensors = sensors(&[("tof", SensorKind::Tof200f), ("room", SensorKind::Room)]);
+        let jsonl = export(&history, &sensors, BASE, BASE + 60_000, None, Format::Jsonl).unwrap();
+        let lines: Vec<Value> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Sensor by sensor, in the order of `sensors`.
+        let readings = [("tof", tof(BASE, 100)), ("tof", tof(BASE + 1_000, 200)), ("room", room(BASE + 500, &["left"]))];
+        let expected: Vec<Value> = readings
+            .into_iter()
+            .map(|(id, sample)| {
+                serde_json::json!({ "sensor": id, "timestamp_ms": sample.timestamp_ms, "data": sample.data })
+            })
+            .collect();
+        assert_eq!(lines, expected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+// parquet.rs
+// Minimal Parquet writer for the exports: one row group, one uncompressed PLAIN data page (v1) per column,
+// all the columns OPTIONAL (INT64, DOUBLE or BYTE_ARRAY UTF8). The metadata is encoded with the Thrift
+// compact protocol, see https://github.com/apache/parquet-format (parquet.thrift).
+use super::{Cell, ColumnType};
+
+const MAGIC: &[u8] = b"PAR1";
+
+// parquet.thrift enums.
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const CONVERTED_TYPE_UTF8: i32 = 0;
+const REPETITION_OPTIONAL: i32 = 1;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_TYPE_DATA_PAGE: i32 = 0;
+
+// Thrift compact protocol types.
+const CT_I32: u8 = 5;
+const CT_I64: u8 = 6;
+const CT_BINARY: u8 = 8;
+const CT_LIST: u8 = 9;
+const CT_STRUCT: u8 = 12;
+
+// write() Returns the Parquet file of the rows, in the order of `columns`.
+pub fn write(columns: &[(String, ColumnType)], rows: &[Vec<Cell>]) -> Vec<u8> {
+    let mut file = MAGIC.to_vec();
+    let mut chunks = Vec::new();
+    for (index, (name, kind)) in columns.iter().enumerate() {
+        let cells: Vec<&Cell> = rows.iter().map(|row| &row[index]).collect();
+        let data = page_data(*kind, &cells);
+        let mut header = Compact::default();
+        header.struct_begin();
+        header.field_i32(1, PAGE_TYPE_DATA_PAGE);
+        header.field_i32(2, data.len() as i32);
+        header.field_i32(3, data.len() as i32);
+        header.field_struct_begin(5);
+        header.field_i32(1, cells.len() as i32);
+        header.field_i32(2, ENCODING_PLAIN);
+        header.field_i32(3, ENCODING_RLE);
+        header.field_i32(4, ENCODING_RLE);
+        header.struct_end();
+        header.struct_end();
+        let offset = file.len() as i64;
+        let size = (header.buffer.len() + data.len()) as i64;
+        file.extend_from_slice(&header.buffer);
+        file.extend_from_slice(&data);
+        chunks.push((name, *kind, offset, size, cells.len() as i64));
+    }
+
+    let mut metadata = Compact::default();
+    metadata.struct_begin();
+    metadata.field_i32(1, 1);
+    // Schema: the root then the columns.
+    metadata.field_list_begin(2, CT_STRUCT, columns.len() + 1);
+    metadata.struct_begin();
+    metadata.field_binary(4, b"schema");
+    metadata.field_i32(5, columns.len() as i32);
+    metadata.struct_end();
+    for (name, kind) in columns {
+        metadata.struct_begin();
+        metadata.field_i32(1, physical_type(*kind));
+        metadata.field_i32(3, REPETITION_OPTIONAL);
+        metadata.field_binary(4, name.as_bytes());
+        if *kind == ColumnType::Text {
+            metadata.field_i32(6, CONVERTED_TYPE_UTF8);
+        }
+        metadata.struct_end();
+    }
+    metadata.field_i64(3, rows.len() as i64);
+    // One row group.
+    metadata.field_list_begin(4, CT_STRUCT, 1);
+    metadata.struct_begin();
+    metadata.field_list_begin(1, CT_STRUCT, chunks.len());
+    for (name, kind, offset, size, values) in &chunks {
+        metadata.struct_begin();
+        metadata.field_i64(2, *offset);
+        metadata.field_struct_begin(3);
+        metadata.field_i32(1, physical_type(*kind));
+        metadata.field_list_begin(2, CT_I32, 2);
+        metadata.i32(ENCODING_PLAIN);
+        metadata.i32(ENCODING_RLE);
+        metadata.field_list_begin(3, CT_BINARY, 1);
+        metadata.binary(name.as_bytes());
+        metadata.field_i32(4, CODEC_UNCOMPRESSED);
+        metadata.field_i64(5, *values);
+        metadata.field_i64(6, *size);
+        metadata.field_i64(7, *size);
+        metadata.field_i64(9, *offset);
+        metadata.struct_end();
+        metadata.struct_end();
+    }
+    let total_size: i64 = chunks.iter().map(|chunk| chunk.3).sum();
+    metadata.field_i64(2, total_size);
+    metadata.field_i64(3, rows.len() as i64);
+    metadata.struct_end();
+    metadata.field_binary(6, b"sensor export");
+    metadata.struct_end();
+
+    file.extend_from_slice(&metadata.buffer);
+    file.extend_from_slice(&(metadata.buffer.len() as u32).to_le_bytes());
+    file.extend_from_slice(MAGIC);
+    file
+}
+
+fn physical_type(kind: ColumnType) -> i32 {
+    match kind {
+        ColumnType::Int => TYPE_INT64,
+        ColumnType::Float => TYPE_DOUBLE,
+        ColumnType::Text => TYPE_BYTE_ARRAY,
+    }
+}
+
+// page_data() Encodes the definition levels (1 for a value, 0 for a null) as a bit-packed run of the
+// RLE/bit-packing hybrid encoding, followed by the PLAIN values.
+fn page_data(kind: ColumnType, cells: &[&Cell]) -> Vec<u8> {
+    let mut levels = Vec::new();
+    let groups = cells.len().div_ceil(8);
+    put_varint(&mut levels, ((groups as u64) << 1) | 1);
+    for group in cells.chunks(8) {
+        let byte = group
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| ***cell != Cell::Null)
+            .fold(0u8, |byte, (bit, _)| byte | (1 << bit));
+        levels.push(byte);
+    }
+    let mut data = (levels.len() as u32).to_le_bytes().to_vec();
+    data.extend_from_slice(&levels);
+    for cell in cells {
+        match (kind, cell) {
+            (_, Cell::Null) => {}
+            (ColumnType::Int, Cell::Int(value)) => data.extend_from_slice(&value.to_le_bytes()),
+            (ColumnType::Float, Cell::Float(value)) => data.extend_from_slice(&value.to_le_bytes()),
+            (ColumnType::Float, Cell::Int(value)) => data.extend_from_slice(&(*value as f64).to_le_bytes()),
+            (ColumnType::Text, Cell::Text(value)) => {
+                data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                data.extend_from_slice(value.as_bytes());
+            }
+            (kind, cell) => unreachable!("{cell:?} in a {kind:?} column"),
+        }
+    }
+    data
+}
+
+// Compact is a Thrift compact protocol encoder. `last_ids` holds the last field id of each open struct.
+#[derive(Default)]
+struct Compact {
+    buffer: Vec<u8>,
+    last_ids: Vec<i16>,
+}
+
+impl Compact {
+    fn struct_begin(&mut self) {
+        self.last_ids.push(0);
+    }
+
+    fn struct_end(&mut self) {
+        self.buffer.push(0); // stop field
+        self.last_ids.pop();
+    }
+
+    fn field_header(&mut self, id: i16, kind: u8) {
+        let last = self.last_ids.last_mut().expect("field outside of a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.buffer.push(((delta as u8) << 4) | kind);
+        } else {
+            self.buffer.push(kind);
+            put_varint(&mut self.buffer, zigzag(id as i64));
+        }
+        *last = id;
+    }
+
+    fn field_i32(&mut self, id: i16, value: i32) {
+        self.field_header(id, CT_I32);
+        self.i32(value);
+    }
+
+    fn field_i64(&mut self, id: i16, value: i64) {
+        self.field_header(id, CT_I64);
+        put_varint(&mut self.buffer, zigzag(value));
+    }
+
+    fn field_binary(&mut self, id: i16, value: &[u8]) {
+        self.field_header(id, CT_BINARY);
+        self.binary(value);
+    }
+
+    fn field_struct_begin(&mut self, id: i16) {
+        self.field_header(id, CT_STRUCT);
+        self.struct_begin();
+    }
+
+    fn field_list_begin(&mut self, id: i16, element: u8, len: usize) {
+        self.field_header(id, CT_LIST);
+        if len < 15 {
+            self.buffer.push(((len as u8) << 4) | element);
+        } else {
+            self.buffer.push(0xF0 | element);
+            put_varint(&mut self.buffer, len as u64);
+        }
+    }
+
+    fn i32(&mut self, value: i32) {
+        put_varint(&mut self.buffer, zigzag(value as i64));
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        put_varint(&mut self.buffer, value.len() as u64);
+        self.buffer.extend_from_slice(value);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::parquet::basic::{ConvertedType, Repetition, Type};
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+    use ::parquet::record::Field;
+
+    #[test]
+    fn read_back_with_the_parquet_crate() {
+        let columns = vec![
+            ("timestamp_ms".to_string(), ColumnType::Int),
+            ("distance".to_string(), ColumnType::Float),
+            ("sensor".to_string(), ColumnType::Text),
+        ];
+        // 10 rows to span two groups of definition levels, with nulls in every column.
+        let rows: Vec<Vec<Cell>> = (0..10)
+            .map(|i| {
+                vec![
+                    if i == 9 { Cell::Null } else { Cell::Int(1_700_000_000_000 + i) },
+                    match i % 3 {
+                        0 => Cell::Null,
+                        1 => Cell::Float(i as f64 / 4.0),
+                        _ => Cell::Int(i),
+                    },
+                    if i % 4 == 3 { Cell::Null } else { Cell::Text(format!("luna-{i}")) },
+                ]
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("sensor-export-{}.parquet", std::process::id()));
+        std::fs::write(&path, write(&columns, &rows)).unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 10);
+        assert_eq!(metadata.created_by(), Some("sensor export"));
+        let fields = metadata.schema().get_fields();
+        let schema: Vec<_> = fields
+            .iter()
+            .map(|field| {
+                let info = field.get_basic_info();
+                (info.name(), field.get_physical_type(), info.repetition(), info.converted_type())
+            })
+            .collect();
+        assert_eq!(
+            schema,
+            [
+                ("timestamp_ms", Type::INT64, Repetition::OPTIONAL, ConvertedType::NONE),
+                ("distance", Type::DOUBLE, Repetition::OPTIONAL, ConvertedType::NONE),
+                ("sensor", Type::BYTE_ARRAY, Repetition::OPTIONAL, ConvertedType::UTF8),
+            ]
+        );
+
+        let read: Vec<Vec<Field>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().get_column_iter().map(|(_, field)| field.clone()).collect())
+            .collect();
+        let expected: Vec<Vec<Field>> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&columns)
+                    .map(|(cell, (_, kind))| match (cell, kind) {
+                        (Cell::Null, _) => Field::Null,
+                        (Cell::Int(value), ColumnType::Float) => Field::Double(*value as f64),
+                        (Cell::Int(value), _) => Field::Long(*value),
+                        (Cell::Float(value), _) => Field::Double(*value),
+                        (Cell::Text(value), _) => Field::Str(value.clone()),
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(read, expected);
+    }
+}
@@ -0,0 +1,8 @@
+// fusion/mod.rs
+// Virtual sensors of the hub: they open no port but fuse the readings of other configured sensors, and are
+// published like any other sensor (latest reading, history, MQTT, WebSocket).
+//   presence   an LD2410C and a TF-Luna or TOF200F on the same bracket: "person at X cm, moving/still,
+//              confidence" (presence.rs)
+//   room       several RD03D covering one room: one list of tracked people in the frame of the room (room.rs)
+pub mod presence;
+pub mod room;
@@ -0,0 +1,274 @@
+// fusion/presence.rs
+// Fusion of an LD2410C radar and a lidar (TF-Luna or TOF200F) mounted on the same bracket. The lidar measures
+// a precise distance but cannot tell a person from a wall; the radar tells whether the target moves or stands
+// still but only knows its distance to a gate (75 or 20 cm). Together they give a single estimate: a person
+// at X cm, moving or still, with a confidence.
+//
+// The basis of the estimate sets its confidence, scaled by the energy of the radar target (0 to 100):
+//   agreement     the radar target is within agreement_cm of the lidar distance: lidar distance
+//   disagreement  the radar target is elsewhere: on_disagreement decides (lidar, radar or absent)
+//   radar_only    no valid lidar reading, or the lidar sees the background: radar distance
+//   lidar_only    no radar target but the lidar sees something in front of the background: still person
+//   no_target     nobody
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Disagreement, PresenceConfig};
+use crate::hub::Reading;
+use crate::ld2410c::{TargetData, TargetStatus};
+
+const AGREEMENT_CONFIDENCE: f32 = 1.0;
+const RADAR_ONLY_CONFIDENCE: f32 = 0.6;
+const DISAGREEMENT_CONFIDENCE: f32 = 0.4;
+// A still person is the likeliest explanation but a new object in the beam gives the same reading.
+const LIDAR_ONLY_CONFIDENCE: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+    Moving,
+    Still,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Basis {
+    Agreement,
+    Disagreement,
+    RadarOnly,
+    LidarOnly,
+    NoTarget,
+}
+
+// Presence is the reading of a presence sensor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Presence {
+    pub present: bool,
+    // Distance of the person from the reference of the offsets, None if absent.
+    pub distance_cm: Option<u32>,
+    pub motion: Option<Motion>,
+    // 0 to 1, 0 if absent.
+    pub confidence: f32,
+    pub basis: Basis,
+}
+
+impl Presence {
+    fn absent(basis: Basis) -> Self {
+        Self {
+            present: false,
+            distance_cm: None,
+            motion: None,
+            confidence: 0.0,
+            basis,
+        }
+    }
+
+    fn person(distance_cm: i32, motion: Motion, confidence: f32, basis: Basis) -> Self {
+        Self {
+            present: true,
+            distance_cm: Some(distance_cm.max(0) as u32),
+            motion: Some(motion),
+            confidence: confidence.clamp(0.0, 1.0),
+            basis,
+        }
+    }
+}
+
+// Lidar is a distance measured by the TF-Luna or the TOF200F.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lidar {
+    pub distance_cm: u32,
+    // Signal strength (TF-Luna only).
+    pub amplitude: Option<u16>,
+}
+
+impl Lidar {
+    // from_reading() Returns the distance of a TF-Luna or TOF200F reading (mm for the TOF200F).
+    pub fn from_reading(reading: &Reading) -> Option<Self> {
+        match reading {
+            Reading::Tfluna(data) => Some(Self {
+                distance_cm: data.distance() as u32,
+                amplitude: Some(data.amplitude()),
+            }),
+            Reading::Tof200f(data) => Some(Self {
+                distance_cm: data.distance() as u32 / 10,
+                amplitude: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// RadarTarget is the moving or the stationary target of an LD2410C report.
+struct RadarTarget {
+    motion: Motion,
+    distance_cm: i32,
+    // 0 to 1.
+    energy: f32,
+}
+
+// radar_targets() Returns the targets reported by the radar, with the offset applied.
+fn radar_targets(config: &PresenceConfig, target: &TargetData) -> Vec<RadarTarget> {
+    let moving = RadarTarget {
+        motion: Motion::Moving,
+        distance_cm: target.movement_target_distance as i32 + config.radar_offset_cm,
+        energy: target.movement_target_energy.min(100) as f32 / 100.0,
+    };
+    let still = RadarTarget {
+        motion: Motion::Still,
+        distance_cm: target.stationary_target_distance as i32 + config.radar_offset_cm,
+        energy: target.stationary_target_energy.min(100) as f32 / 100.0,
+    };
+    match target.target_status {
+        TargetStatus::NoTarget => Vec::new(),
+        TargetStatus::MovingTarget => vec![moving],
+        TargetStatus::StationaryTarget => vec![still],
+        TargetStatus::MovingAndStationaryTarget => vec![moving, still],
+    }
+}
+
+// fuse() Returns the presence estimate from the latest radar report and the latest lidar reading (None if
+// missing or stale).
+pub fn fuse(config: &PresenceConfig, radar: &TargetData, lidar: Option<Lidar>) -> Presence {
+    // Lidar readings without a return or too weak
+    let lidar = lidar.filter(|lidar| {
+        lidar.distance_cm > 0
+            && lidar.distance_cm <= config.lidar_range_cm
+            && lidar.amplitude.is_none_or(|amplitude| amplitude >= config.min_amplitude)
+    });
+    let agreement_cm = config.agreement_cm as i32;
+    // The lidar sees the background: the person, if any, is out of its beam.
+    let background = |distance_cm: u32| {
+        config
+            .background_cm
+            .is_some_and(|background_cm| distance_cm as i32 >= background_cm as i32 - agreement_cm)
+    };
+    let lidar_cm = lidar
+        .filter(|lidar| !background(lidar.distance_cm))
+        .map(|lidar| lidar.distance_cm as i32 + config.lidar_offset_cm);
+    let targets = radar_targets(config, radar);
+    let energy_scaled = |confidence: f32, target: &RadarTarget| confidence * (0.5 + 0.5 * target.energy);
+    let Some(lidar_cm) = lidar_cm else {
+        return match targets.iter().max_by(|a, b| a.energy.total_cmp(&b.energy)) {
+            Some(target) => {
+                let confidence = energy_scaled(RADAR_ONLY_CONFIDENCE, target);
+                Presence::person(target.distance_cm, target.motion, confidence, Basis::RadarOnly)
+            }
+            None => Presence::absent(Basis::NoTarget),
+        };
+    };
+    // Radar target closest to the lidar distance
+    let Some(target) = targets.iter().min_by_key(|target| (target.distance_cm - lidar_cm).abs()) else {
+        // Without a background, the lidar alone cannot tell a person from the furniture.
+        return match config.background_cm {
+            Some(_) => Presence::person(lidar_cm, Motion::Still, LIDAR_ONLY_CONFIDENCE, Basis::LidarOnly),
+            None => Presence::absent(Basis::NoTarget),
+        };
+    };
+    if (target.distance_cm - lidar_cm).abs() <= agreement_cm {
+        let confidence = energy_scaled(AGREEMENT_CONFIDENCE, target);
+        return Presence::person(lidar_cm, target.motion, confidence, Basis::Agreement);
+    }
+    let confidence = energy_scaled(DISAGREEMENT_CONFIDENCE, target);
+    match config.on_disagreement {
+        Disagreement::Lidar => Presence::person(lidar_cm, target.motion, confidence, Basis::Disagreement),
+        Disagreement::Radar => Presence::person(target.distance_cm, target.motion, confidence, Basis::Disagreement),
+        Disagreement::Absent => Presence::absent(Basis::Disagreement),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // config() Returns a presence configuration with the defaults and the `extra` TOML lines.
+    fn config(extra: &str) -> PresenceConfig {
+        toml::from_str(&format!("radar = \"radar\"\nlidar = \"lidar\"\n{extra}")).unwrap()
+    }
+
+    fn radar(target_status: TargetStatus, moving: (u16, u8), stationary: (u16, u8)) -> TargetData {
+        TargetData {
+            target_status,
+            movement_target_distance: moving.0,
+            movement_target_energy: moving.1,
+            stationary_target_distance: stationary.0,
+            stationary_target_energy: stationary.1,
+            detection_distance: moving.0.max(stationary.0),
+            engineering_model: None,
+        }
+    }
+
+    fn lidar(distance_cm: u32, amplitude: u16) -> Option<Lidar> {
+        Some(Lidar {
+            distance_cm,
+            amplitude: Some(amplitude),
+        })
+    }
+
+    fn assert_presence(presence: Presence, distance_cm: u32, motion: Motion, confidence: f32, basis: Basis) {
+        assert!(presence.present, "{presence:?}");
+        assert_eq!((presence.distance_cm, presence.motion, presence.basis), (Some(distance_cm), Some(motion), basis));
+        assert!((presence.confidence - confidence).abs() < 1e-6, "{presence:?}");
+    }
+
+    #[test]
+    fn radar_and_lidar_agree() {
+        let moving = radar(TargetStatus::MovingTarget, (200, 100), (0, 0));
+        assert_presence(fuse(&config(""), &moving, lidar(180, 500)), 180, Motion::Moving, 1.0, Basis::Agreement);
+        // The offsets are applied before the comparison.
+        let config = config("radar_offset_cm = -100\nlidar_offset_cm = 20");
+        let still = radar(TargetStatus::StationaryTarget, (0, 0), (300, 50));
+        assert_presence(fuse(&config, &still, lidar(180, 500)), 200, Motion::Still, 0.75, Basis::Agreement);
+    }
+
+    #[test]
+    fn radar_only() {
+        let still = radar(TargetStatus::StationaryTarget, (0, 0), (300, 50));
+        assert_presence(fuse(&config(""), &still, None), 300, Motion::Still, 0.45, Basis::RadarOnly);
+        // The strongest target when both are reported.
+        let both = radar(TargetStatus::MovingAndStationaryTarget, (150, 80), (300, 50));
+        assert_presence(fuse(&config(""), &both, None), 150, Motion::Moving, 0.54, Basis::RadarOnly);
+        // The lidar sees the background.
+        let moving = radar(TargetStatus::MovingTarget, (200, 100), (0, 0));
+        let config = config("background_cm = 400");
+        assert_presence(fuse(&config, &moving, lidar(360, 500)), 200, Motion::Moving, 0.6, Basis::RadarOnly);
+    }
+
+    #[test]
+    fn stale_lidar() {
+        let nobody = radar(TargetStatus::NoTarget, (0, 0), (0, 0));
+        assert_eq!(fuse(&config("background_cm = 400"), &nobody, None), Presence::absent(Basis::NoTarget));
+        let moving = radar(TargetStatus::MovingTarget, (200, 100), (0, 0));
+        assert_presence(fuse(&config(""), &moving, None), 200, Motion::Moving, 0.6, Basis::RadarOnly);
+    }
+
+    #[test]
+    fn lidar_out_of_range_or_too_weak() {
+        let moving = radar(TargetStatus::MovingTarget, (200, 100), (0, 0));
+        for reading in [lidar(900, 500), lidar(0, 500), lidar(180, 20)] {
+            assert_presence(fuse(&config(""), &moving, reading), 200, Motion::Moving, 0.6, Basis::RadarOnly);
+        }
+        // Without amplitude (TOF200F), only the range is checked.
+        let tof = Some(Lidar {
+            distance_cm: 180,
+            amplitude: None,
+        });
+        assert_presence(fuse(&config(""), &moving, tof), 180, Motion::Moving, 1.0, Basis::Agreement);
+    }
+
+    #[test]
+    fn lidar_only() {
+        let nobody = radar(TargetStatus::NoTarget, (0, 0), (0, 0));
+        assert_presence(fuse(&config("background_cm = 400"), &nobody, lidar(250, 500)), 250, Motion::Still, 0.3, Basis::LidarOnly);
+        // Without a background the lidar may see furniture.
+        assert_eq!(fuse(&config(""), &nobody, lidar(250, 500)), Presence::absent(Basis::NoTarget));
+    }
+
+    #[test]
+    fn radar_and_lidar_disagree() {
+        let moving = radar(TargetStatus::MovingTarget, (500, 100), (0, 0));
+        let fuse_with = |rule: &str| fuse(&config(&format!("on_disagreement = \"{rule}\"")), &moving, lidar(180, 500));
+        assert_presence(fuse_with("lidar"), 180, Motion::Moving, 0.4, Basis::Disagreement);
+        assert_presence(fuse_with("radar"), 500, Motion::Moving, 0.4, Basis::Disagreement);
+        assert_eq!(fuse_with("absent"), Presence::absent(Basis::Disagreement));
+    }
+}
@@ -0,0 +1,338 @@
+// fusion/room.rs
+// Fusion of several RD03D covering the same room into one map. Each radar reports up to 3 targets in its own
+// frame; with the pose of each radar (config.rs RadarPose) they are placed in the frame of the room, the
+// detections of the same person by several radars are merged, and the merged targets are tracked so that a
+// person keeps the same id while moving from the field of a radar to the next.
+//
+//   to_room()   radar frame -> room frame
+//   merge()     one point per person: detections of different radars closer than merge_mm are averaged
+//   Tracker     associates the points with the tracks of the previous update (nearest first, within gate_mm),
+//               smooths their positions and velocities, and drops the tracks not seen for track_timeout_ms
+use serde::{Deserialize, Serialize};
+
+use crate::config::{RadarPose, RoomConfig};
+use crate::rd03d::Target;
+
+// Room is the reading of a room sensor.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Room {
+    pub targets: Vec<RoomTarget>,
+}
+
+// RoomTarget is a tracked person, in the frame of the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomTarget {
+    // Stable while the person is tracked.
+    pub id: u32,
+    pub x_mm: i32,
+    pub y_mm: i32,
+    pub vx_mm_s: i32,
+    pub vy_mm_s: i32,
+    // Ids of the radars seeing the person in the latest update, empty while the track is kept without
+    // being seen.
+    pub radars: Vec<String>,
+}
+
+// Detection is a target of one radar (index in RoomConfig::radars) in the frame of the room.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub radar: usize,
+    pub x_mm: f64,
+    pub y_mm: f64,
+}
+
+// Point is a person seen by one or more radars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub x_mm: f64,
+    pub y_mm: f64,
+    pub radars: Vec<usize>,
+}
+
+// to_room() Returns the position of a target in the frame of the room: the target is at x on the side
+// (towards the right of the RD03D dashboard) and y in front of the radar.
+pub fn to_room(pose: &RadarPose, target: &Target) -> (f64, f64) {
+    let side = target.x as f64;
+    let front = target.y as f64;
+    let (sin, cos) = pose.rotation_deg.to_radians().sin_cos();
+    (
+        pose.x_mm as f64 + side * cos - front * sin,
+        pose.y_mm as f64 + side * sin + front * cos,
+    )
+}
+
+// detections() Returns the targets of a reading of the radar `radar` in the frame of the room. The empty
+// slots (zero distance) are skipped.
+pub fn detections(radar: usize, pose: &RadarPose, targets: &[Target]) -> Vec<Detection> {
+    targets
+        .iter()
+        .filter(|target| target.distance > 0.0)
+        .map(|target| {
+            let (x_mm, y_mm) = to_room(pose, target);
+            Detection { radar, x_mm, y_mm }
+        })
+        .collect()
+}
+
+// merge() Groups the detections of the same person: each detection joins the nearest point within merge_mm
+// that has no detection of its radar yet (a radar already separates its own targets), and the position of a
+// point is the mean of its detections.
+pub fn merge(config: &RoomConfig, detections: &[Detection]) -> Vec<Point> {
+    // Detections of each point
+    let mut groups: Vec<Vec<Detection>> = Vec::new();
+    for detection in detections {
+        let nearest = groups
+            .iter_mut()
+            .filter(|group| group.iter().all(|other| other.radar != detection.radar))
+            .map(|group| {
+                let (x, y) = centroid(group);
+                (distance(x - detection.x_mm, y - detection.y_mm), group)
+            })
+            .filter(|(distance, _)| *distance <= config.merge_mm as f64)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match nearest {
+            Some((_, group)) => group.push(*detection),
+            None => groups.push(vec![*detection]),
+        }
+    }
+    groups
+        .iter()
+        .map(|group| {
+            let (x_mm, y_mm) = centroid(group);
+            Point {
+                x_mm,
+                y_mm,
+                radars: group.iter().map(|detection| detection.radar).collect(),
+            }
+        })
+        .collect()
+}
+
+fn centroid(group: &[Detection]) -> (f64, f64) {
+    let n = group.len().max(1) as f64;
+    let x = group.iter().map(|detection| detection.x_mm).sum::<f64>() / n;
+    let y = group.iter().map(|detection| detection.y_mm).sum::<f64>() / n;
+    (x, y)
+}
+
+fn distance(dx: f64, dy: f64) -> f64 {
+    (dx * dx + dy * dy).sqrt()
+}
+
+struct Track {
+    id: u32,
+    x_mm: f64,
+    y_mm: f64,
+    vx_mm_s: f64,
+    vy_mm_s: f64,
+    last_seen_ms: u64,
+    radars: Vec<usize>,
+}
+
+// Tracker keeps the ids of the people across the updates of a room sensor.
+#[derive(Default)]
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u32,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // update() Associates the points of a new update with the tracks and returns the tracked targets, by id.
+    // A point is matched with the track whose predicted position (last position + velocity) is the nearest,
+    // nearest pairs first; the points left start new tracks.
+    pub fn update(&mut self, config: &RoomConfig, points: &[Point], now_ms: u64) -> Room {
+        self.tracks
+            .retain(|track| now_ms.saturating_sub(track.last_seen_ms) <= config.track_timeout_ms);
+        let elapsed = |track: &Track| now_ms.saturating_sub(track.last_seen_ms) as f64 / 1000.0;
+        let predicted = |track: &Track| {
+            let dt = elapsed(track);
+            (track.x_mm + track.vx_mm_s * dt, track.y_mm + track.vy_mm_s * dt)
+        };
+        // Track / point pairs within the gate, nearest first
+        let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            let (x, y) = predicted(track);
+            for (p, point) in points.iter().enumerate() {
+                let d = distance(point.x_mm - x, point.y_mm - y);
+                if d <= config.gate_mm as f64 {
+                    pairs.push((d, t, p));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        let mut matched_points = vec![false; points.len()];
+        let alpha = config.smoothing.clamp(0.0, 1.0);
+        for (_, t, p) in pairs {
+            if matched_tracks[t] || matched_points[p] {
+                continue;
+            }
+            matched_tracks[t] = true;
+            matched_points[p] = true;
+            let track = &mut self.tracks[t];
+            let point = &points[p];
+            let dt = elapsed(track);
+            let (px, py) = predicted(track);
+            let x = px + alpha * (point.x_mm - px);
+            let y = py + alpha * (point.y_mm - py);
+            if dt > 0.0 {
+                track.vx_mm_s += alpha * ((x - track.x_mm) / dt - track.vx_mm_s);
+                track.vy_mm_s += alpha * ((y - track.y_mm) / dt - track.vy_mm_s);
+            }
+            track.x_mm = x;
+            track.y_mm = y;
+            track.last_seen_ms = now_ms;
+            track.radars = point.radars.clone();
+        }
+        for (track, matched) in self.tracks.iter_mut().zip(&matched_tracks) {
+            if !matched {
+                track.radars.clear();
+            }
+        }
+        for (point, _) in points.iter().zip(&matched_points).filter(|(_, matched)| !**matched) {
+            self.tracks.push(Track {
+                id: self.next_id,
+                x_mm: point.x_mm,
+                y_mm: point.y_mm,
+                vx_mm_s: 0.0,
+                vy_mm_s: 0.0,
+                last_seen_ms: now_ms,
+                radars: point.radars.clone(),
+            });
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        self.tracks.sort_by_key(|track| track.id);
+        let targets = self
+            .tracks
+            .iter()
+            .map(|track| RoomTarget {
+                id: track.id,
+                x_mm: track.x_mm.round() as i32,
+                y_mm: track.y_mm.round() as i32,
+                vx_mm_s: track.vx_mm_s.round() as i32,
+                vy_mm_s: track.vy_mm_s.round() as i32,
+                radars: track
+                    .radars
+                    .iter()
+                    .filter_map(|&radar| config.radars.get(radar).map(|pose| pose.id.clone()))
+                    .collect(),
+            })
+            .collect();
+        Room { targets }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // config() Returns a room with the radar "a" at the origin and "b" 3 m further along x, both facing +y,
+    // without smoothing, and the `extra` TOML lines.
+    fn config(extra: &str) -> RoomConfig {
+        toml::from_str(&format!(
+            "smoothing = 1.0\n{extra}\n[[radars]]\nid = \"a\"\n[[radars]]\nid = \"b\"\nx_mm = 3000\n"
+        ))
+        .unwrap()
+    }
+
+    // seen() Returns the detections of a person at (x, y) in the room by the radars `radars`.
+    fn seen(config: &RoomConfig, radars: &[usize], x: i16, y: i16) -> Vec<Detection> {
+        radars
+            .iter()
+            .flat_map(|&radar| {
+                let pose = &config.radars[radar];
+                let target = Target::new(x - pose.x_mm as i16, y - pose.y_mm as i16, 0, 0);
+                detections(radar, pose, &[target])
+            })
+            .collect()
+    }
+
+    fn position(target: &RoomTarget) -> (u32, i32, i32, Vec<&str>) {
+        (target.id, target.x_mm, target.y_mm, target.radars.iter().map(String::as_str).collect())
+    }
+
+    #[test]
+    fn to_room_applies_the_pose() {
+        let pose: RadarPose = toml::from_str("id = \"r\"\nx_mm = 4000\ny_mm = 3000\nrotation_deg = 90").unwrap();
+        let (x, y) = to_room(&pose, &Target::new(0, 1000, 0, 0));
+        assert!((x - 3000.0).abs() < 1e-6 && (y - 3000.0).abs() < 1e-6);
+        // Facing -x, the right of the radar is towards +y.
+        let (x, y) = to_room(&pose, &Target::new(500, 1000, 0, 0));
+        assert!((x - 3000.0).abs() < 1e-6 && (y - 3500.0).abs() < 1e-6);
+        // Empty slots are skipped.
+        assert!(detections(0, &pose, &[Target::new(0, 0, 0, 0)]).is_empty());
+    }
+
+    #[test]
+    fn merge_two_radars_seeing_the_same_person() {
+        let config = config("");
+        let mut detections = seen(&config, &[0, 1], 1400, 2000);
+        // The second radar is 200 mm off.
+        detections[1].x_mm += 200.0;
+        let points = merge(&config, &detections);
+        assert_eq!(points, [Point { x_mm: 1500.0, y_mm: 2000.0, radars: vec![0, 1] }]);
+        // Farther than merge_mm: two people.
+        detections[1].x_mm += 400.0;
+        assert_eq!(merge(&config, &detections).len(), 2);
+        // Two targets of the same radar are never merged.
+        let same_radar = [seen(&config, &[0], 1400, 2000), seen(&config, &[0], 1500, 2000)].concat();
+        assert_eq!(merge(&config, &same_radar).len(), 2);
+    }
+
+    #[test]
+    fn person_keeps_its_id_from_one_radar_to_the_next() {
+        let config = config("");
+        let mut tracker = Tracker::new();
+        // Walks along y = 2000 at 1 m/s, seen by "a" up to x = 1200, by both up to 1800, then by "b".
+        let mut room = Room::default();
+        for step in 0..=30 {
+            let x = step * 100;
+            let radars: &[usize] = match x {
+                ..=1200 => &[0],
+                1201..=1800 => &[0, 1],
+                _ => &[1],
+            };
+            let points = merge(&config, &seen(&config, radars, x as i16, 2000));
+            room = tracker.update(&config, &points, step as u64 * 100);
+            assert_eq!(room.targets.len(), 1, "at x = {x}");
+            assert_eq!(room.targets[0].id, 0, "at x = {x}");
+        }
+        assert_eq!(position(&room.targets[0]), (0, 3000, 2000, vec!["b"]));
+        assert_eq!((room.targets[0].vx_mm_s, room.targets[0].vy_mm_s), (1000, 0));
+    }
+
+    #[test]
+    fn track_expires_after_track_timeout_ms() {
+        let config = config("track_timeout_ms = 1500");
+        let mut tracker = Tracker::new();
+        let points = merge(&config, &seen(&config, &[0], 500, 2000));
+        tracker.update(&config, &points, 1000);
+        // Kept at its last position without being seen, until the timeout.
+        let room = tracker.update(&config, &[], 2500);
+        assert_eq!(room.targets.iter().map(position).collect::<Vec<_>>(), [(0, 500, 2000, vec![])]);
+        assert!(tracker.update(&config, &[], 2501).targets.is_empty());
+        // The person seen again gets a new id.
+        let room = tracker.update(&config, &points, 2600);
+        assert_eq!(room.targets.iter().map(position).collect::<Vec<_>>(), [(1, 500, 2000, vec!["a"])]);
+    }
+
+    #[test]
+    fn update_at_the_same_time_keeps_the_velocity() {
+        let mut config = config("");
+        config.smoothing = 0.5;
+        let mut tracker = Tracker::new();
+        tracker.update(&config, &merge(&config, &seen(&config, &[0], 0, 2000)), 1000);
+        // dt == 0: the position is smoothed, the velocity is not updated.
+        let room = tracker.update(&config, &merge(&config, &seen(&config, &[0], 100, 2000)), 1000);
+        let target = &room.targets[0];
+        assert_eq!((target.id, target.x_mm, target.y_mm, target.vx_mm_s, target.vy_mm_s), (0, 50, 2000, 0, 0));
+        // The next update gets the velocity from the smoothed position.
+        let room = tracker.update(&config, &merge(&config, &seen(&config, &[0], 150, 2000)), 1100);
+        let target = &room.targets[0];
+        assert_eq!((target.x_mm, target.vx_mm_s), (100, 250));
+    }
+}
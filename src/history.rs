@@ -0,0 +1,340 @@
+// history.rs
+// Storage of the readings: append-only segment files of JSON lines ({"timestamp_ms": ..., "data": ...}),
+//   <dir>/<sensor id>/raw/<segment start ms>.jsonl          readings recorded at record_hz
+//   <dir>/<sensor id>/downsampled/<segment start ms>.jsonl  averages of the raw readings, one every downsampled_secs
+// A raw segment older than raw_retention_hours is downsampled then deleted; a segment older than
+// retention_days is deleted. The segments are read back by the history endpoint (api.rs) and the export.
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::config::{HistoryConfig, SensorKind};
+use crate::hub::{now_ms, Hub, Reading, Sample, Sensor};
+use crate::stream::{self, Rate, Reduce};
+
+// Period of the writes of the recorded readings to the segment files.
+const FLUSH_PERIOD: Duration = Duration::from_secs(1);
+// Period of the downsampling and deletion of the old segments.
+const MAINTENANCE_PERIOD: Duration = Duration::from_secs(600);
+// Maximum number of readings returned by a query, the readings are averaged beyond.
+pub const MAX_POINTS: usize = 10_000;
+
+pub(crate) const RAW: &str = "raw";
+const DOWNSAMPLED: &str = "downsampled";
+
+#[derive(Clone)]
+pub struct History {
+    config: Arc<HistoryConfig>,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    // start() Spawns the recording of the readings of all the sensors and the maintenance of the segments.
+    // Must be called from a tokio runtime.
+    pub fn start(&self, hub: &Hub) {
+        for sensor in hub.sensors() {
+            tokio::spawn(self.clone().record(sensor.clone()));
+        }
+        let history = self.clone();
+        let sensors: Vec<(String, SensorKind)> = hub
+            .sensors()
+            .iter()
+            .map(|sensor| (sensor.config.id.clone(), sensor.config.kind))
+            .collect();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(MAINTENANCE_PERIOD);
+            loop {
+                interval.tick().await;
+                let (history, sensors) = (history.clone(), sensors.clone());
+                let result = tokio::task::spawn_blocking(move || {
+                    for (id, kind) in sensors {
+                        if let Err(e) = history.maintain(&id, kind, now_ms()) {
+                            println!("history of {id}: {e}");
+                        }
+                    }
+                })
+                .await;
+                if let Err(e) = result {
+                    println!("history maintenance failed: {e}");
+                }
+            }
+        });
+    }
+
+    // record() Appends the readings of a sensor to its raw segments.
+    async fn record(self, sensor: Arc<Sensor>) {
+        let samples = stream::samples(sensor.clone(), Rate::Hz(self.config.record_hz), Reduce::Average);
+        let mut chunks = Box::pin(tokio_stream::StreamExt::chunks_timeout(samples, 1024, FLUSH_PERIOD));
+        while let Some(chunk) = chunks.next().await {
+            let (history, id) = (self.clone(), sensor.config.id.clone());
+            let result = tokio::task::spawn_blocking(move || history.append(&id, RAW, &chunk)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => println!("history of {}: {e}", sensor.config.id),
+                Err(e) => println!("history of {}: {e}", sensor.config.id),
+            }
+        }
+    }
+
+    fn segment_ms(&self) -> u64 {
+        self.config.segment_minutes * 60_000
+    }
+
+    fn dir(&self, id: &str, level: &str) -> PathBuf {
+        let id: String = id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        Path::new(&self.config.dir).join(id).join(level)
+    }
+
+    // append() Appends readings to the segments of a sensor, one JSON line per reading.
+    pub(crate) fn append(&self, id: &str, level: &str, samples: &[Sample]) -> std::io::Result<()> {
+        let dir = self.dir(id, level);
+        fs::create_dir_all(&dir)?;
+        for segment in samples.chunk_by(|a, b| a.timestamp_ms / self.segment_ms() == b.timestamp_ms / self.segment_ms()) {
+            let start = segment[0].timestamp_ms / self.segment_ms() * self.segment_ms();
+            let mut lines = Vec::new();
+            for sample in segment {
+                serde_json::to_writer(&mut lines, sample)?;
+                lines.push(b'\n');
+            }
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(format!("{start}.jsonl")))?;
+            file.write_all(&lines)?;
+        }
+        Ok(())
+    }
+
+    // segments() Returns the start (ms) and path of the segments of a sensor, sorted by start.
+    fn segments(&self, id: &str, level: &str) -> Vec<(u64, PathBuf)> {
+        let mut segments: Vec<(u64, PathBuf)> = fs::read_dir(self.dir(id, level))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let start = path.file_stem()?.to_str()?.parse().ok()?;
+                (path.extension()? == "jsonl").then_some((start, path))
+            })
+            .collect();
+        segments.sort();
+        segments
+    }
+
+    // read() Returns the readings of a sensor between `from` and `to` (ms, inclusive), sorted by time.
+    // Lines that cannot be decoded (e.g. a write interrupted by a crash) are skipped.
+    pub fn read(&self, id: &str, kind: SensorKind, from: u64, to: u64) -> std::io::Result<Vec<Sample>> {
+        let mut samples = Vec::new();
+        for level in [DOWNSAMPLED, RAW] {
+            for (start, path) in self.segments(id, level) {
+                if start > to || start + self.segment_ms() <= from {
+                    continue;
+                }
+                samples.extend(
+                    read_segment(&path, kind)?
+                        .into_iter()
+                        .filter(|sample| (from..=to).contains(&sample.timestamp_ms)),
+                );
+            }
+        }
+        samples.sort_by_key(|sample| sample.timestamp_ms);
+        Ok(samples)
+    }
+
+    // query() Returns the readings of a sensor between `from` and `to` (ms), averaged over `resolution_ms`
+    // if given. Without resolution, or if there are more than MAX_POINTS readings, the resolution is the
+    // smallest one returning at most MAX_POINTS readings. Returns the readings and the resolution used.
+    pub fn query(
+        &self,
+        id: &str,
+        kind: SensorKind,
+        from: u64,
+        to: u64,
+        resolution_ms: Option<u64>,
+    ) -> std::io::Result<(Vec<Sample>, Option<u64>)> {
+        let samples = self.read(id, kind, from, to)?;
+        let minimum = (to - from).div_ceil(MAX_POINTS as u64).max(1);
+        let resolution = match resolution_ms {
+            Some(resolution) => Some(resolution.max(minimum)),
+            None if samples.len() > MAX_POINTS => Some(minimum),
+            None => None,
+        };
+        Ok(match resolution {
+            Some(resolution) => (downsample(&samples, resolution), Some(resolution)),
+            None => (samples, None),
+        })
+    }
+
+    // maintain() Downsamples the raw segments older than raw_retention_hours and deletes the segments older
+    // than retention_days.
+    fn maintain(&self, id: &str, kind: SensorKind, now: u64) -> std::io::Result<()> {
+        let raw_limit = now.saturating_sub(self.config.raw_retention_hours * 3_600_000);
+        let limit = now.saturating_sub(self.config.retention_days * 86_400_000);
+        for (start, path) in self.segments(id, RAW) {
+            let end = start + self.segment_ms();
+            if end > raw_limit {
+                break;
+            }
+            if end > limit {
+                let samples = read_segment(&path, kind)?;
+                let downsampled = downsample(&samples, self.config.downsampled_secs * 1000);
+                self.append(id, DOWNSAMPLED, &downsampled)?;
+            }
+            fs::remove_file(&path)?;
+        }
+        for (start, path) in self.segments(id, DOWNSAMPLED) {
+            if start + self.segment_ms() > limit {
+                break;
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_segment(path: &Path, kind: SensorKind) -> std::io::Result<Vec<Sample>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        // Deleted by the maintenance in the meantime.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut samples = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&line?) else {
+            continue;
+        };
+        let Some(timestamp_ms) = value["timestamp_ms"].as_u64() else {
+            continue;
+        };
+        if let Ok(data) = Reading::from_json(kind, value["data"].take()) {
+            samples.push(Sample { timestamp_ms, data });
+        }
+    }
+    Ok(samples)
+}
+
+// downsample() Averages the readings (sorted by time) over periods of `resolution_ms`. The timestamp of an
+// average is the start of its period.
+pub fn downsample(samples: &[Sample], resolution_ms: u64) -> Vec<Sample> {
+    samples
+        .chunk_by(|a, b| a.timestamp_ms / resolution_ms == b.timestamp_ms / resolution_ms)
+        .filter_map(|period| {
+            let data = stream::average(period)?;
+            Some(Sample {
+                timestamp_ms: period[0].timestamp_ms / resolution_ms * resolution_ms,
+                data,
+            })
+        })
+        .collect()
+}
+
+// parse_duration() Parses a duration such as "500ms", "10s", "5m", "1h" or "1d" (seconds without unit) and
+// returns it in milliseconds.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: u64 = value.parse().ok()?;
+    let unit_ms = match unit {
+        "ms" => 1,
+        "" | "s" => 1000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    value.checked_mul(unit_ms).filter(|ms| *ms > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tof200f::Tof200fData;
+
+    const DAY_MS: u64 = 86_400_000;
+    // Midnight UTC, a multiple of the segment length.
+    const BASE: u64 = 19_676 * DAY_MS;
+
+    // history() Returns a history in a new temporary directory, with 1 minute segments, raw readings kept 1 h,
+    // averaged over 10 s, and everything deleted after 1 day.
+    fn history(name: &str) -> History {
+        let dir = std::env::temp_dir().join(format!("sensor-history-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        History::new(HistoryConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            record_hz: 2.0,
+            segment_minutes: 1,
+            raw_retention_hours: 1,
+            downsampled_secs: 10,
+            retention_days: 1,
+        })
+    }
+
+    fn tof(timestamp_ms: u64, distance: u16) -> Sample {
+        Sample {
+            timestamp_ms,
+            data: Reading::Tof200f(Tof200fData::new(distance)),
+        }
+    }
+
+    fn distances(samples: &[Sample]) -> Vec<(u64, u16)> {
+        samples
+            .iter()
+            .map(|sample| match &sample.data {
+                Reading::Tof200f(data) => (sample.timestamp_ms, data.distance()),
+                data => panic!("unexpected reading {data:?}"),
+            })
+            .collect()
+    }
+
+    fn starts(history: &History, level: &str) -> Vec<u64> {
+        history.segments("tof", level).into_iter().map(|(start, _)| start).collect()
+    }
+
+    #[test]
+    fn append_rotates_segments() {
+        let history = history("rotation");
+        let samples = [tof(BASE, 100), tof(BASE + 59_999, 200), tof(BASE + 60_000, 300), tof(BASE + 125_000, 400)];
+        history.append("tof", RAW, &samples[..2]).unwrap();
+        history.append("tof", RAW, &samples[2..]).unwrap();
+        assert_eq!(starts(&history, RAW), [BASE, BASE + 60_000, BASE + 120_000]);
+        let lines = fs::read_to_string(history.dir("tof", RAW).join(format!("{BASE}.jsonl"))).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+
+        let read = history.read("tof", SensorKind::Tof200f, BASE + 59_999, BASE + 125_000).unwrap();
+        assert_eq!(distances(&read), [(BASE + 59_999, 200), (BASE + 60_000, 300), (BASE + 125_000, 400)]);
+        fs::remove_dir_all(&history.config.dir).unwrap();
+    }
+
+    #[test]
+    fn maintain_downsamples_then_deletes_old_segments() {
+        let history = history("retention");
+        let now = BASE + 3 * DAY_MS;
+        let expired = BASE;
+        let old = now - 2 * 3_600_000;
+        let recent = now - 10 * 60_000;
+        history.append("tof", RAW, &[tof(expired, 10), tof(old, 100), tof(old + 5_000, 200), tof(old + 12_000, 300)]).unwrap();
+        history.append("tof", RAW, &[tof(recent, 500)]).unwrap();
+        history.append("tof", DOWNSAMPLED, &[tof(expired, 20)]).unwrap();
+
+        history.maintain("tof", SensorKind::Tof200f, now).unwrap();
+        // The expired segments are deleted, the old raw one is replaced by its 10 s averages.
+        assert_eq!(starts(&history, RAW), [recent]);
+        assert_eq!(starts(&history, DOWNSAMPLED), [old]);
+        let read = history.read("tof", SensorKind::Tof200f, BASE, now).unwrap();
+        assert_eq!(distances(&read), [(old, 150), (old + 10_000, 300), (recent, 500)]);
+        fs::remove_dir_all(&history.config.dir).unwrap();
+    }
+}
@@ -0,0 +1,1160 @@
+// hub.rs
+// Long-lived sensor tasks of the web server. Each configured sensor is opened once by its own task, which
+// publishes the latest reading and keeps the connection state and the frame/error counters. The HTML, SSE
+// and JSON endpoints of api.rs only read from the hub instead of opening the serial port themselves.
+//
+// The connection is supervised: after an error the sensor is reopened with an exponential backoff, an
+// unplugged device is reopened as soon as it reappears (device.rs), and the configured modes are applied
+// again on every connection. The changes of connection state are sent as events (Hub::events()).
+//
+// The virtual sensors (fusion/) open no port: their task fuses the readings of other sensors of the hub.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::config::{SensorConfig, SensorKind};
+use crate::device;
+use crate::fusion::{presence, room};
+use crate::{ld2410c, rd03d, tf_luna, tof200f};
+
+// Delays before reopening a sensor after a connection or I/O error: doubled after each failed attempt.
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+// A session connected for at least this long resets the reconnection delay.
+const STABLE_SESSION: Duration = Duration::from_secs(10);
+// Consecutive failed reads (timeout, invalid response) after which the sensor is reopened.
+const MAX_READ_FAILURES: u32 = 3;
+// Period of the checks of the device: unplugged while connected, plugged back in while waiting for it.
+const DEVICE_CHECK_PERIOD: Duration = Duration::from_millis(500);
+// Delay between the reappearance of a device and its opening, for the system to set it up.
+const DEVICE_SETTLE_DELAY: Duration = Duration::from_millis(500);
+// Number of connection events kept for slow receivers.
+const EVENT_QUEUE_LEN: usize = 64;
+// Maximum time to wait for the sensor task to run a command.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// Maximum time to wait for a command restarting the sensor or reopening its port. The LD2410C baud rate change
+// restarts the radar, waits for it and looks for it at every baud rate if it does not answer at the new one.
+const SLOW_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+// Number of commands waiting for the sensor task.
+const COMMAND_QUEUE_LEN: usize = 8;
+
+// Ld2410cCommand is a configuration command run by the LD2410C task between two reads, so that it
+// does not collide with the report stream.
+#[derive(Debug, Clone)]
+pub enum Ld2410cCommand {
+    ReadFirmwareVersion,
+    GetMacAddress,
+    ReadParameters,
+    QueryDistanceResolution,
+    QueryAuxiliaryControl,
+    SetEngineeringMode(bool),
+    SetDistanceResolution(ld2410c::DistanceResolution),
+    SetGateSensitivity {
+        gate: ld2410c::GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    },
+    SetBaudRate(ld2410c::BaudRate),
+    SetBluetooth(bool),
+    SetBluetoothPassword(String),
+    SetAuxiliaryControl(ld2410c::AuxiliaryControl),
+    Restart,
+    FactoryReset,
+}
+
+impl Ld2410cCommand {
+    // gate_sensitivity() Returns the SetGateSensitivity command after checking the gate (0 to 8, all the
+    // gates if None) and the sensitivities (0 to 100).
+    pub fn gate_sensitivity(gate: Option<u8>, motion_sensitivity: u8, standstill_sensitivity: u8) -> Result<Self, String> {
+        let gate = match gate {
+            None => ld2410c::GateValue::GateValueAll,
+            Some(gate) => ld2410c::GateValue::from_gate(gate).ok_or_else(|| format!("invalid gate {gate}, expected 0 to 8"))?,
+        };
+        if motion_sensitivity > 100 || standstill_sensitivity > 100 {
+            return Err(ld2410c::CommandError::InvalidSensitivity.to_string());
+        }
+        Ok(Ld2410cCommand::SetGateSensitivity {
+            gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TflunaCommand {
+    SetOutputFrequency(tf_luna::OutputFrequency),
+    SetDistanceLimit { dist_min: u16, dist_max: u16 },
+    SetOutputFormat(tf_luna::OutputFormat),
+    SetBaudRate(u32),
+}
+
+#[derive(Debug, Clone)]
+pub enum Rd03dCommand {
+    SetMultiMode(bool),
+    SetMode(u8),
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Ld2410c(Ld2410cCommand),
+    Tfluna(TflunaCommand),
+    Rd03d(Rd03dCommand),
+}
+
+impl Command {
+    // kind() Returns the kind of sensor supporting the command.
+    pub fn kind(&self) -> SensorKind {
+        match self {
+            Command::Ld2410c(_) => SensorKind::Ld2410c,
+            Command::Tfluna(_) => SensorKind::Tfluna,
+            Command::Rd03d(_) => SensorKind::Rd03d,
+        }
+    }
+
+    // timeout() Returns the maximum time to wait for the sensor task to run the command.
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Command::Ld2410c(Ld2410cCommand::SetBaudRate(_) | Ld2410cCommand::Restart | Ld2410cCommand::FactoryReset)
+            | Command::Tfluna(TflunaCommand::SetBaudRate(_)) => SLOW_COMMAND_TIMEOUT,
+            _ => COMMAND_TIMEOUT,
+        }
+    }
+}
+
+// SendError is returned when a command could not be run by the sensor task.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendError {
+    Unsupported(SensorKind),
+    NotConnected,
+    // Too many commands are waiting for the sensor task.
+    Busy,
+    Timeout,
+    // The sensor rejected the command or its response could not be decoded.
+    Failed(String),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Unsupported(kind) => write!(f, "command not supported by the {} sensor", kind.name()),
+            SendError::NotConnected => write!(f, "sensor not connected"),
+            SendError::Busy => write!(f, "too many pending commands"),
+            SendError::Timeout => write!(f, "no response from the sensor in time, the command may still be running"),
+            SendError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+// Request is a command sent to the sensor task, with the channel to send the result back.
+struct Request {
+    command: Command,
+    reply: oneshot::Sender<anyhow::Result<Value>>,
+}
+
+// Reading is the latest decoded data of a sensor. It serializes to the same JSON as the SSE streams.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Reading {
+    Ld2410c(ld2410c::Ld2410CData),
+    Rd03d(Vec<rd03d::Target>),
+    Tfluna(tf_luna::TfLunaData),
+    Tof200f(tof200f::Tof200fData),
+    Presence(presence::Presence),
+    Room(room::Room),
+}
+
+impl Reading {
+    // from_json() Decodes the JSON of a reading of a sensor of the given kind (the TF-Luna and TOF200F
+    // readings have the same JSON).
+    pub fn from_json(kind: SensorKind, value: Value) -> serde_json::Result<Self> {
+        Ok(match kind {
+            SensorKind::Ld2410c => Reading::Ld2410c(serde_json::from_value(value)?),
+            SensorKind::Rd03d => Reading::Rd03d(serde_json::from_value(value)?),
+            SensorKind::Tfluna => Reading::Tfluna(serde_json::from_value(value)?),
+            SensorKind::Tof200f => Reading::Tof200f(serde_json::from_value(value)?),
+            SensorKind::Presence => Reading::Presence(serde_json::from_value(value)?),
+            SensorKind::Room => Reading::Room(serde_json::from_value(value)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    // Reception time, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub data: Reading,
+}
+
+// ConnectionState is the state of the connection of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    // Opening the port and applying the configured modes.
+    Connecting,
+    Connected,
+    // Waiting before reopening the sensor after an error.
+    Disconnected,
+    // The device is not plugged in: its port does not exist or no USB adapter matches.
+    WaitingForDevice,
+}
+
+impl ConnectionState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::WaitingForDevice => "waiting_for_device",
+        }
+    }
+}
+
+// log_state() Prints a change of connection state of a sensor or of a connection of the hub (MQTT).
+pub fn log_state(name: &str, state: ConnectionState, error: Option<&str>, retry_in: Option<Duration>) {
+    match (error, retry_in) {
+        (Some(error), Some(retry_in)) => println!(
+            "{name}: {} ({error}), retry in {} ms",
+            state.name(),
+            retry_in.as_millis()
+        ),
+        _ => println!("{name}: {}", state.name()),
+    }
+}
+
+// SensorEvent is a change of connection state of a sensor.
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorEvent {
+    pub sensor: String,
+    pub timestamp_ms: u64,
+    pub state: ConnectionState,
+    // Port of the device, None while it is not plugged in.
+    pub port: Option<String>,
+    // Error that ended the connection (Disconnected).
+    pub error: Option<String>,
+    // Delay before the next attempt (Disconnected).
+    pub retry_in_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorStatus {
+    pub id: String,
+    pub kind: SensorKind,
+    // Port of the device, found from the USB identifiers if configured.
+    pub port: String,
+    pub connected: bool,
+    pub state: ConnectionState,
+    // Time elapsed since the last decoded frame, None if no frame was received yet.
+    pub last_frame_age_ms: Option<u64>,
+    pub frames: u64,
+    // Serial and session errors.
+    pub errors: u64,
+    // Frames rejected by the decoder (invalid header, tail, check byte or length).
+    pub decode_errors: u64,
+    // Number of times the sensor was reopened.
+    pub reconnects: u64,
+    // Clients of the SSE stream of the sensor.
+    pub sse_clients: u64,
+    pub last_error: Option<String>,
+}
+
+pub struct Sensor {
+    pub config: SensorConfig,
+    latest: watch::Sender<Option<Sample>>,
+    commands: mpsc::Sender<Request>,
+    // Shared by the successive sessions of the sensor task.
+    requests: tokio::sync::Mutex<mpsc::Receiver<Request>>,
+    // LD2410C only: engineering mode applied when (re)connecting, changed by SetEngineeringMode.
+    engineering_mode: AtomicBool,
+    // Settings applied through the commands since the start of the server, e.g. {"multi_mode": true}.
+    settings: Mutex<serde_json::Map<String, Value>>,
+    state: Mutex<ConnectionState>,
+    // Port of the last connection.
+    port: Mutex<String>,
+    events: broadcast::Sender<SensorEvent>,
+    frames: AtomicU64,
+    errors: AtomicU64,
+    decode_errors: AtomicU64,
+    reconnects: AtomicU64,
+    // Whether the sensor was connected once, so that the next connections are counted as reconnections.
+    was_connected: AtomicBool,
+    sse_clients: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Sensor {
+    fn new(config: SensorConfig, events: broadcast::Sender<SensorEvent>) -> Self {
+        let (commands, requests) = mpsc::channel(COMMAND_QUEUE_LEN);
+        let mut settings = serde_json::Map::new();
+        if config.kind == SensorKind::Rd03d {
+            settings.insert("multi_mode".into(), json!(config.multi_mode));
+        }
+        Self {
+            engineering_mode: AtomicBool::new(config.engineering_mode),
+            port: Mutex::new(config.port.clone()),
+            config,
+            latest: watch::Sender::new(None),
+            commands,
+            requests: tokio::sync::Mutex::new(requests),
+            settings: Mutex::new(settings),
+            state: Mutex::new(ConnectionState::Disconnected),
+            events,
+            frames: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            was_connected: AtomicBool::new(false),
+            sse_clients: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    pub fn latest(&self) -> Option<Sample> {
+        self.latest.borrow().clone()
+    }
+
+    // subscribe() Returns a receiver notified on every new reading.
+    pub fn subscribe(&self) -> watch::Receiver<Option<Sample>> {
+        self.latest.subscribe()
+    }
+
+    // settings() Returns the settings applied since the start of the server. The sensors cannot be
+    // queried for most of them, so only the settings changed through the API are known.
+    pub fn settings(&self) -> Value {
+        let mut settings = self.settings.lock().unwrap().clone();
+        match self.config.kind {
+            SensorKind::Ld2410c => {
+                settings.insert("engineering_mode".into(), json!(self.engineering_mode.load(Ordering::Relaxed)));
+            }
+            SensorKind::Rd03d | SensorKind::Tfluna | SensorKind::Tof200f | SensorKind::Presence | SensorKind::Room => {}
+        }
+        Value::Object(settings)
+    }
+
+    fn set_setting(&self, name: &str, value: Value) {
+        self.settings.lock().unwrap().insert(name.to_string(), value);
+    }
+
+    fn setting(&self, name: &str) -> Option<Value> {
+        self.settings.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn status(&self) -> SensorStatus {
+        let state = self.state();
+        SensorStatus {
+            id: self.config.id.clone(),
+            kind: self.config.kind,
+            port: self.port.lock().unwrap().clone(),
+            connected: state == ConnectionState::Connected,
+            state,
+            last_frame_age_ms: self
+                .latest
+                .borrow()
+                .as_ref()
+                .map(|sample| now_ms().saturating_sub(sample.timestamp_ms)),
+            frames: self.frames.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            sse_clients: self.sse_clients.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+
+    // send() Runs a command in the sensor task and returns its result as JSON.
+    pub async fn send(&self, command: Command) -> Result<Value, SendError> {
+        if command.kind() != self.config.kind {
+            return Err(SendError::Unsupported(self.config.kind));
+        }
+        if self.state() != ConnectionState::Connected {
+            return Err(SendError::NotConnected);
+        }
+        let timeout = command.timeout();
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .try_send(Request { command, reply })
+            .map_err(|_| SendError::Busy)?;
+        match tokio::time::timeout(timeout, response).await {
+            Ok(Ok(result)) => result.map_err(|e| SendError::Failed(e.to_string())),
+            Ok(Err(_)) => Err(SendError::NotConnected),
+            Err(_) => Err(SendError::Timeout),
+        }
+    }
+
+    fn publish(&self, data: Reading) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.latest.send_replace(Some(Sample {
+            timestamp_ms: now_ms(),
+            data,
+        }));
+    }
+
+    fn error(&self, error: impl std::fmt::Display) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    fn decode_error(&self, error: impl std::fmt::Display) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    // sse_client() Counts an SSE client of the sensor until the returned guard is dropped.
+    pub fn sse_client(self: &Arc<Self>) -> SseClient {
+        self.sse_clients.fetch_add(1, Ordering::Relaxed);
+        SseClient(self.clone())
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        *self.state.lock().unwrap()
+    }
+
+    // set_state() Changes the connection state and sends the event, if the state changed.
+    fn set_state(&self, state: ConnectionState, port: Option<String>, error: Option<String>, retry_in: Option<Duration>) {
+        {
+            let mut current = self.state.lock().unwrap();
+            if *current == state {
+                return;
+            }
+            *current = state;
+        }
+        if let Some(port) = &port {
+            *self.port.lock().unwrap() = port.clone();
+        }
+        let event = SensorEvent {
+            sensor: self.config.id.clone(),
+            timestamp_ms: now_ms(),
+            state,
+            port,
+            error,
+            retry_in_ms: retry_in.map(|delay| delay.as_millis() as u64),
+        };
+        log_state(&event.sensor, state, event.error.as_deref(), retry_in);
+        // No receiver is not an error.
+        let _ = self.events.send(event);
+    }
+
+    fn set_connected(&self) {
+        if self.was_connected.swap(true, Ordering::Relaxed) {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        let port = self.port.lock().unwrap().clone();
+        self.set_state(ConnectionState::Connected, Some(port), None, None);
+    }
+}
+
+pub struct SseClient(Arc<Sensor>);
+
+impl Drop for SseClient {
+    fn drop(&mut self) {
+        self.0.sse_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Hub holds the configured sensors, in the order of the configuration.
+#[derive(Clone)]
+pub struct Hub {
+    sensors: Arc<Vec<Arc<Sensor>>>,
+    events: broadcast::Sender<SensorEvent>,
+}
+
+impl Hub {
+    // new() Returns the hub of the configured sensors, without starting their tasks.
+    fn new(configs: &[SensorConfig]) -> Self {
+        let (events, _) = broadcast::channel(EVENT_QUEUE_LEN);
+        let sensors = configs
+            .iter()
+            .map(|config| Arc::new(Sensor::new(config.clone(), events.clone())))
+            .collect();
+        Self {
+            sensors: Arc::new(sensors),
+            events,
+        }
+    }
+
+    // start() Spawns the task of each sensor. Must be called from a tokio runtime.
+    pub fn start(configs: &[SensorConfig]) -> Self {
+        let hub = Self::new(configs);
+        let sensors = hub.sensors();
+        let source = |id: &str| sensors.iter().find(|sensor| sensor.config.id == id).cloned();
+        for sensor in sensors {
+            match sensor.config.kind {
+                SensorKind::Presence => {
+                    let presence = sensor.config.presence.as_ref();
+                    match presence.map(|config| (source(&config.radar), source(&config.lidar))) {
+                        Some((Some(radar), Some(lidar))) => {
+                            tokio::spawn(run_presence(sensor.clone(), radar, lidar));
+                        }
+                        _ => println!("{}: unknown radar or lidar", sensor.config.id),
+                    }
+                }
+                SensorKind::Room => {
+                    let room = sensor.config.room.as_ref();
+                    match room.and_then(|config| config.radars.iter().map(|pose| source(&pose.id)).collect()) {
+                        Some(radars) => {
+                            tokio::spawn(run_room(sensor.clone(), radars));
+                        }
+                        None => println!("{}: unknown radars", sensor.config.id),
+                    }
+                }
+                _ => {
+                    tokio::spawn(run(sensor.clone()));
+                }
+            }
+        }
+        hub
+    }
+
+    // events() Returns a receiver of the changes of connection state of all the sensors.
+    pub fn events(&self) -> broadcast::Receiver<SensorEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn sensors(&self) -> &[Arc<Sensor>] {
+        &self.sensors
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Arc<Sensor>> {
+        self.sensors.iter().find(|sensor| sensor.config.id == id)
+    }
+
+    // first() Returns the first sensor of the given kind, used by the dashboards.
+    pub fn first(&self, kind: SensorKind) -> Option<&Arc<Sensor>> {
+        self.sensors.iter().find(|sensor| sensor.config.kind == kind)
+    }
+}
+
+#[cfg(test)]
+impl Hub {
+    // without_tasks() Returns the hub of the configured sensors without starting their tasks: the tests publish
+    // the readings and answer the commands themselves.
+    pub(crate) fn without_tasks(configs: &[SensorConfig]) -> Self {
+        Self::new(configs)
+    }
+}
+
+#[cfg(test)]
+impl Sensor {
+    // fake_reading() Publishes a reading as if it was received from the sensor.
+    pub(crate) fn fake_reading(&self, data: Reading) {
+        self.publish(data);
+    }
+
+    // fake_session() Connects the sensor and answers its commands with `answer`, like the session of the
+    // sensor task.
+    pub(crate) fn fake_session(
+        self: &Arc<Self>,
+        answer: impl Fn(Command) -> anyhow::Result<Value> + Send + 'static,
+    ) {
+        self.set_connected();
+        let sensor = self.clone();
+        tokio::spawn(async move {
+            let mut requests = sensor.requests.lock().await;
+            while let Some(request) = requests.recv().await {
+                let _ = request.reply.send(answer(request.command));
+            }
+        });
+    }
+}
+
+// Backoff is the delay before reopening a sensor. It doubles after each failed attempt up to
+// RECONNECT_DELAY_MAX, and is reset by a stable session or when the device is plugged back in.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self {
+            delay: RECONNECT_DELAY_MIN,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.delay = RECONNECT_DELAY_MIN;
+    }
+
+    // session_ended() Resets the delay if the session stayed connected for STABLE_SESSION, `connected_for` being
+    // None if it never connected.
+    fn session_ended(&mut self, connected_for: Option<Duration>) {
+        if connected_for.is_some_and(|duration| duration >= STABLE_SESSION) {
+            self.reset();
+        }
+    }
+
+    // next() Returns the delay before the next attempt, and doubles it for the following one.
+    fn next(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+        delay
+    }
+}
+
+// run() Keeps the sensor connected: each session runs in its own task so that a panic in a driver
+// is reported as an error and the sensor is reopened. The delay before reopening it doubles after each
+// failed attempt, but an unplugged device is reopened as soon as it is plugged back in.
+async fn run(sensor: Arc<Sensor>) {
+    let mut backoff = Backoff::new();
+    loop {
+        let (port, replugged) = wait_for_device(&sensor).await;
+        if replugged {
+            backoff.reset();
+        }
+        sensor.set_state(ConnectionState::Connecting, Some(port.clone()), None, None);
+        let started = Instant::now();
+        let error = match tokio::spawn(session(sensor.clone(), port)).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{e:#}")),
+            Err(e) => Some(format!("sensor task panicked: {e}")),
+        };
+        if let Some(error) = &error {
+            sensor.error(error);
+        }
+        let error = error.unwrap_or_else(|| "session ended".to_string());
+        backoff.session_ended((sensor.state() == ConnectionState::Connected).then(|| started.elapsed()));
+        if device::find(&sensor.config).is_some() {
+            let delay = backoff.next();
+            sensor.set_state(ConnectionState::Disconnected, None, Some(error), Some(delay));
+            tokio::time::sleep(delay).await;
+        } else {
+            // Unplugged: wait_for_device() waits for it.
+            sensor.set_state(ConnectionState::Disconnected, None, Some(error), None);
+        }
+    }
+}
+
+// read_failed() Counts a failed read (timeout, invalid response) as an error without closing the port. Returns
+// the error to end the session if the port failed (I/O error, port closed) or after MAX_READ_FAILURES
+// consecutive failed reads, `failures` being reset by each successful read.
+fn read_failed(sensor: &Sensor, failures: &mut u32, error: anyhow::Error) -> anyhow::Result<()> {
+    if error.is::<std::io::Error>() {
+        return Err(error);
+    }
+    *failures += 1;
+    if *failures >= MAX_READ_FAILURES {
+        return Err(error.context(format!("{failures} failed reads in a row")));
+    }
+    sensor.error(&error);
+    Ok(())
+}
+
+// run_presence() Fuses the latest readings of the radar and of the lidar of a presence sensor on every new
+// reading of either. The presence sensor is connected while its radar is: without a recent radar report
+// nothing is published.
+async fn run_presence(sensor: Arc<Sensor>, radar: Arc<Sensor>, lidar: Arc<Sensor>) {
+    let Some(config) = sensor.config.presence.clone() else {
+        return;
+    };
+    let mut radar_readings = radar.subscribe();
+    let mut lidar_readings = lidar.subscribe();
+    let mut check = tokio::time::interval(DEVICE_CHECK_PERIOD);
+    loop {
+        tokio::select! {
+            Ok(()) = radar_readings.changed() => {}
+            Ok(()) = lidar_readings.changed() => {}
+            _ = check.tick() => {
+                match radar.state() {
+                    ConnectionState::Connected => sensor.set_state(ConnectionState::Connected, None, None, None),
+                    state => {
+                        let error = format!("radar {} {}", radar.config.id, state.name());
+                        sensor.set_state(ConnectionState::Disconnected, None, Some(error), None);
+                    }
+                }
+                continue;
+            }
+        }
+        let now = now_ms();
+        let fresh = |sample: Option<Sample>| sample.filter(|sample| now.saturating_sub(sample.timestamp_ms) <= config.max_age_ms);
+        let Some(Reading::Ld2410c(radar_data)) = fresh(radar.latest()).map(|sample| sample.data) else {
+            continue;
+        };
+        let lidar_data = fresh(lidar.latest()).and_then(|sample| presence::Lidar::from_reading(&sample.data));
+        sensor.publish(Reading::Presence(presence::fuse(&config, &radar_data.target_data, lidar_data)));
+    }
+}
+
+// run_room() Merges the latest targets of the RD03D of a room sensor (in the order of RoomConfig::radars) on
+// every new reading of one of them. The room sensor is connected while at least one of its radars is.
+async fn run_room(sensor: Arc<Sensor>, radars: Vec<Arc<Sensor>>) {
+    let Some(config) = sensor.config.room.clone() else {
+        return;
+    };
+    let mut readings: Vec<_> = radars.iter().map(|radar| radar.subscribe()).collect();
+    let mut tracker = room::Tracker::new();
+    let mut check = tokio::time::interval(DEVICE_CHECK_PERIOD);
+    loop {
+        let changed = futures::future::select_all(readings.iter_mut().map(|rx| Box::pin(rx.changed())));
+        tokio::select! {
+            _ = changed => {}
+            _ = check.tick() => {
+                let disconnected: Vec<String> = radars
+                    .iter()
+                    .filter(|radar| radar.state() != ConnectionState::Connected)
+                    .map(|radar| format!("radar {} {}", radar.config.id, radar.state().name()))
+                    .collect();
+                if disconnected.len() < radars.len() {
+                    sensor.set_state(ConnectionState::Connected, None, None, None);
+                } else {
+                    sensor.set_state(ConnectionState::Disconnected, None, Some(disconnected.join(", ")), None);
+                }
+                continue;
+            }
+        }
+        let now = now_ms();
+        let mut detections = Vec::new();
+        for (i, (radar, pose)) in radars.iter().zip(&config.radars).enumerate() {
+            let Some(sample) = radar.latest() else {
+                continue;
+            };
+            if now.saturating_sub(sample.timestamp_ms) > config.max_age_ms {
+                continue;
+            }
+            if let Reading::Rd03d(targets) = &sample.data {
+                detections.extend(room::detections(i, pose, targets));
+            }
+        }
+        let points = room::merge(&config, &detections);
+        sensor.publish(Reading::Room(tracker.update(&config, &points, now)));
+    }
+}
+
+// wait_for_device() Returns the port of the sensor, after waiting for its device to be plugged in, and
+// whether it had to wait.
+async fn wait_for_device(sensor: &Sensor) -> (String, bool) {
+    let mut waited = false;
+    loop {
+        if let Some(port) = device::find(&sensor.config) {
+            if waited {
+                tokio::time::sleep(DEVICE_SETTLE_DELAY).await;
+            }
+            return (port, waited);
+        }
+        sensor.set_state(ConnectionState::WaitingForDevice, None, None, None);
+        waited = true;
+        tokio::time::sleep(DEVICE_CHECK_PERIOD).await;
+    }
+}
+
+// unplugged() Completes when the device of `port` is no longer plugged in (or is found under another port).
+async fn unplugged(config: &SensorConfig, port: &str) {
+    loop {
+        tokio::time::sleep(DEVICE_CHECK_PERIOD).await;
+        if device::find(config).as_deref() != Some(port) {
+            return;
+        }
+    }
+}
+
+// run_ld2410c_command() Sends a configuration command to the LD2410C and decodes its ACK.
+async fn run_ld2410c_command(
+    sensor: &Sensor,
+    ld2410c: &mut ld2410c::Ld2410C,
+    command: Ld2410cCommand,
+) -> anyhow::Result<Value> {
+    let ack = |response: Vec<u8>| json!({ "ack": response });
+    Ok(match command {
+        Ld2410cCommand::ReadFirmwareVersion => {
+            let version = ld2410c::FirmwareVersion::from_response(&ld2410c.read_firmware_version().await?)?;
+            json!({ "version": version.to_string(), "firmware": version })
+        }
+        Ld2410cCommand::GetMacAddress => {
+            let mac_address = ld2410c::MacAddress::from_response(&ld2410c.get_mac_adress().await?)?;
+            json!({ "mac_address": mac_address.to_string() })
+        }
+        Ld2410cCommand::ReadParameters => {
+            json!(ld2410c::RadarParameters::from_response(&ld2410c.read_parameter().await?)?)
+        }
+        Ld2410cCommand::QueryDistanceResolution => {
+            let response = ld2410c.query_distance_resolution_setting().await?;
+            json!({ "resolution": ld2410c::DistanceResolution::from_response(&response)? })
+        }
+        Ld2410cCommand::QueryAuxiliaryControl => json!(ld2410c.query_auxiliary_control().await?),
+        Ld2410cCommand::SetEngineeringMode(enabled) => {
+            let response = if enabled {
+                ld2410c.set_engineering_mode().await?
+            } else {
+                ld2410c.set_engineering_mode_off().await?
+            };
+            sensor.engineering_mode.store(enabled, Ordering::Relaxed);
+            ack(response)
+        }
+        Ld2410cCommand::SetDistanceResolution(resolution) => {
+            ack(ld2410c.set_distance_resolution_setting(resolution).await?)
+        }
+        Ld2410cCommand::SetGateSensitivity {
+            gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        } => ack(
+            ld2410c
+                .set_distance_gate_sensitivity_configuration(gate, motion_sensitivity, standstill_sensitivity)
+                .await?,
+        ),
+        Ld2410cCommand::SetBaudRate(baud_rate) => {
+            // Restarts the radar and reopens the port at the new rate; the rate is kept for the next connections.
+            let result = ld2410c.change_baud_rate(baud_rate).await;
+            sensor.set_setting("baud_rate", json!(ld2410c.baud_rate()));
+            result?;
+            json!({ "baud_rate": ld2410c.baud_rate() })
+        }
+        Ld2410cCommand::SetBluetooth(enabled) => {
+            let module = if enabled {
+                ld2410c::BluetoothModule::TurnOn
+            } else {
+                ld2410c::BluetoothModule::TurnOff
+            };
+            ack(ld2410c.set_bluetooth_module(module).await?)
+        }
+        Ld2410cCommand::SetBluetoothPassword(password) => ack(ld2410c.set_bluetooth_password(password).await?),
+        Ld2410cCommand::SetAuxiliaryControl(auxiliary_control) => {
+            ack(ld2410c.set_auxiliary_control(auxiliary_control).await?)
+        }
+        Ld2410cCommand::Restart => ack(ld2410c.set_restart_module().await?),
+        Ld2410cCommand::FactoryReset => ack(ld2410c.restore_factory_settings().await?),
+    })
+}
+
+// run_tf_luna_command() Sends a setting to the TF-Luna and waits for its response, the applied value is
+// returned.
+async fn run_tf_luna_command(
+    sensor: &Sensor,
+    tf_luna: &mut tf_luna::TfLuna,
+    command: TflunaCommand,
+) -> anyhow::Result<Value> {
+    let (name, value) = match command {
+        TflunaCommand::SetOutputFrequency(frequency) => {
+            tf_luna.set_output_frequency(frequency).await?;
+            ("frequency_hz", json!(frequency as u16))
+        }
+        TflunaCommand::SetDistanceLimit { dist_min, dist_max } => {
+            tf_luna.set_distance_limit_setting(dist_min, dist_max).await?;
+            ("distance_limit", json!({ "dist_min": dist_min, "dist_max": dist_max }))
+        }
+        TflunaCommand::SetOutputFormat(format) => {
+            // The sensor could not be read any more.
+            if !format.is_decoded() {
+                anyhow::bail!("output format {format:?} is not decoded by the driver");
+            }
+            tf_luna.set_output_format_setting(format).await?;
+            ("output_format", json!(format))
+        }
+        TflunaCommand::SetBaudRate(baud_rate) => {
+            // Reopens the port at the new rate, or at the previous one if the TF-Luna does not switch.
+            let result = tf_luna.change_baud_rate(baud_rate).await;
+            sensor.set_setting("baud_rate", json!(tf_luna.baud_rate()));
+            result?;
+            ("baud_rate", json!(baud_rate))
+        }
+    };
+    sensor.set_setting(name, value.clone());
+    Ok(json!({ name: value }))
+}
+
+// run_rd03d_command() Switches the RD03D tracking mode or sets its working mode.
+async fn run_rd03d_command(sensor: &Sensor, rd03d: &mut rd03d::RD03D, command: Rd03dCommand) -> anyhow::Result<Value> {
+    let (name, value) = match command {
+        Rd03dCommand::SetMultiMode(multi_mode) => {
+            rd03d.set_multi_mode(multi_mode).await?;
+            ("multi_mode", json!(multi_mode))
+        }
+        Rd03dCommand::SetMode(mode) => {
+            rd03d.open_command_mode().await?;
+            rd03d.set_mode(mode).await?;
+            rd03d.close_command_mode().await?;
+            // The command responses are not target frames
+            rd03d.buffer.clear();
+            ("mode", json!(mode))
+        }
+    };
+    sensor.set_setting(name, value.clone());
+    Ok(json!({ name: value }))
+}
+
+// reapply_tf_luna_settings() Applies again the settings changed through the API, which the TF-Luna loses
+// when it is powered off (e.g. its USB adapter unplugged). The port is opened at the configured baud rate,
+// the one of the TF-Luna after a power cycle; the baud rate changed through the API is set again first (if
+// the TF-Luna kept it, the command is lost but the port is reopened at the right rate).
+async fn reapply_tf_luna_settings(sensor: &Sensor, tf_luna: &mut tf_luna::TfLuna) -> anyhow::Result<()> {
+    let baud_rate = sensor.setting("baud_rate").and_then(|value| value.as_u64());
+    if let Some(baud_rate) = baud_rate.map(|baud_rate| baud_rate as u32) {
+        if baud_rate != tf_luna.baud_rate() {
+            tf_luna.change_baud_rate(baud_rate).await?;
+        }
+    }
+    let hz = sensor.setting("frequency_hz").and_then(|value| value.as_u64());
+    if let Some(frequency) = hz.and_then(|hz| tf_luna::OutputFrequency::from_hz(hz as u16)) {
+        tf_luna.set_output_frequency(frequency).await?;
+    }
+    if let Some(limit) = sensor.setting("distance_limit") {
+        let limit = |name: &str| limit[name].as_u64().map(|value| value as u16);
+        if let (Some(dist_min), Some(dist_max)) = (limit("dist_min"), limit("dist_max")) {
+            tf_luna.set_distance_limit_setting(dist_min, dist_max).await?;
+        }
+    }
+    if let Some(format) = sensor.setting("output_format") {
+        if let Ok(format) = serde_json::from_value::<tf_luna::OutputFormat>(format) {
+            if format.is_decoded() {
+                tf_luna.set_output_format_setting(format).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// session() Opens the sensor on `port`, applies its modes and reads it until an I/O error occurs, the
+// device is unplugged or MAX_READ_FAILURES reads in a row fail. Decoding errors are only counted.
+async fn session(sensor: Arc<Sensor>, port: String) -> anyhow::Result<()> {
+    let config = &sensor.config;
+    let unplugged = unplugged(config, &port);
+    tokio::pin!(unplugged);
+    match config.kind {
+        SensorKind::Ld2410c => {
+            let mut ld2410c = ld2410c::Ld2410C::new(port.clone());
+            let baud_rate = sensor.setting("baud_rate").and_then(|value| value.as_u64());
+            if let Some(baud_rate) = baud_rate.map(|baud_rate| baud_rate as u32).or(config.baud_rate) {
+                ld2410c.set_baud_rate(baud_rate);
+            }
+            if let Some(timeout) = config.timeout() {
+                ld2410c.set_timeout(timeout);
+            }
+            if let Some(retries) = config.retries {
+                ld2410c.set_retries(retries);
+            }
+            ld2410c.connect().await?;
+            // The baud rate of the radar may have been changed by another program.
+            if ld2410c.check_communication().await.is_err() {
+                let baud_rate = ld2410c.detect_baud_rate().await?;
+                println!("{}: LD2410C detected at {baud_rate} baud", config.id);
+                sensor.set_setting("baud_rate", json!(baud_rate));
+            }
+            sensor.set_connected();
+            if sensor.engineering_mode.load(Ordering::Relaxed) {
+                ld2410c.set_engineering_mode().await?;
+            }
+            let mut requests = sensor.requests.lock().await;
+            let mut failures = 0;
+            loop {
+                tokio::select! {
+                    _ = &mut unplugged => anyhow::bail!("device unplugged"),
+                    data = ld2410c.read_data() => match data {
+                        Ok(data) => {
+                            failures = 0;
+                            sensor.publish(Reading::Ld2410c(data));
+                        }
+                        Err(e) if e.is::<ld2410c::DecodeError>() => sensor.decode_error(e),
+                        Err(e) => read_failed(&sensor, &mut failures, e)?,
+                    },
+                    Some(request) = requests.recv() => {
+                        let result = match request.command {
+                            Command::Ld2410c(command) => run_ld2410c_command(&sensor, &mut ld2410c, command).await,
+                            other => Err(anyhow::anyhow!("unsupported command {other:?}")),
+                        };
+                        let _ = request.reply.send(result);
+                    }
+                }
+            }
+        }
+        SensorKind::Rd03d => {
+            let mut rd03d = rd03d::RD03D::new(port.clone());
+            if let Some(baud_rate) = config.baud_rate {
+                rd03d.set_baud_rate(baud_rate);
+            }
+            if let Some(timeout) = config.timeout() {
+                rd03d.timeout = timeout;
+            }
+            if let Some(retries) = config.retries {
+                rd03d.retries = retries;
+            }
+            // Applied by connect().
+            rd03d.multi_mode = sensor
+                .setting("multi_mode")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(config.multi_mode);
+            rd03d.connect().await?;
+            sensor.set_connected();
+            let mut requests = sensor.requests.lock().await;
+            let mut failures = 0;
+            loop {
+                tokio::select! {
+                    _ = &mut unplugged => anyhow::bail!("device unplugged"),
+                    updated = rd03d.update() => match updated {
+                        Ok(true) => {
+                            failures = 0;
+                            sensor.publish(Reading::Rd03d(rd03d.targets.clone()));
+                        }
+                        Ok(false) => {}
+                        Err(e) => read_failed(&sensor, &mut failures, e)?,
+                    },
+                    Some(request) = requests.recv() => {
+                        let result = match request.command {
+                            Command::Rd03d(command) => run_rd03d_command(&sensor, &mut rd03d, command).await,
+                            other => Err(anyhow::anyhow!("unsupported command {other:?}")),
+                        };
+                        let _ = request.reply.send(result);
+                    }
+                }
+            }
+        }
+        SensorKind::Tfluna => {
+            let mut tf_luna = tf_luna::TfLuna::new(port.clone());
+            if let Some(baud_rate) = config.baud_rate {
+                tf_luna.set_baud_rate(baud_rate);
+            }
+            if let Some(timeout) = config.timeout() {
+                tf_luna.set_timeout(timeout);
+            }
+            if let Some(retries) = config.retries {
+                tf_luna.set_retries(retries);
+            }
+            tf_luna.connect().await?;
+            reapply_tf_luna_settings(&sensor, &mut tf_luna).await?;
+            sensor.set_connected();
+            let mut requests = sensor.requests.lock().await;
+            let mut failures = 0;
+            loop {
+                tokio::select! {
+                    _ = &mut unplugged => anyhow::bail!("device unplugged"),
+                    data = tf_luna.read_data() => match data {
+                        Ok(data) => {
+                            failures = 0;
+                            sensor.publish(Reading::Tfluna(data));
+                        }
+                        Err(e) => read_failed(&sensor, &mut failures, e)?,
+                    },
+                    Some(request) = requests.recv() => {
+                        let result = match request.command {
+                            Command::Tfluna(command) => run_tf_luna_command(&sensor, &mut tf_luna, command).await,
+                            other => Err(anyhow::anyhow!("unsupported command {other:?}")),
+                        };
+                        let _ = request.reply.send(result);
+                    }
+                }
+            }
+        }
+        SensorKind::Tof200f => {
+            let mut tof200f = tof200f::TOF200F::new(port.clone());
+            if let Some(baud_rate) = config.baud_rate {
+                tof200f.set_baud_rate(baud_rate);
+            }
+            if let Some(timeout) = config.timeout() {
+                tof200f.set_timeout(timeout);
+            }
+            tof200f.connect().await?;
+            sensor.set_connected();
+            let mut failures = 0;
+            loop {
+                tokio::select! {
+                    _ = &mut unplugged => anyhow::bail!("device unplugged"),
+                    data = tof200f.read_data() => match data {
+                        Ok(data) => {
+                            failures = 0;
+                            sensor.publish(Reading::Tof200f(data));
+                        }
+                        Err(e) => read_failed(&sensor, &mut failures, e)?,
+                    },
+                }
+            }
+        }
+        SensorKind::Presence | SensorKind::Room => anyhow::bail!("{} is a virtual sensor", config.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_commands_have_a_longer_timeout() {
+        for command in [
+            Command::Ld2410c(Ld2410cCommand::SetBaudRate(ld2410c::BaudRate::BaudRate115200)),
+            Command::Ld2410c(Ld2410cCommand::Restart),
+            Command::Ld2410c(Ld2410cCommand::FactoryReset),
+            Command::Tfluna(TflunaCommand::SetBaudRate(115200)),
+        ] {
+            assert_eq!(command.timeout(), SLOW_COMMAND_TIMEOUT, "{command:?}");
+        }
+        for command in [
+            Command::Ld2410c(Ld2410cCommand::ReadFirmwareVersion),
+            Command::Tfluna(TflunaCommand::SetDistanceLimit { dist_min: 20, dist_max: 800 }),
+            Command::Rd03d(Rd03dCommand::SetMultiMode(true)),
+        ] {
+            assert_eq!(command.timeout(), COMMAND_TIMEOUT, "{command:?}");
+        }
+    }
+
+    // sensor() Returns a TF-Luna sensor whose task is not started.
+    fn sensor() -> Arc<Sensor> {
+        let config: crate::config::Config = toml::from_str("[[sensors]]\nid = \"lidar\"\nkind = \"tfluna\"\n").unwrap();
+        Hub::without_tasks(&config.sensors).sensors()[0].clone()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[test]
+    fn backoff_is_reset_by_a_stable_session_only() {
+        let mut backoff = Backoff::new();
+        backoff.next();
+        backoff.next();
+        backoff.session_ended(None);
+        assert_eq!(backoff.next(), Duration::from_secs(4));
+        backoff.session_ended(Some(STABLE_SESSION - Duration::from_secs(1)));
+        assert_eq!(backoff.next(), Duration::from_secs(8));
+        backoff.session_ended(Some(STABLE_SESSION));
+        assert_eq!(backoff.next(), RECONNECT_DELAY_MIN);
+        backoff.next();
+        backoff.reset();
+        assert_eq!(backoff.next(), RECONNECT_DELAY_MIN);
+    }
+
+    #[test]
+    fn failed_reads_are_counted_until_the_maximum() {
+        let sensor = sensor();
+        let mut failures = 0;
+        for _ in 1..MAX_READ_FAILURES {
+            read_failed(&sensor, &mut failures, anyhow::anyhow!("read timed out")).unwrap();
+        }
+        assert_eq!(sensor.status().errors, u64::from(MAX_READ_FAILURES - 1));
+        assert_eq!(sensor.status().last_error.as_deref(), Some("read timed out"));
+        let error = read_failed(&sensor, &mut failures, anyhow::anyhow!("read timed out")).unwrap_err();
+        assert_eq!(error.to_string(), "3 failed reads in a row");
+        assert_eq!(sensor.status().errors, u64::from(MAX_READ_FAILURES - 1));
+    }
+
+    #[test]
+    fn io_errors_end_the_session() {
+        let sensor = sensor();
+        let mut failures = 0;
+        let error = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed");
+        assert!(read_failed(&sensor, &mut failures, error.into()).is_err());
+        assert_eq!(failures, 0);
+        assert_eq!(sensor.status().errors, 0);
+    }
+
+    #[test]
+    fn only_reopened_ports_are_reconnections() {
+        let sensor = sensor();
+        sensor.set_connected();
+        assert_eq!(sensor.status().reconnects, 0);
+        sensor.set_connected();
+        sensor.set_connected();
+        assert_eq!(sensor.status().reconnects, 2);
+    }
+}
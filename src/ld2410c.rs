@@ -2,303 +2,102 @@
 // This file contains the implementation of the Ld2410C class, which is used to communicate with the LD2410C radar module.
 // It includes methods for connecting to the module, reading data, and sending commands to configure the module's settings.
 // Fayel MOHAMED
-use serde::Serialize;
-use std::vec;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+pub use crate::protocol::ld2410c::*;
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+use crate::protocol::{find_ack_frame, find_frame, FrameSearch};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "tokio")]
+use tokio_serial::SerialPortBuilderExt;
 
-pub struct Ld2410C {
-    path: String,
-    baud_rate: u32,
-    stream: Option<SerialStream>,
+// is_missing_ack() Returns whether a command failed because its ACK was not received in time, in which case
+// the command can be sent again.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub(crate) fn is_missing_ack(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<DecodeError>(), Some(DecodeError::MissingAck(_)))
 }
-#[derive(Debug, Clone, PartialEq, Serialize)]
-pub enum DataType {
-    EngineeringMode = 0x01,
-    TargetBasicInformation = 0x02,
-    NoDataType,
-}
-impl DataType {
-    fn find_type(data: &[u8]) -> Self {
-        match data[0] {
-            0x01 => Self::EngineeringMode,
-            0x02 => Self::TargetBasicInformation,
-            _ => Self::NoDataType, // Default to EngineeringMode if unknown
+
+// next_report() Takes the first complete report frame out of the received bytes and decodes it. The bytes
+// before it (e.g. ACK frames) are dropped. Returns None if no complete report frame was received yet.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub(crate) fn next_report(buffer: &mut Vec<u8>) -> Option<Result<Ld2410CData, DecodeError>> {
+    match find_frame(buffer, REPORT_FRAME_HEADER) {
+        FrameSearch::Complete { start, end } => {
+            let data = Ld2410CData::from_frame(&buffer[start..end]).unwrap_or(Err(DecodeError::InvalidFrameEnd));
+            buffer.drain(..end);
+            Some(data)
         }
-    }
-}
-#[derive(Debug, Clone, Serialize)]
-enum TargetStatus {
-    NoTarget,
-    CampaignTarget,
-    StationnaryTarget,
-    CampaignAndStationaryTarget,
-}
-impl TargetStatus {
-    fn find_status(data: &[u8]) -> Self {
-        match data[2] {
-            0x00 => Self::NoTarget,
-            0x01 => Self::CampaignTarget,
-            0x02 => Self::StationnaryTarget,
-            0x03 => Self::CampaignAndStationaryTarget,
-            _ => Self::NoTarget, // Default to NoTarget if unknown
+        FrameSearch::Incomplete { start } => {
+            buffer.drain(..start);
+            None
         }
-    }
-}
-#[derive(Debug, Clone, Serialize)]
-#[allow(dead_code)]
-struct EngineeringModel {
-    maximum_mov_distance_gate: u8,
-    maximum_static_distance_gate: u8,
-    mouvement_distance_gates: Vec<u8>,
-    static_distance_gates: Vec<u8>,
-    retain_data: Vec<u8>,
-}
-
-impl EngineeringModel {
-    fn new(data: &[u8]) -> Self {
-        Self {
-            maximum_mov_distance_gate: data[11],
-            maximum_static_distance_gate: data[12],
-            mouvement_distance_gates: data[13..data[11] as usize + 14].to_vec(),
-            static_distance_gates: data[data[11] as usize + 14..(data[11] as usize * 2) + 15]
-                .to_vec(),
-            retain_data: data[(data[11] as usize * 2) + 15..data.len() - 2].to_vec(),
+        FrameSearch::NotFound => {
+            // Keep the last bytes, they may be the beginning of a header.
+            buffer.drain(..buffer.len().saturating_sub(3));
+            None
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-#[allow(dead_code)]
-struct TargetData {
-    target_status: TargetStatus,
-    movement_target_distance: u16,
-    exercise_target: u8,
-    stationary_target_distance: u16,
-    stationary_target: u8,
-    detection_distance: u16,
-    engineering_model: Option<EngineeringModel>,
-}
-impl TargetData {
-    fn new(target_status: TargetStatus, data: &[u8]) -> Self {
-        Self {
-            target_status,
-            movement_target_distance: u16::from_be_bytes([data[4], data[3]]),
-            exercise_target: data[5],
-            stationary_target_distance: u16::from_be_bytes([data[7], data[6]]),
-            stationary_target: data[8],
-            detection_distance: u16::from_be_bytes([data[10], data[9]]),
-            engineering_model: if data[0] == 0x01 {
-                Some(EngineeringModel::new(data))
-            } else {
-                None
-            },
+// next_ack() Takes the ACK of the command `word` out of the received bytes. The report frames and the ACKs
+// of other commands before it are dropped. Returns None if it was not received yet, an error if the radar
+// reported a failure.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub(crate) fn next_ack(buffer: &mut Vec<u8>, word: u16) -> Option<Result<Vec<u8>, DecodeError>> {
+    match find_ack_frame(buffer, COMMAND_FRAME_HEADER, word) {
+        FrameSearch::Complete { start, end } => {
+            let ack = find_ack(&buffer[start..end], word).map(|ack| ack.to_vec());
+            buffer.drain(..end);
+            Some(ack)
         }
-    }
-}
-#[derive(Debug, Clone, Serialize)]
-#[allow(dead_code)]
-pub struct Ld2410CData {
-    data_type: DataType,
-    head: u8,
-    target_data: TargetData,
-    tail: u8,
-    calibration: u8,
-}
-
-impl Ld2410CData {
-    fn new(data_type: DataType, target_data: TargetData, data: &[u8]) -> Self {
-        if data_type == DataType::TargetBasicInformation {
-            return Self {
-                data_type,
-                head: data[1],
-                target_data,
-                tail: data[11],
-                calibration: data[12],
-            };
-        } else {
-            return Self {
-                data_type,
-                head: data[1],
-                target_data,
-                tail: data[33],
-                calibration: data[34],
-            };
+        FrameSearch::Incomplete { start } => {
+            buffer.drain(..start);
+            None
         }
-    }
-    fn default() -> Self {
-        Self {
-            data_type: DataType::NoDataType,
-            head: 0x00,
-            target_data: TargetData {
-                target_status: TargetStatus::NoTarget,
-                movement_target_distance: 0,
-                exercise_target: 0,
-                stationary_target_distance: 0,
-                stationary_target: 0,
-                detection_distance: 0,
-                engineering_model: None,
-            },
-            tail: 0x00,
-            calibration: 0x00,
+        FrameSearch::NotFound => {
+            buffer.drain(..buffer.len().saturating_sub(3));
+            None
         }
     }
 }
 
-struct Ld2410CFrame {
-    frame_header: Vec<u8>,
-    intraframe_length: Vec<u8>,
-    intraframe_data: Ld2410CCommand,
-    end_frame: Vec<u8>,
-}
+// Port is the serial port of the async driver: a SerialStream, or an in-memory stream in the tests.
+#[cfg(feature = "tokio")]
+pub(crate) trait Port: AsyncRead + AsyncWrite + Unpin + Send {}
+#[cfg(feature = "tokio")]
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Port for T {}
 
-impl Ld2410CFrame {
-    fn new(data_length: Vec<u8>, command: Ld2410CCommand) -> Self {
-        Self {
-            frame_header: vec![0xFD, 0xFC, 0xFB, 0xFA],
-            intraframe_length: data_length,
-            intraframe_data: command,
-            end_frame: vec![0x04, 0x03, 0x02, 0x01],
-        }
-    }
-    fn to_u8(&self) -> Vec<u8> {
-        [
-            &self.frame_header[..],
-            &self.intraframe_length[..],
-            &self.intraframe_data.word[..],
-            &self.intraframe_data.value[..],
-            &self.end_frame[..],
-        ]
-        .concat()
-    }
-}
-struct Ld2410CCommand {
-    word: Vec<u8>,
-    value: Vec<u8>,
-}
-impl Ld2410CCommand {
-    fn new(word: Vec<u8>, value: Vec<u8>) -> Self {
-        Self { word, value }
-    }
-}
+// Opens the serial port `path` at a baud rate.
+#[cfg(feature = "tokio")]
+type Open = Box<dyn Fn(&str, u32) -> anyhow::Result<Box<dyn Port>> + Send + Sync>;
 
-pub enum GateValue{
-    GateValue0,
-    GateValue1,
-    GateValue2,
-    GateValue3,
-    GateValue4,
-    GateValue5,
-    GateValue6,
-    GateValue7,
-    GateValue8,
-    GateValueAll,
-}
-
-impl GateValue {
-    fn to_vec(&self) -> Vec<u8> {
-        match self {
-            GateValue::GateValue0 => vec![0x00, 0x00,0x00,0x00],
-            GateValue::GateValue1 => vec![0x01, 0x00,0x00,0x00],
-            GateValue::GateValue2 => vec![0x02, 0x00,0x00,0x00],
-            GateValue::GateValue3 => vec![0x03, 0x00,0x00,0x00],
-            GateValue::GateValue4 => vec![0x04, 0x00,0x00,0x00],
-            GateValue::GateValue5 => vec![0x05, 0x00,0x00,0x00],
-            GateValue::GateValue6 => vec![0x06, 0x00,0x00,0x00],
-            GateValue::GateValue7 => vec![0x07, 0x00,0x00,0x00],
-            GateValue::GateValue8 => vec![0x08, 0x00,0x00,0x00],
-            GateValue::GateValueAll => vec![0xFF, 0xFF,0x00,0x00],
-        }
-    }
-}
-struct GateSensitivity {
-    distance_gate_word: Vec<u8>,
-    distance_gate_value: GateValue,
-    motion_sensitivity_word: Vec<u8>,
-    motion_sensitivity_value: Vec<u8>,
-    standstill_sensitivity_word: Vec<u8>,
-    standstill_sensitivity_value: Vec<u8>,
-}
-impl GateSensitivity {
-    fn new(
-        distance_gate_value: GateValue,
-        motion_sensitivity_value: u8,
-        standstill_sensitivity_value: u8,
-    ) -> Self {
-        Self {
-            distance_gate_word: vec![0x00, 0x00],
-            distance_gate_value,
-            motion_sensitivity_word:vec![0x01, 0x00],
-            motion_sensitivity_value: vec![motion_sensitivity_value as u8, 0x00, 0x00, 0x00],
-            standstill_sensitivity_word: vec![0x02, 0x00],
-            standstill_sensitivity_value: vec![standstill_sensitivity_value as u8, 0x00, 0x00, 0x00],
-        }
-    }
-    fn to_vec(&self) -> Vec<u8> {
-        [
-            &self.distance_gate_word[..],
-            &self.distance_gate_value.to_vec()[..],
-            &self.motion_sensitivity_word[..],
-            &self.motion_sensitivity_value[..],
-            &self.standstill_sensitivity_word[..],
-            &self.standstill_sensitivity_value[..],
-        ]
-        .concat()
-    }
-    
-}
-
-pub enum BaudRate {
-    BaudRate115200,
-    BaudRate230400,
-    BaudRate256000,
-    BaudRate460800,
-    BaudRate57600,
-    BaudRate38400,
-    BaudRate19200,
-    BaudRate9600,
-}
-impl BaudRate {
-    fn to_vec(&self) -> Vec<u8> {
-        match self {
-            BaudRate::BaudRate9600 => vec![0x01, 0x00],
-            BaudRate::BaudRate19200 => vec![0x02, 0x00],
-            BaudRate::BaudRate38400 => vec![0x03, 0x00],
-            BaudRate::BaudRate57600 => vec![0x04, 0x00],
-            BaudRate::BaudRate115200 => vec![0x05, 0x00],
-            BaudRate::BaudRate230400 => vec![0x06, 0x00],
-            BaudRate::BaudRate256000 => vec![0x07, 0x00],
-            BaudRate::BaudRate460800 => vec![0x08, 0x00],
-        }
-    }
-}
-pub enum DistanceResolution {
-    DistanceGate0_75m,
-    DistanceGate0_2m,
-}
-impl DistanceResolution {
-    fn to_vec(&self) -> Vec<u8> {
-        match self {
-            DistanceResolution::DistanceGate0_75m => vec![0x00,0x00],
-            DistanceResolution::DistanceGate0_2m => vec![0x01,0x00],
-        }
-    }
-}
+// Default deadline of a read and of the ACK of a command. The radar reports about 10 times per second.
+#[cfg(feature = "tokio")]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+// Default number of times a command is sent again when its ACK is not received.
+#[cfg(feature = "tokio")]
+pub const DEFAULT_RETRIES: u8 = 2;
+// Maximum time to wait for the ACK of a command during the baud rate detection.
+#[cfg(feature = "tokio")]
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+// Time for the radar to restart, e.g. at its new baud rate.
+#[cfg(feature = "tokio")]
+const RESTART_DELAY: Duration = Duration::from_millis(1500);
 
-#[derive(Clone)]
-pub enum BluetoothModule {
-    TurnOn,
-    TurnOff,
-}
-impl BluetoothModule {
-    fn to_vec(&self) -> Vec<u8> {
-        match self {
-            BluetoothModule::TurnOn => vec![0x01, 0x00],
-            BluetoothModule::TurnOff => vec![0x00, 0x00],
-        }
-    }
+#[cfg(feature = "tokio")]
+pub struct Ld2410C {
+    path: String,
+    baud_rate: u32,
+    timeout: Duration,
+    retries: u8,
+    open: Open,
+    stream: Option<Box<dyn Port>>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
 }
-
+#[cfg(feature = "tokio")]
 impl Ld2410C {
     pub fn new(path: String) -> Self {
         Self {
@@ -306,140 +105,121 @@ impl Ld2410C {
             // Set defaut baud rate to 256000
             // This can be changed later using the `set_baud_rate` method
             baud_rate: 256000,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            open: Box::new(|path, baud_rate| Ok(Box::new(tokio_serial::new(path, baud_rate).open_native_async()?))),
             stream: None,
+            buffer: Vec::new(),
         }
     }
 
     pub fn set_baud_rate(&mut self, baud_rate: u32) {
         self.baud_rate = baud_rate;
     }
+
+    // set_timeout() Sets the deadline of a read and of the ACK of a command (DEFAULT_TIMEOUT by default).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // set_retries() Sets the number of times a command is sent again when its ACK is not received.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    // baud_rate() Returns the baud rate the port is opened with, e.g. after detect_baud_rate().
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
     // connect() This method opens the serial port connection to the LD2410C radar module.
     // It uses the tokio_serial crate to create a new serial port stream with the specified baud rate.
     pub async fn connect(&mut self) -> anyhow::Result<()> {
-        match tokio_serial::new(&self.path, self.baud_rate).open_native_async() {
-            Ok(stream) => {
-                self.stream = Some(stream);
-                Ok(())
-            }
-            Err(e) => Err(e.into()),
-        }
+        self.stream = Some((self.open)(&self.path, self.baud_rate)?);
+        self.buffer.clear();
+        Ok(())
     }
-    // read_data() This method reads data from the LD2410C radar module. It reads a buffer of 1024 bytes and checks the header and tail of the data.
-    // If the header is 0x0D or 0x23, it processes the data to extract the data type and target status.
+    // read_data() This method reads data from the LD2410C radar module. The received bytes are kept until a
+    // complete report frame is received, whose header, length, head, tail and end are checked before decoding
+    // it. ACK frames are discarded. Fails if no report frame was received before the timeout (set_timeout()).
     pub async fn read_data(&mut self) -> anyhow::Result<Ld2410CData> {
-        let mut buf = [0u8; 1024];
-        self.stream.as_mut().unwrap().read(&mut buf).await?;
-        if buf[4] == 0x0D || buf[4] == 0x23 {
-            let data = &buf[6..buf[4] as usize + 6];
-            let data_type = DataType::find_type(data);
-            let target_status = TargetStatus::find_status(data);
-            let target_data = TargetData::new(target_status, data);
-            let ld2410cdata = Ld2410CData::new(data_type, target_data, data);
-            Ok(ld2410cdata)
-        }
-        else {
-            Ok(Ld2410CData::default())
-        }
-    }
-    // response_configuration() This method reads the response configuration from the LD2410C radar module.
-    // It reads a buffer of 1024 bytes and returns the data as a vector of bytes.
-    async fn response_configuration(&mut self) -> anyhow::Result<Vec<u8>> {
-        let mut buf = [0u8; 1024];
-        match self.stream.as_mut().unwrap().read(&mut buf).await {
-            Ok(n) => {
-                Ok(buf[..n].to_vec())
-            }
-            Err(e) => {
-                Err(e.into())
+        let timeout = self.timeout;
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("LD2410C is not connected"))?;
+        let buffer = &mut self.buffer;
+        let read = async {
+            let mut buf = [0u8; 256];
+            loop {
+                if let Some(data) = next_report(buffer) {
+                    return Ok(data?);
+                }
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                buffer.extend_from_slice(&buf[..n]);
             }
+        };
+        match tokio::time::timeout(timeout, read).await {
+            Ok(data) => data,
+            Err(_) => anyhow::bail!("no report from the LD2410C within {timeout:?}"),
         }
     }
 
     // write_data() This method writes data to the LD2410C radar module. It takes a command as a byte slice and sends it to the module.
     // It returns the command as a vector of bytes.
     async fn write_data(&mut self, command: &[u8])-> anyhow::Result<Vec<u8>> {
-        match self.stream.as_mut().unwrap().write(command).await {
-            Ok(_) => {
-                Ok(command.to_vec())
-            }
-            Err(e) => {
-                Err(e.into())
-            }
-        }
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("LD2410C is not connected"))?;
+        stream.write_all(command).await?;
+        Ok(command.to_vec())
     }
 
     //set_enabling_configuration() Any other commands issued to the radar must be executed
     //after this command is issued, otherwise they are invalid.
     async fn set_enabling_configuration(&mut self)-> anyhow::Result<Vec<u8>> {
-        let command = Ld2410CCommand::new(vec![0xFF, 0x00], vec![0x01, 0x00]);
-        let data_length = vec![0x04, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        Ok( self.response_configuration().await?)
+        self.command_with_retries(Ld2410CFrame::enabling_configuration()).await
     }
 
     // set_ending_configuration() and the radar resumes working mode after execution.
     // If you need to issue other commands again, you need to send the enable configuration
     // command first
     async fn set_ending_configuration(&mut self)-> anyhow::Result<Vec<u8>> {
-        let command = Ld2410CCommand::new(vec![0xFE, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        Ok( self.response_configuration().await?)
+        self.command_with_retries(Ld2410CFrame::ending_configuration()).await
     }
 
-    // read_firmware_version() This command reads the radar firmware version information.
-    pub async fn read_firmware_version(&mut self)-> anyhow::Result<Vec<u8>> {
+    // send_command() Sends a command frame between the enable and end configuration commands
+    // and returns its ACK frame.
+    async fn send_command(&mut self, frame: Ld2410CFrame) -> anyhow::Result<Vec<u8>> {
         self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA0, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
+        let response = self.command_with_retries(frame).await?;
         self.set_ending_configuration().await?;
         Ok(response)
     }
 
+    // read_firmware_version() This command reads the radar firmware version information.
+    pub async fn read_firmware_version(&mut self)-> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::read_firmware_version()).await
+    }
+
     // set_bluetooth_module() This command sets the Bluetooth module to be turned on or off.
     pub async fn set_bluetooth_module(&mut self, module: BluetoothModule)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA4, 0x00], module.to_vec());
-        let data_length = vec![0x04, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::bluetooth_module(module)).await
     }
 
     // set_bluetooth_password() This command sets the Bluetooth password. The password is a 6-byte string,
     // which is used to connect to the radar module via Bluetooth. The default password is HiLink.
     pub async fn set_bluetooth_password(&mut self, password: String)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        if password.len() != 6 {
-            return Err(anyhow::anyhow!("Password must be exactly 6 bytes long"));
-        }
-        let command = Ld2410CCommand::new(vec![0xA9, 0x00], password.as_bytes().to_vec());
-        let data_length = vec![0x08, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::bluetooth_password(&password)?).await
     }
 
     // obtaining_bluetooth_permissions() This command obtains the Bluetooth permissions of the radar module.
     // The password is a 6-byte string, which is used to connect to the radar module via Bluetooth.
     pub async fn obtaining_bluetooth_permissions(&mut self,password: String)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA8, 0x00], password.as_bytes().to_vec());
-        let data_length = vec![0x08, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::obtaining_bluetooth_permissions(&password)?).await
     }
 
 
@@ -449,64 +229,29 @@ impl Ld2410C {
     // is off by default after the module is powered on, this configuration value is lost when
     // power is lost.
     pub async fn set_engineering_mode(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0x62, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::engineering_mode()).await
     }
 
     // set_engineering_mode_off() This command turns off the radar engineering mode.
     // After it is turned off, please refer
     // to 2.3.2 Target Data Composition for the format of radar report data.
     pub async fn set_engineering_mode_off(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0x63, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::engineering_mode_off()).await
     }
     // read_parameter() This command allows you to read the current configuration parameters of the radar.
     pub async fn read_parameter(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0x61, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::read_parameter()).await
     }
 
     // reset_module() This command resets the radar module. After the reset, the radar will automatically
     pub async fn set_restart_module(&mut self)-> anyhow::Result<Vec<u8>>{
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA3, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::restart_module()).await
     }
 
     // restore_factory_settings() This command restores all the configuration values to their non-factory
     // values, which take effect after rebooting the module.
     pub async fn restore_factory_settings(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA2, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::restore_factory_settings()).await
     }
 
     // set_distance_resolution_setting() This command sets the distance resolution of the module, that is how far away each distance gate
@@ -515,52 +260,118 @@ impl Ld2410C {
     // Can be configured to 0.75m or 0.2m per distance gate, the maximum number of
     // distance gates supported are 8.
     pub async fn set_distance_resolution_setting(&mut self, distance_resolution: DistanceResolution)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xAA, 0x00], distance_resolution.to_vec());
-        let data_length = vec![0x04, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::distance_resolution_setting(distance_resolution)).await
     }
 
     // query_distance_resolutiion_setting() This command queries the module's current distance resolution setting, i.e. how far away each distance
     // gate represents.
     pub async fn query_distance_resolution_setting(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xAB, 0x00], vec![]);
-        let data_length = vec![0x02, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::query_distance_resolution_setting()).await
     }
 
     // get_mac_adress() This command reads the MAC address of the radar module.
     pub async fn get_mac_adress(&mut self)-> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA5, 0x00], vec![0x01, 0x00]);
-        let data_length = vec![0x04, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
+        self.send_command(Ld2410CFrame::mac_address()).await
+    }
+
+    // read_ack() Reads until the ACK of the command `word` is received and returns the ACK frame. The report
+    // frames and the ACKs of other commands received in between are discarded. Fails with
+    // DecodeError::MissingAck after `timeout`, or if the radar reports a failure.
+    async fn read_ack(&mut self, word: u16, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("LD2410C is not connected"))?;
+        let buffer = &mut self.buffer;
+        let read = async {
+            let mut buf = [0u8; 256];
+            loop {
+                if let Some(ack) = next_ack(buffer, word) {
+                    return Ok(ack?);
+                }
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                buffer.extend_from_slice(&buf[..n]);
+            }
+        };
+        tokio::time::timeout(timeout, read)
+            .await
+            .map_err(|_| DecodeError::MissingAck(word))?
+    }
+
+    // command_with_ack() Sends a command frame and waits at most `timeout` for its ACK.
+    async fn command_with_ack(&mut self, frame: &Ld2410CFrame, timeout: Duration) -> anyhow::Result<Vec<u8>> {
         self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.read_ack(frame.word(), timeout).await
+    }
+
+    // command_with_retries() Sends a command frame and waits for its ACK, sending it again up to `retries`
+    // times when the ACK is not received before the timeout.
+    async fn command_with_retries(&mut self, frame: Ld2410CFrame) -> anyhow::Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            match self.command_with_ack(&frame, self.timeout).await {
+                Err(e) if attempt < self.retries && is_missing_ack(&e) => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    // check_communication() Checks that the radar answers at the current baud rate: sends the enable then
+    // end configuration commands and waits for their ACKs.
+    pub async fn check_communication(&mut self) -> anyhow::Result<()> {
+        self.command_with_ack(&Ld2410CFrame::enabling_configuration(), ACK_TIMEOUT).await?;
+        self.command_with_ack(&Ld2410CFrame::ending_configuration(), ACK_TIMEOUT).await?;
+        Ok(())
+    }
+
+    // detect_baud_rate() Opens the port at each baud rate of the radar (BaudRate::ALL, the factory default
+    // first) until the radar acknowledges the enable configuration command. The port stays open at the
+    // detected baud rate, which is returned.
+    pub async fn detect_baud_rate(&mut self) -> anyhow::Result<u32> {
+        for baud_rate in BaudRate::ALL {
+            self.stream = None;
+            self.baud_rate = baud_rate.to_baud();
+            self.connect().await?;
+            if self.check_communication().await.is_ok() {
+                return Ok(self.baud_rate);
+            }
+        }
+        self.stream = None;
+        anyhow::bail!("no LD2410C answered on {} at any baud rate", self.path)
+    }
+
+    // change_baud_rate() Sets the baud rate of the radar, restarts it so that the rate takes effect and
+    // reopens the port at the new rate. Returns once the radar answers at the new rate. If it does not, the
+    // radar is looked for at every baud rate (detect_baud_rate()) so that the driver does not lose it, and
+    // an error is returned.
+    pub async fn change_baud_rate(&mut self, baud_rate: BaudRate) -> anyhow::Result<()> {
+        let new_baud_rate = baud_rate.to_baud();
+        self.set_enabling_configuration().await?;
+        self.command_with_retries(Ld2410CFrame::serial_port_baud_rate(baud_rate)).await?;
+        // The radar does not send the ending configuration ACK after a restart.
+        self.command_with_retries(Ld2410CFrame::restart_module()).await?;
+        self.stream = None;
+        tokio::time::sleep(RESTART_DELAY).await;
+        self.baud_rate = new_baud_rate;
+        self.connect().await?;
+        if let Err(e) = self.check_communication().await {
+            let found = self.detect_baud_rate().await;
+            match found {
+                Ok(found) => anyhow::bail!("the radar does not answer at {new_baud_rate} baud ({e}), it answers at {found} baud"),
+                Err(_) => anyhow::bail!("the radar does not answer at {new_baud_rate} baud ({e}) nor at any other rate"),
+            }
+        }
+        Ok(())
     }
 
     //set_serial_port_baud_rate() This command sets the serial port baud rate of the radar module.
     // The default baud rate is 256000, and the baud rate can be set to 460800, 230400, 115200, 57600, 38400, 19200, 9600.
+    // It takes effect after the radar restarts, see change_baud_rate() to change it without losing the radar.
     pub async fn set_serial_port_baud_rate(&mut self, baud_rate: BaudRate) -> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
-        let command = Ld2410CCommand::new(vec![0xA1, 0x00], baud_rate.to_vec());
-        let data_length = vec![0x04, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+        self.send_command(Ld2410CFrame::serial_port_baud_rate(baud_rate)).await
     }
 
     // set_distance_gate_sensitivity_configuration() This command configures the sensitivity of the distance gate, and the configured value
@@ -574,20 +385,211 @@ impl Ld2410C {
         motion_sensitivity: u8,
         standstill_sensitivity: u8,
     ) -> anyhow::Result<Vec<u8>> {
-        self.set_enabling_configuration().await?;
+        let frame = Ld2410CFrame::distance_gate_sensitivity_configuration(
+            distance_gate,
+            motion_sensitivity,
+            standstill_sensitivity,
+        )?;
+        self.send_command(frame).await
+    }
+
+    // set_auxiliary_control() This command sets the auxiliary control of the OUT pin. When the light control
+    // is enabled, the OUT pin only outputs the presence state if the photosensitive value is below
+    // (or above) the threshold, otherwise it stays at its default level. The configured value is not
+    // lost when power is lost.
+    pub async fn set_auxiliary_control(&mut self, auxiliary_control: AuxiliaryControl) -> anyhow::Result<Vec<u8>> {
+        self.send_command(Ld2410CFrame::auxiliary_control(auxiliary_control)).await
+    }
+
+    // query_auxiliary_control() This command queries the current auxiliary control configuration
+    // (light control mode, light threshold and OUT pin default level).
+    pub async fn query_auxiliary_control(&mut self) -> anyhow::Result<AuxiliaryControl> {
+        let response = self.send_command(Ld2410CFrame::query_auxiliary_control()).await?;
+        Ok(AuxiliaryControl::from_response(&response)?)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::DuplexStream;
+
+    const BASIC_REPORT: [u8; 23] = [
+        0xF4, 0xF3, 0xF2, 0xF1, 0x0D, 0x00, 0x02, 0xAA, 0x02, 0x51, 0x00, 0x00, 0x00, 0x00, 0x3B, 0x00,
+        0x00, 0x55, 0x00, 0xF8, 0xF7, 0xF6, 0xF5,
+    ];
+    const END_ACK: [u8; 14] = [
+        0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xFE, 0x01, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01,
+    ];
+
+    // connected() Returns a driver connected to an in-memory port, and the radar end of the port.
+    fn connected() -> (Ld2410C, DuplexStream) {
+        let (port, radar) = tokio::io::duplex(1024);
+        let mut ld2410c = Ld2410C::new("test".to_string());
+        ld2410c.stream = Some(Box::new(port));
+        (ld2410c, radar)
+    }
+
+    #[test]
+    fn next_report_waits_for_complete_frame() {
+        let mut buffer = vec![0x00, 0x13];
+        buffer.extend_from_slice(&BASIC_REPORT[..10]);
+        assert!(next_report(&mut buffer).is_none());
+        assert_eq!(buffer, BASIC_REPORT[..10]);
+        buffer.extend_from_slice(&BASIC_REPORT[10..]);
+        let data = next_report(&mut buffer).unwrap().unwrap();
+        assert_eq!(data.target_data.movement_target_distance, 0x51);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn next_ack_skips_reports_and_other_acks() {
+        let mut buffer = [&BASIC_REPORT[..], &END_ACK, &BASIC_REPORT].concat();
+        assert!(next_ack(&mut buffer, 0x00FE).unwrap().is_ok());
+        assert_eq!(buffer, BASIC_REPORT);
+        let mut buffer = [&BASIC_REPORT[..], &END_ACK].concat();
+        assert!(next_ack(&mut buffer, 0x00FF).is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_report_split_across_reads() {
+        let (mut ld2410c, mut radar) = connected();
+        radar.write_all(&[0x00, 0x13]).await.unwrap();
+        radar.write_all(&BASIC_REPORT[..10]).await.unwrap();
+        let rest = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            radar.write_all(&BASIC_REPORT[10..]).await.unwrap();
+        };
+        let (data, ()) = tokio::join!(ld2410c.read_data(), rest);
+        let data = data.unwrap();
+        assert_eq!(data.data_type, DataType::TargetBasicInformation);
+        assert_eq!(data.target_data.target_status, TargetStatus::StationaryTarget);
+        assert_eq!(data.target_data.stationary_target_energy, 0x3B);
+    }
 
-        if motion_sensitivity > 100 || standstill_sensitivity > 100 {
-            return Err(anyhow::anyhow!(
-                "Sensitivity values must be between 0 and 100"
-            ));
+    #[tokio::test]
+    async fn keeps_reports_received_in_one_read() {
+        let (mut ld2410c, mut radar) = connected();
+        radar.write_all(&[&END_ACK[..], &BASIC_REPORT, &BASIC_REPORT].concat()).await.unwrap();
+        for _ in 0..2 {
+            let data = ld2410c.read_data().await.unwrap();
+            assert_eq!(data.data_type, DataType::TargetBasicInformation);
         }
-        let command_value = GateSensitivity::new(distance_gate, motion_sensitivity, standstill_sensitivity);
-        let command = Ld2410CCommand::new(vec![0x64, 0x00], command_value.to_vec());
-        let data_length = vec![0x14, 0x00];
-        let frame = Ld2410CFrame::new(data_length, command);
-        self.write_data(&frame.to_u8()).await?;
-        let response = self.response_configuration().await?;
-        self.set_ending_configuration().await?;
-        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn read_data_times_out_without_report() {
+        let (mut ld2410c, mut radar) = connected();
+        ld2410c.set_timeout(Duration::from_millis(50));
+        radar.write_all(&END_ACK).await.unwrap();
+        assert!(ld2410c.read_data().await.is_err());
+    }
+
+    // Baud rates of the values 1 to 8 of the baud rate command.
+    const BAUD_RATES: [u32; 8] = [9600, 19200, 38400, 57600, 115200, 230400, 256000, 460800];
+
+    // FakeRadar answers the commands with ACKs only on a port opened at its baud rate. A baud rate command
+    // takes effect at the next restart.
+    #[derive(Default)]
+    struct FakeRadar {
+        baud_rate: u32,
+        pending: Option<u32>,
+        // Ignores the baud rate commands, like a radar that did not switch.
+        ignore_baud_rate: bool,
+        // Baud rates the port was opened at.
+        opened: Vec<u32>,
+    }
+
+    // fake_radar() Returns a driver whose port opens a fake radar at `baud_rate`, and the radar.
+    fn fake_radar(baud_rate: u32) -> (Ld2410C, Arc<Mutex<FakeRadar>>) {
+        let radar = Arc::new(Mutex::new(FakeRadar {
+            baud_rate,
+            ..Default::default()
+        }));
+        let mut ld2410c = Ld2410C::new("fake".to_string());
+        let state = radar.clone();
+        ld2410c.open = Box::new(move |_, baud_rate| {
+            state.lock().unwrap().opened.push(baud_rate);
+            let (port, end) = tokio::io::duplex(1024);
+            tokio::spawn(answer(end, baud_rate, state.clone()));
+            Ok(Box::new(port))
+        });
+        (ld2410c, radar)
+    }
+
+    // answer() Answers the commands received on a port opened at `baud_rate` until the port is closed.
+    async fn answer(mut port: DuplexStream, baud_rate: u32, radar: Arc<Mutex<FakeRadar>>) {
+        let mut buffer = Vec::new();
+        let mut buf = [0u8; 256];
+        while let Ok(n @ 1..) = port.read(&mut buf).await {
+            buffer.extend_from_slice(&buf[..n]);
+            while let FrameSearch::Complete { start, end } = find_frame(&buffer, COMMAND_FRAME_HEADER) {
+                let word = u16::from_le_bytes([buffer[start + 6], buffer[start + 7]]);
+                let value = buffer[start + 8];
+                buffer.drain(..end);
+                let answer = {
+                    let mut radar = radar.lock().unwrap();
+                    if radar.baud_rate != baud_rate {
+                        // Garbage at another baud rate.
+                        vec![0xFF, 0x00, 0x55]
+                    } else {
+                        match word {
+                            0x00A1 if !radar.ignore_baud_rate => radar.pending = Some(BAUD_RATES[value as usize - 1]),
+                            0x00A3 => radar.baud_rate = radar.pending.take().unwrap_or(radar.baud_rate),
+                            _ => {}
+                        }
+                        let mut ack = COMMAND_FRAME_HEADER.to_vec();
+                        ack.extend_from_slice(&[0x04, 0x00]);
+                        ack.extend_from_slice(&(word | 0x0100).to_le_bytes());
+                        ack.extend_from_slice(&[0x00, 0x00, 0x04, 0x03, 0x02, 0x01]);
+                        ack
+                    }
+                };
+                if port.write_all(&answer).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn detect_baud_rate_finds_the_radar() {
+        let (mut ld2410c, radar) = fake_radar(57600);
+        assert_eq!(ld2410c.detect_baud_rate().await.unwrap(), 57600);
+        assert_eq!(ld2410c.baud_rate(), 57600);
+        assert_eq!(radar.lock().unwrap().opened, [256000, 115200, 460800, 230400, 57600]);
+        // The port stays open at the detected rate.
+        ld2410c.check_communication().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn detect_baud_rate_fails_without_radar() {
+        let (mut ld2410c, radar) = fake_radar(1200);
+        assert!(ld2410c.detect_baud_rate().await.is_err());
+        assert_eq!(radar.lock().unwrap().opened.len(), BaudRate::ALL.len());
+        assert!(ld2410c.check_communication().await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn change_baud_rate_reopens_at_new_rate() {
+        let (mut ld2410c, radar) = fake_radar(256000);
+        ld2410c.connect().await.unwrap();
+        ld2410c.change_baud_rate(BaudRate::BaudRate115200).await.unwrap();
+        assert_eq!(ld2410c.baud_rate(), 115200);
+        let radar = radar.lock().unwrap();
+        assert_eq!(radar.baud_rate, 115200);
+        assert_eq!(radar.opened, [256000, 115200]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn change_baud_rate_finds_radar_that_did_not_switch() {
+        let (mut ld2410c, radar) = fake_radar(256000);
+        radar.lock().unwrap().ignore_baud_rate = true;
+        ld2410c.connect().await.unwrap();
+        let error = ld2410c.change_baud_rate(BaudRate::BaudRate9600).await.unwrap_err();
+        assert!(error.to_string().contains("answers at 256000 baud"), "{error}");
+        assert_eq!(ld2410c.baud_rate(), 256000);
+        ld2410c.check_communication().await.unwrap();
     }
 }
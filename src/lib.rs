@@ -0,0 +1,45 @@
+// lib.rs
+// Drivers for the LD2410C, RD03D, TF-Luna and TOF200F sensors. Each sensor is behind the cargo
+// feature of the same name so that services only pull in the sensors they use. The protocol
+// module is no_std; the async drivers are behind the `tokio` feature, the blocking ones (blocking
+// module) behind the `blocking` feature, and the web dashboard (api.rs and the templates) is
+// behind the `server` feature. All of them require the `std` feature, except the drivers generic
+// over embedded-io (embedded module, `embedded` and `embedded-async` features) which are no_std.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod protocol;
+
+#[cfg(feature = "server")]
+pub mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod device;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+#[cfg(feature = "server")]
+pub mod export;
+#[cfg(feature = "server")]
+pub mod fusion;
+#[cfg(feature = "server")]
+pub mod history;
+#[cfg(feature = "server")]
+pub mod hub;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod mqtt;
+#[cfg(all(feature = "ld2410c", feature = "std"))]
+pub mod ld2410c;
+#[cfg(all(feature = "rd03d", feature = "std"))]
+pub mod rd03d;
+#[cfg(all(feature = "tf_luna", feature = "std"))]
+pub mod tf_luna;
+#[cfg(all(feature = "tof200f", feature = "std"))]
+pub mod tof200f;
+#[cfg(feature = "server")]
+pub mod stream;
+#[cfg(feature = "server")]
+pub mod ws;
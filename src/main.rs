@@ -1,33 +1,162 @@
-pub mod api;
-pub mod ld2410c;
-pub mod rd03d;
-pub mod tf_luna;
-pub mod tof200f;
+use sensor::{
+    api,
+    config::Config,
+    device,
+    export::{self, Format},
+    history::{self, History},
+    hub::{self, Hub},
+    mqtt,
+};
 
+// Usage:
+//   sensor --config sensor.toml
+//   sensor <port> [ld2410c|rd03d|tfluna|tof200f]   (default: COM7 ld2410c)
+//   sensor export --config sensor.toml [--sensors a,b] [--from 24h] [--to <ms>] [--resolution 1m]
+//                 [--format csv|jsonl|parquet] [--output file]
+//   sensor ports [--probe] [--config sensor.toml]
+// The SENSOR_API_TOKEN environment variable overrides the api_token of the configuration.
 #[tokio::main]
-async fn main() {
-    // let app = api::api("COM7".to_string(),ld2410c::DataType::EngineeringMode).await;
-    // let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-    //     .await
-    //     .unwrap();
-    // println!("listening on {}", listener.local_addr().unwrap());
-    // axum::serve(listener, app).await.unwrap();
-    let mut tof200f = tof200f::TOF200F::new("COM7".to_string());
-    if let Err(e) = tof200f.connect().await {
-        eprintln!("Error connecting to Tof200F: {}", e);
-        return;
-    }
-    else {
-        loop {
-            match tof200f.read_data().await {
-                Ok(data) => {
-                    println!("Distance: {:?} cm", data);
-                }
-                Err(e) => {
-                    eprintln!("Error reading data from Tof200F: {}", e);
-                }
-                
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        return export_command(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("ports") {
+        return ports_command(&args[1..]).await;
+    }
+    let mut config = match args.first().map(String::as_str) {
+        Some("--config") => {
+            let path = args.get(1).ok_or_else(|| anyhow::anyhow!("--config needs a file path"))?;
+            Config::load(path)?
+        }
+        port => {
+            let port = port.unwrap_or("COM7").to_string();
+            let kind = args.get(1).map(|kind| kind.parse()).transpose()?;
+            let kind = kind.unwrap_or(sensor::config::SensorKind::Ld2410c);
+            if kind.is_virtual() {
+                anyhow::bail!("a {} sensor fuses other sensors and needs --config", kind.name());
+            }
+            Config::single_sensor(port, kind)
+        }
+    };
+    if let Ok(api_token) = std::env::var("SENSOR_API_TOKEN") {
+        config.api_token = Some(api_token);
+    }
+    let hub = Hub::start(&config.sensors);
+    if let Some(mqtt_config) = &config.mqtt {
+        mqtt::start(hub.clone(), mqtt_config.clone());
+    }
+    let history = config.history.clone().map(History::new);
+    if let Some(history) = &history {
+        history.start(&hub);
+    }
+    let app = api::api(hub, history, &config);
+    let listener = tokio::net::TcpListener::bind(&config.bind).await?;
+    println!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// export_command() Writes the recorded readings to a file (or the standard output), see export.rs. The
+// times are milliseconds since the Unix epoch or durations before now ("24h"); the format defaults to the
+// extension of the output file, or CSV.
+fn export_command(args: &[String]) -> anyhow::Result<()> {
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .map(|i| args.get(i + 1).map(String::as_str).ok_or_else(|| anyhow::anyhow!("{name} needs a value")))
+            .transpose()
+    };
+    let path = option("--config")?.ok_or_else(|| anyhow::anyhow!("export needs --config <file>"))?;
+    let config = Config::load(path)?;
+    let history_config = config
+        .history
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("no [history] section in {path}"))?;
+    let sensors = match option("--sensors")? {
+        Some(ids) => ids
+            .split(',')
+            .map(|id| {
+                let sensor = config.sensors.iter().find(|sensor| sensor.id == id);
+                let sensor = sensor.ok_or_else(|| anyhow::anyhow!("unknown sensor {id:?}"))?;
+                Ok((sensor.id.clone(), sensor.kind))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => config.sensors.iter().map(|sensor| (sensor.id.clone(), sensor.kind)).collect(),
+    };
+    let now = hub::now_ms();
+    let time = |name: &str, default: u64| -> anyhow::Result<u64> {
+        match option(name)? {
+            Some(text) => export::parse_time(text, now).ok_or_else(|| anyhow::anyhow!("invalid time {text:?}")),
+            None => Ok(default),
+        }
+    };
+    let to = time("--to", now)?;
+    let from = time("--from", to.saturating_sub(3_600_000))?;
+    if from > to {
+        anyhow::bail!("--from must not be after --to");
+    }
+    let resolution = option("--resolution")?
+        .map(|text| history::parse_duration(text).ok_or_else(|| anyhow::anyhow!("invalid resolution {text:?}")))
+        .transpose()?;
+    let output = option("--output")?;
+    let format: Format = match (option("--format")?, output.and_then(|output| output.rsplit_once('.'))) {
+        (Some(format), _) => format.parse()?,
+        (None, Some((_, extension))) => extension.parse().unwrap_or(Format::Csv),
+        (None, None) => Format::Csv,
+    };
+    let data = export::export(&History::new(history_config), &sensors, from, to, resolution, format)?;
+    match output {
+        Some(output) => std::fs::write(output, data)?,
+        None => std::io::Write::write_all(&mut std::io::stdout(), &data)?,
+    }
+    Ok(())
+}
+
+// ports_command() Lists the serial ports with their USB identifiers and, with --probe, the sensor found on
+// each of them. The ports of the sensors of the configuration (--config) are not probed.
+async fn ports_command(args: &[String]) -> anyhow::Result<()> {
+    let sensors = match args.iter().position(|arg| arg == "--config") {
+        Some(i) => {
+            let path = args.get(i + 1).ok_or_else(|| anyhow::anyhow!("--config needs a value"))?;
+            Config::load(path)?.sensors
+        }
+        None => Vec::new(),
+    };
+    let ports = device::list(&sensors);
+    if ports.is_empty() {
+        println!("no serial port found");
+        return Ok(());
+    }
+    let probes = if args.iter().any(|arg| arg == "--probe") {
+        println!("probing {} port(s)...", ports.iter().filter(|port| port.sensor.is_none()).count());
+        device::probe_all(&sensors).await
+    } else {
+        Vec::new()
+    };
+    for port in ports {
+        let mut line = port.port.clone();
+        if let Some(usb) = &port.usb {
+            line += &format!("  usb = {{ vid = 0x{:04x}, pid = 0x{:04x}", usb.vid, usb.pid);
+            if let Some(serial_number) = &usb.serial_number {
+                line += &format!(", serial_number = {serial_number:?}");
+            }
+            line += " }";
+        }
+        if let Some(product) = port.product.as_ref().or(port.manufacturer.as_ref()) {
+            line += &format!("  {product}");
+        }
+        if let Some(sensor) = &port.sensor {
+            line += &format!("  [sensor {sensor}]");
+        }
+        if let Some(probe) = probes.iter().find(|probe| probe.port == port.port) {
+            match (&probe.kind, &probe.baud_rate, &probe.error) {
+                (Some(kind), Some(baud_rate), _) => line += &format!("  -> {} at {baud_rate} baud", kind.name()),
+                (_, _, Some(error)) => line += &format!("  -> {error}"),
+                _ => line += "  -> no sensor recognized",
             }
         }
+        println!("{line}");
     }
+    Ok(())
 }
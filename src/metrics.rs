@@ -0,0 +1,253 @@
+// metrics.rs
+// Prometheus metrics (GET /metrics, text exposition format): connection state and counters of every
+// sensor, and gauges computed from its latest reading. All the samples have the labels sensor="<id>" and
+// kind="<kind>".
+use std::fmt::Write;
+
+use crate::hub::{Hub, Reading, Sample, SensorStatus};
+use crate::ld2410c;
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    // add() Adds a sample of a sensor, with the extra labels (name, value).
+    fn add(&mut self, sensor: &SensorStatus, labels: &[(&str, &str)], value: f64) {
+        let mut text = format!("sensor=\"{}\",kind=\"{}\"", escape(&sensor.id), sensor.kind.name());
+        for (name, label) in labels {
+            let _ = write!(text, ",{name}=\"{}\"", escape(label));
+        }
+        self.samples.push((text, value));
+    }
+}
+
+// render() Returns the metrics of all the sensors.
+pub fn render(hub: &Hub) -> String {
+    let sensors: Vec<(SensorStatus, Option<Sample>)> =
+        hub.sensors().iter().map(|sensor| (sensor.status(), sensor.latest())).collect();
+    render_sensors(&sensors)
+}
+
+// render_sensors() Returns the metrics of the sensors, from their status and latest reading.
+fn render_sensors(sensors: &[(SensorStatus, Option<Sample>)]) -> String {
+    let mut connected = Family::new("sensor_connected", "gauge", "1 if the serial port of the sensor is open.");
+    let mut frames = Family::new("sensor_frames_total", "counter", "Frames decoded.");
+    let mut decode_errors = Family::new(
+        "sensor_decode_errors_total",
+        "counter",
+        "Frames rejected by the decoder (invalid header, tail, check byte or length).",
+    );
+    let mut serial_errors = Family::new("sensor_serial_errors_total", "counter", "Serial port and session errors.");
+    let mut reconnects = Family::new("sensor_reconnects_total", "counter", "Number of times the sensor was reopened.");
+    let mut sse_clients = Family::new("sensor_sse_clients", "gauge", "Clients connected to the SSE stream.");
+    let mut frame_age = Family::new(
+        "sensor_last_frame_age_seconds",
+        "gauge",
+        "Time elapsed since the last decoded frame.",
+    );
+    let mut distance = Family::new(
+        "sensor_distance_meters",
+        "gauge",
+        "Measured distance (TF-Luna, TOF200F), detection distance (LD2410C) or distance of the person (presence).",
+    );
+    let mut presence = Family::new("sensor_presence", "gauge", "1 if a target is detected (LD2410C, RD03D) or a person is present (presence, room).");
+    let mut confidence = Family::new(
+        "sensor_presence_confidence",
+        "gauge",
+        "Confidence of the presence estimate, 0 to 1 (presence).",
+    );
+    let mut target_count = Family::new("sensor_target_count", "gauge", "Number of tracked targets (RD03D, room).");
+    let mut target_distance = Family::new(
+        "sensor_target_distance_meters",
+        "gauge",
+        "Distance of a target: moving or stationary (LD2410C), slot 1 to 3 (RD03D).",
+    );
+    let mut target_energy = Family::new(
+        "sensor_target_energy",
+        "gauge",
+        "Energy of the moving and stationary targets, 0 to 100 (LD2410C).",
+    );
+    let mut gate_energy = Family::new(
+        "sensor_gate_energy",
+        "gauge",
+        "Energy of each distance gate, 0 to 100 (LD2410C engineering mode).",
+    );
+
+    for (sensor, sample) in sensors {
+        connected.add(sensor, &[], sensor.connected as u8 as f64);
+        frames.add(sensor, &[], sensor.frames as f64);
+        decode_errors.add(sensor, &[], sensor.decode_errors as f64);
+        serial_errors.add(sensor, &[], sensor.errors as f64);
+        reconnects.add(sensor, &[], sensor.reconnects as f64);
+        sse_clients.add(sensor, &[], sensor.sse_clients as f64);
+        let Some(sample) = sample else {
+            continue;
+        };
+        if let Some(age_ms) = sensor.last_frame_age_ms {
+            frame_age.add(sensor, &[], age_ms as f64 / 1000.0);
+        }
+        match &sample.data {
+            Reading::Tfluna(data) => distance.add(sensor, &[], data.distance() as f64 / 100.0),
+            Reading::Tof200f(data) => distance.add(sensor, &[], data.distance() as f64 / 1000.0),
+            Reading::Presence(data) => {
+                presence.add(sensor, &[], data.present as u8 as f64);
+                confidence.add(sensor, &[], data.confidence as f64);
+                if let Some(distance_cm) = data.distance_cm {
+                    distance.add(sensor, &[], distance_cm as f64 / 100.0);
+                }
+            }
+            Reading::Ld2410c(data) => {
+                let target = &data.target_data;
+                distance.add(sensor, &[], target.detection_distance as f64 / 100.0);
+                let detected = target.target_status != ld2410c::TargetStatus::NoTarget;
+                presence.add(sensor, &[], detected as u8 as f64);
+                target_distance.add(sensor, &[("target", "moving")], target.movement_target_distance as f64 / 100.0);
+                target_distance.add(
+                    sensor,
+                    &[("target", "stationary")],
+                    target.stationary_target_distance as f64 / 100.0,
+                );
+                target_energy.add(sensor, &[("target", "moving")], target.movement_target_energy as f64);
+                target_energy.add(sensor, &[("target", "stationary")], target.stationary_target_energy as f64);
+                if let Some(model) = &target.engineering_model {
+                    for (kind, energies) in [("moving", &model.moving_distance_gates), ("static", &model.static_distance_gates)] {
+                        for (gate, energy) in energies.iter().enumerate() {
+                            gate_energy.add(sensor, &[("gate", &gate.to_string()), ("type", kind)], *energy as f64);
+                        }
+                    }
+                }
+            }
+            Reading::Rd03d(targets) => {
+                // Empty slots have a zero distance.
+                let count = targets.iter().filter(|target| target.distance > 0.0).count();
+                presence.add(sensor, &[], (count > 0) as u8 as f64);
+                target_count.add(sensor, &[], count as f64);
+                for (slot, target) in targets.iter().enumerate() {
+                    target_distance.add(sensor, &[("target", &(slot + 1).to_string())], target.distance / 1000.0);
+                }
+            }
+            Reading::Room(room) => {
+                presence.add(sensor, &[], !room.targets.is_empty() as u8 as f64);
+                target_count.add(sensor, &[], room.targets.len() as f64);
+            }
+        }
+    }
+
+    let mut text = String::new();
+    let families = [
+        connected,
+        frames,
+        decode_errors,
+        serial_errors,
+        reconnects,
+        sse_clients,
+        frame_age,
+        distance,
+        presence,
+        confidence,
+        target_count,
+        target_distance,
+        target_energy,
+        gate_energy,
+    ];
+    for family in families.iter().filter(|family| !family.samples.is_empty()) {
+        let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(text, "# TYPE {} {}", family.name, family.kind);
+        for (labels, value) in &family.samples {
+            let _ = writeln!(text, "{}{{{labels}}} {value}", family.name);
+        }
+    }
+    text
+}
+
+// escape() Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SensorKind;
+    use crate::hub::ConnectionState;
+    use crate::tof200f::Tof200fData;
+
+    fn status(id: &str, kind: SensorKind, connected: bool) -> SensorStatus {
+        SensorStatus {
+            id: id.to_string(),
+            kind,
+            port: "/dev/ttyUSB0".to_string(),
+            connected,
+            state: if connected { ConnectionState::Connected } else { ConnectionState::Disconnected },
+            last_frame_age_ms: connected.then_some(1500),
+            frames: if connected { 42 } else { 0 },
+            errors: 1,
+            decode_errors: 2,
+            reconnects: 3,
+            sse_clients: 0,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn text_exposition_format() {
+        let sample = Sample {
+            timestamp_ms: 0,
+            data: Reading::Tof200f(Tof200fData::new(1234)),
+        };
+        let sensors = [
+            (status("tof", SensorKind::Tof200f, true), Some(sample)),
+            (status("desk \"2\"\\", SensorKind::Ld2410c, false), None),
+        ];
+        let expected = r#"# HELP sensor_connected 1 if the serial port of the sensor is open.
+# TYPE sensor_connected gauge
+sensor_connected{sensor="tof",kind="tof200f"} 1
+sensor_connected{sensor="desk \"2\"\\",kind="ld2410c"} 0
+# HELP sensor_frames_total Frames decoded.
+# TYPE sensor_frames_total counter
+sensor_frames_total{sensor="tof",kind="tof200f"} 42
+sensor_frames_total{sensor="desk \"2\"\\",kind="ld2410c"} 0
+# HELP sensor_decode_errors_total Frames rejected by the decoder (invalid header, tail, check byte or length).
+# TYPE sensor_decode_errors_total counter
+sensor_decode_errors_total{sensor="tof",kind="tof200f"} 2
+sensor_decode_errors_total{sensor="desk \"2\"\\",kind="ld2410c"} 2
+# HELP sensor_serial_errors_total Serial port and session errors.
+# TYPE sensor_serial_errors_total counter
+sensor_serial_errors_total{sensor="tof",kind="tof200f"} 1
+sensor_serial_errors_total{sensor="desk \"2\"\\",kind="ld2410c"} 1
+# HELP sensor_reconnects_total Number of times the sensor was reopened.
+# TYPE sensor_reconnects_total counter
+sensor_reconnects_total{sensor="tof",kind="tof200f"} 3
+sensor_reconnects_total{sensor="desk \"2\"\\",kind="ld2410c"} 3
+# HELP sensor_sse_clients Clients connected to the SSE stream.
+# TYPE sensor_sse_clients gauge
+sensor_sse_clients{sensor="tof",kind="tof200f"} 0
+sensor_sse_clients{sensor="desk \"2\"\\",kind="ld2410c"} 0
+# HELP sensor_last_frame_age_seconds Time elapsed since the last decoded frame.
+# TYPE sensor_last_frame_age_seconds gauge
+sensor_last_frame_age_seconds{sensor="tof",kind="tof200f"} 1.5
+# HELP sensor_distance_meters Measured distance (TF-Luna, TOF200F), detection distance (LD2410C) or distance of the person (presence).
+# TYPE sensor_distance_meters gauge
+sensor_distance_meters{sensor="tof",kind="tof200f"} 1.234
+"#;
+        assert_eq!(render_sensors(&sensors), expected);
+    }
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+}
@@ -0,0 +1,215 @@
+// commands.rs
+// Configuration of the sensors over MQTT. A message on <prefix>/<id>/set/<setting> runs the command in the
+// sensor task, as the configuration endpoints, and the result is published on <prefix>/<id>/response:
+//   sensor/radar/set/engineering-mode   true, "ON" or {"enabled": true}                         (LD2410C)
+//   sensor/radar/set/gate-sensitivity   {"gate": 3, "motion_sensitivity": 40, "standstill_sensitivity": 30}
+//   sensor/rd03d/set/multi-mode         false, "OFF" or {"enabled": false}                      (RD03D)
+//   sensor/tfluna/set/frequency         100 or {"frequency_hz": 100}                            (TF-Luna)
+// Response: {"setting": "frequency", "result": {...}} or {"setting": "frequency", "error": "..."}, with the
+// "request_id" of the message if it had one.
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::{MqttConfig, SensorKind};
+use crate::hub::{Command, Hub, Ld2410cCommand, Rd03dCommand, TflunaCommand};
+use crate::tf_luna;
+
+use super::Message;
+
+// filter() Returns the topic filter of the command topics.
+pub fn filter(config: &MqttConfig) -> String {
+    format!("{}/+/set/+", config.prefix)
+}
+
+// handle() Runs the command of a message received on a command topic and returns the response message.
+// Returns None if the topic is not a command topic.
+pub async fn handle(hub: &Hub, config: &MqttConfig, topic: &str, payload: &[u8]) -> Option<Message> {
+    let mut parts = topic.strip_prefix(&config.prefix)?.strip_prefix('/')?.split('/');
+    let (id, set, setting) = (parts.next()?, parts.next()?, parts.next()?);
+    if set != "set" || parts.next().is_some() {
+        return None;
+    }
+    let sensor = hub.get(id)?;
+    // Plain text payloads ("ON", "100") are accepted as well as JSON.
+    let text = String::from_utf8_lossy(payload);
+    let value = serde_json::from_str(&text).unwrap_or_else(|_| Value::String(text.trim().to_string()));
+    let mut response = json!({ "setting": setting });
+    if let Some(request_id) = value.get("request_id") {
+        response["request_id"] = request_id.clone();
+    }
+    let result = match parse_command(sensor.config.kind, setting, value) {
+        Ok(command) => sensor.send(command).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(result) => response["result"] = result,
+        Err(error) => response["error"] = json!(error),
+    }
+    let response_topic = format!("{}/{id}/response", config.prefix);
+    Some(Message::new(response_topic, response.to_string(), false))
+}
+
+#[derive(Deserialize)]
+struct GateSensitivity {
+    gate: Option<u8>,
+    motion_sensitivity: u8,
+    standstill_sensitivity: u8,
+}
+
+// parse_command() Returns the command setting `setting` of a sensor of the given kind to `value`.
+fn parse_command(kind: SensorKind, setting: &str, value: Value) -> Result<Command, String> {
+    match (kind, setting) {
+        (SensorKind::Ld2410c, "engineering-mode") => {
+            Ok(Command::Ld2410c(Ld2410cCommand::SetEngineeringMode(enabled(&value)?)))
+        }
+        (SensorKind::Ld2410c, "gate-sensitivity") => {
+            let body: GateSensitivity = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            let command =
+                Ld2410cCommand::gate_sensitivity(body.gate, body.motion_sensitivity, body.standstill_sensitivity)?;
+            Ok(Command::Ld2410c(command))
+        }
+        (SensorKind::Rd03d, "multi-mode") => Ok(Command::Rd03d(Rd03dCommand::SetMultiMode(enabled(&value)?))),
+        (SensorKind::Tfluna, "frequency") => {
+            let hz = value
+                .get("frequency_hz")
+                .unwrap_or(&value)
+                .as_u64()
+                .or_else(|| value.as_str().and_then(|hz| hz.parse().ok()))
+                .ok_or("expected a frequency in Hz")?;
+            let frequency = u16::try_from(hz)
+                .ok()
+                .and_then(tf_luna::OutputFrequency::from_hz)
+                .ok_or_else(|| format!("unsupported frequency {hz} Hz"))?;
+            Ok(Command::Tfluna(TflunaCommand::SetOutputFrequency(frequency)))
+        }
+        (kind, setting) => Err(format!("unsupported setting {setting:?} for the {}", kind.name())),
+    }
+}
+
+// enabled() Reads a boolean setting: true/false, "ON"/"OFF", 1/0 or {"enabled": true}.
+fn enabled(value: &Value) -> Result<bool, String> {
+    match value.get("enabled").unwrap_or(value) {
+        Value::Bool(enabled) => Ok(*enabled),
+        Value::Number(n) if n.as_u64() == Some(0) || n.as_u64() == Some(1) => Ok(n.as_u64() == Some(1)),
+        Value::String(s) if s.eq_ignore_ascii_case("on") || s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("off") || s.eq_ignore_ascii_case("false") => Ok(false),
+        other => Err(format!("expected true or false, got {other}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // command() Returns the command parsed from the JSON `payload`, as its Debug text for the comparisons.
+    fn command(kind: SensorKind, setting: &str, payload: &str) -> Result<String, String> {
+        parse_command(kind, setting, serde_json::from_str(payload).unwrap()).map(|command| format!("{command:?}"))
+    }
+
+    #[test]
+    fn enabled_accepts_the_boolean_shapes() {
+        for (payload, expected) in [
+            ("true", true),
+            ("false", false),
+            (r#""ON""#, true),
+            (r#""off""#, false),
+            (r#""True""#, true),
+            ("1", true),
+            ("0", false),
+            (r#"{"enabled": true}"#, true),
+            (r#"{"enabled": "OFF"}"#, false),
+        ] {
+            assert_eq!(enabled(&serde_json::from_str(payload).unwrap()), Ok(expected), "{payload}");
+        }
+        for payload in ["2", "-1", "0.5", r#""yes""#, "null", r#"{"enabled": null}"#, "[true]"] {
+            assert!(enabled(&serde_json::from_str(payload).unwrap()).is_err(), "{payload}");
+        }
+    }
+
+    #[test]
+    fn parses_the_commands_of_each_kind() {
+        assert_eq!(
+            command(SensorKind::Ld2410c, "engineering-mode", r#""ON""#),
+            Ok("Ld2410c(SetEngineeringMode(true))".to_string())
+        );
+        assert_eq!(
+            command(SensorKind::Rd03d, "multi-mode", r#"{"enabled": false}"#),
+            Ok("Rd03d(SetMultiMode(false))".to_string())
+        );
+        let gate = command(
+            SensorKind::Ld2410c,
+            "gate-sensitivity",
+            r#"{"gate": 3, "motion_sensitivity": 40, "standstill_sensitivity": 30}"#,
+        );
+        assert!(gate.unwrap().contains("motion_sensitivity: 40, standstill_sensitivity: 30"));
+        let gate = command(SensorKind::Ld2410c, "gate-sensitivity", r#"{"gate": 9, "motion_sensitivity": 40}"#);
+        assert!(gate.is_err());
+    }
+
+    #[test]
+    fn parses_the_frequency_shapes() {
+        let expected = Ok("Tfluna(SetOutputFrequency(Freq100Hz))".to_string());
+        for payload in ["100", r#""100""#, r#"{"frequency_hz": 100}"#] {
+            assert_eq!(command(SensorKind::Tfluna, "frequency", payload), expected, "{payload}");
+        }
+        assert_eq!(command(SensorKind::Tfluna, "frequency", "99"), Err("unsupported frequency 99 Hz".to_string()));
+        assert_eq!(
+            command(SensorKind::Tfluna, "frequency", "70000"),
+            Err("unsupported frequency 70000 Hz".to_string())
+        );
+        for payload in ["-1", "1.5", r#""fast""#, r#"{"hz": 100}"#] {
+            assert_eq!(
+                command(SensorKind::Tfluna, "frequency", payload),
+                Err("expected a frequency in Hz".to_string()),
+                "{payload}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_the_settings_of_other_kinds() {
+        assert_eq!(
+            command(SensorKind::Tfluna, "engineering-mode", "true"),
+            Err(r#"unsupported setting "engineering-mode" for the tfluna"#.to_string())
+        );
+        assert!(command(SensorKind::Ld2410c, "frequency", "100").is_err());
+        assert!(command(SensorKind::Ld2410c, "restart", "true").is_err());
+    }
+
+    #[tokio::test]
+    async fn handle_matches_the_command_topics() {
+        let config: crate::config::Config = toml::from_str(
+            "[mqtt]\ncommands = true\n[[sensors]]\nid = \"lidar\"\nkind = \"tfluna\"\n",
+        )
+        .unwrap();
+        let mqtt = config.mqtt.clone().unwrap();
+        let hub = Hub::without_tasks(&config.sensors);
+        hub.get("lidar").unwrap().fake_session(|command| Ok(json!(format!("{command:?}"))));
+
+        let response = handle(&hub, &mqtt, "sensor/lidar/set/frequency", br#"{"frequency_hz": 10, "request_id": 7}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.topic, "sensor/lidar/response");
+        assert!(!response.retain);
+        let response: Value = serde_json::from_slice(&response.payload).unwrap();
+        assert_eq!(
+            response,
+            json!({ "setting": "frequency", "request_id": 7, "result": "Tfluna(SetOutputFrequency(Freq10Hz))" })
+        );
+
+        let response = handle(&hub, &mqtt, "sensor/lidar/set/frequency", b" 99 ").await.unwrap();
+        let response: Value = serde_json::from_slice(&response.payload).unwrap();
+        assert_eq!(response, json!({ "setting": "frequency", "error": "unsupported frequency 99 Hz" }));
+
+        for topic in [
+            "other/lidar/set/frequency",
+            "sensorx/lidar/set/frequency",
+            "sensor/lidar/get/frequency",
+            "sensor/lidar/set/frequency/extra",
+            "sensor/lidar/set",
+            "sensor/radar/set/frequency",
+        ] {
+            assert!(handle(&hub, &mqtt, topic, b"10").await.is_none(), "{topic}");
+        }
+    }
+}
@@ -0,0 +1,349 @@
+// mqtt/mod.rs
+// MQTT publisher: sends the readings of every sensor, their availability and their connection events to
+// a broker (mosquitto, Home Assistant, Node-RED, ...), and the Home Assistant discovery payloads. The
+// topics are described in config.rs (MqttConfig). The connection is reopened when it is lost. With
+// `commands = true`, the sensors can be configured over MQTT (commands.rs).
+mod commands;
+mod packet;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::config::{MqttConfig, SensorKind};
+use crate::hub::{log_state, ConnectionState, Hub, Sensor, SensorEvent};
+use crate::stream::{self, Rate, Reduce};
+use futures::StreamExt;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+// Number of messages waiting to be sent to the broker.
+const OUTGOING_QUEUE_LEN: usize = 256;
+
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+impl Message {
+    fn new(topic: String, payload: impl Into<Vec<u8>>, retain: bool) -> Self {
+        Self {
+            topic,
+            payload: payload.into(),
+            retain,
+        }
+    }
+}
+
+// start() Spawns the publisher task. Must be called from a tokio runtime.
+pub fn start(hub: Hub, config: MqttConfig) {
+    tokio::spawn(run(hub, config));
+}
+
+// run() Keeps the connection to the broker open.
+async fn run(hub: Hub, config: MqttConfig) {
+    let name = format!("mqtt {}:{}", config.host, config.port);
+    loop {
+        if let Err(e) = session(&hub, &config, &name).await {
+            log_state(&name, ConnectionState::Disconnected, Some(&e.to_string()), Some(RECONNECT_DELAY));
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+// session() Connects to the broker and publishes until the connection is lost.
+async fn session(hub: &Hub, config: &MqttConfig, name: &str) -> anyhow::Result<()> {
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((config.host.as_str(), config.port)))
+        .await
+        .map_err(|_| anyhow::anyhow!("connection timeout"))??;
+    let (mut reader, mut writer) = stream.into_split();
+    let status_topic = format!("{}/status", config.prefix);
+    let connect = packet::Connect {
+        client_id: &config.client_id,
+        keep_alive_secs: KEEP_ALIVE.as_secs() as u16,
+        username: config.username.as_deref(),
+        password: config.password.as_deref(),
+        will: Some(packet::Will {
+            topic: &status_topic,
+            payload: b"offline",
+            retain: true,
+        }),
+    };
+    writer.write_all(&packet::connect(&connect)).await?;
+    let connack = tokio::time::timeout(CONNECT_TIMEOUT, packet::read_packet(&mut reader))
+        .await
+        .map_err(|_| anyhow::anyhow!("no CONNACK from the broker"))??;
+    match packet::connack_code(&connack) {
+        Some(0) => {}
+        Some(code) => anyhow::bail!("connection refused by the broker (code {code})"),
+        None => anyhow::bail!("unexpected packet 0x{:02X} instead of CONNACK", connack.header),
+    }
+    log_state(name, ConnectionState::Connected, None, None);
+    if config.commands {
+        writer.write_all(&packet::subscribe(1, &[&commands::filter(config)])).await?;
+    }
+    writer.write_all(&packet::publish(&status_topic, b"online", true)).await?;
+    if config.discovery {
+        for sensor in hub.sensors() {
+            for (topic, payload) in discovery(config, sensor) {
+                writer.write_all(&packet::publish(&topic, payload.to_string().as_bytes(), true)).await?;
+            }
+        }
+    }
+
+    // The tasks are aborted when the session ends (JoinSet dropped).
+    let mut tasks = JoinSet::new();
+    let (outgoing, mut outgoing_rx) = mpsc::channel::<Message>(OUTGOING_QUEUE_LEN);
+    for sensor in hub.sensors() {
+        tasks.spawn(publish_readings(config.clone(), sensor.clone(), outgoing.clone()));
+        tasks.spawn(publish_availability(config.clone(), sensor.clone(), hub.events(), outgoing.clone()));
+    }
+    let (incoming, mut incoming_rx) = mpsc::channel(1);
+    tasks.spawn(async move {
+        loop {
+            let packet = packet::read_packet(&mut reader).await;
+            let failed = packet.is_err();
+            if incoming.send(packet).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+    ping.tick().await;
+    loop {
+        // Reaps the command handlers that are done.
+        while tasks.try_join_next().is_some() {}
+        tokio::select! {
+            Some(message) = outgoing_rx.recv() => {
+                writer.write_all(&packet::publish(&message.topic, &message.payload, message.retain)).await?;
+            }
+            _ = ping.tick() => writer.write_all(&packet::pingreq()).await?,
+            packet = incoming_rx.recv() => match packet {
+                Some(Ok(packet)) => {
+                    // PINGRESP and SUBACK are ignored.
+                    let Some(publish) = packet::decode_publish(&packet).filter(|_| config.commands) else {
+                        continue;
+                    };
+                    // The command may take a few seconds, the response is sent when it is done.
+                    let (hub, config, outgoing) = (hub.clone(), config.clone(), outgoing.clone());
+                    tasks.spawn(async move {
+                        if let Some(response) = commands::handle(&hub, &config, &publish.topic, &publish.payload).await {
+                            let _ = outgoing.send(response).await;
+                        }
+                    });
+                }
+                Some(Err(e)) => return Err(e),
+                None => anyhow::bail!("connection closed"),
+            },
+        }
+    }
+}
+
+// publish_readings() Publishes the readings of a sensor on its state topic.
+async fn publish_readings(config: MqttConfig, sensor: Arc<Sensor>, outgoing: mpsc::Sender<Message>) {
+    let topic = state_topic(&config, &sensor);
+    let samples = stream::samples(sensor, Rate::Hz(config.hz), Reduce::Latest);
+    futures::pin_mut!(samples);
+    while let Some(sample) = samples.next().await {
+        let payload = serde_json::to_vec(&sample.data).unwrap_or_default();
+        if outgoing.send(Message::new(topic.clone(), payload, false)).await.is_err() {
+            return;
+        }
+    }
+}
+
+// publish_availability() Publishes the availability of a sensor, and its changes of connection state as
+// events.
+async fn publish_availability(
+    config: MqttConfig,
+    sensor: Arc<Sensor>,
+    mut events: broadcast::Receiver<SensorEvent>,
+    outgoing: mpsc::Sender<Message>,
+) {
+    let availability_topic = format!("{}/{}/availability", config.prefix, sensor.config.id);
+    let event_topic = format!("{}/{}/event", config.prefix, sensor.config.id);
+    let availability = |connected: bool| {
+        let payload = if connected { "online" } else { "offline" };
+        Message::new(availability_topic.clone(), payload, true)
+    };
+    let mut connected = sensor.state() == ConnectionState::Connected;
+    if outgoing.send(availability(connected)).await.is_err() {
+        return;
+    }
+    loop {
+        let mut messages = Vec::new();
+        let state = match events.recv().await {
+            Ok(event) if event.sensor == sensor.config.id => {
+                let payload = json!({
+                    "event": event.state,
+                    "timestamp_ms": event.timestamp_ms,
+                    "port": event.port,
+                    "error": event.error,
+                    "retry_in_ms": event.retry_in_ms,
+                });
+                messages.push(Message::new(event_topic.clone(), payload.to_string(), false));
+                event.state
+            }
+            Ok(_) => continue,
+            // Events were missed, the availability is read again.
+            Err(RecvError::Lagged(_)) => sensor.state(),
+            Err(RecvError::Closed) => return,
+        };
+        if (state == ConnectionState::Connected) != connected {
+            connected = !connected;
+            messages.insert(0, availability(connected));
+        }
+        for message in messages {
+            if outgoing.send(message).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn state_topic(config: &MqttConfig, sensor: &Sensor) -> String {
+    sensor
+        .config
+        .mqtt_topic
+        .clone()
+        .unwrap_or_else(|| format!("{}/{}/state", config.prefix, sensor.config.id))
+}
+
+// discovery() Returns the Home Assistant discovery topics and payloads of the entities of a sensor.
+fn discovery(config: &MqttConfig, sensor: &Sensor) -> Vec<(String, Value)> {
+    let id = &sensor.config.id;
+    let node_id: String = format!("{}_{id}", config.prefix)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let device = json!({
+        "identifiers": [node_id],
+        "name": id,
+        "model": sensor.config.kind.name().to_uppercase(),
+    });
+    let distance = |name: &str, unit: &str, template: &str| {
+        json!({
+            "name": name,
+            "device_class": "distance",
+            "state_class": "measurement",
+            "unit_of_measurement": unit,
+            "value_template": template,
+        })
+    };
+    let energy = |name: &str, template: &str| {
+        json!({
+            "name": name,
+            "state_class": "measurement",
+            "unit_of_measurement": "%",
+            "value_template": template,
+        })
+    };
+    let presence = |template: &str| {
+        json!({
+            "name": "Presence",
+            "device_class": "occupancy",
+            "value_template": template,
+        })
+    };
+    // RD03D targets with a zero distance are empty slots.
+    let rd03d_count = "value_json | selectattr('distance', '>', 0) | list | length";
+    let entities: Vec<(&str, &str, Value)> = match sensor.config.kind {
+        SensorKind::Tfluna => vec![("sensor", "distance", distance("Distance", "cm", "{{ value_json.distance }}"))],
+        SensorKind::Tof200f => vec![("sensor", "distance", distance("Distance", "mm", "{{ value_json.distance }}"))],
+        SensorKind::Ld2410c => vec![
+            (
+                "binary_sensor",
+                "presence",
+                presence("{{ 'ON' if value_json.target_data.target_status != 'NoTarget' else 'OFF' }}"),
+            ),
+            (
+                "sensor",
+                "detection_distance",
+                distance("Detection distance", "cm", "{{ value_json.target_data.detection_distance }}"),
+            ),
+            (
+                "sensor",
+                "moving_distance",
+                distance("Moving target distance", "cm", "{{ value_json.target_data.movement_target_distance }}"),
+            ),
+            (
+                "sensor",
+                "moving_energy",
+                energy("Moving target energy", "{{ value_json.target_data.movement_target_energy }}"),
+            ),
+            (
+                "sensor",
+                "stationary_distance",
+                distance("Stationary target distance", "cm", "{{ value_json.target_data.stationary_target_distance }}"),
+            ),
+            (
+                "sensor",
+                "stationary_energy",
+                energy("Stationary target energy", "{{ value_json.target_data.stationary_target_energy }}"),
+            ),
+        ],
+        SensorKind::Presence => vec![
+            ("binary_sensor", "presence", presence("{{ 'ON' if value_json.present else 'OFF' }}")),
+            (
+                "binary_sensor",
+                "moving",
+                json!({
+                    "name": "Moving",
+                    "device_class": "motion",
+                    "value_template": "{{ 'ON' if value_json.motion == 'moving' else 'OFF' }}",
+                }),
+            ),
+            ("sensor", "distance", distance("Distance", "cm", "{{ value_json.distance_cm if value_json.present else 'unknown' }}")),
+            ("sensor", "confidence", energy("Confidence", "{{ (value_json.confidence * 100) | round }}")),
+        ],
+        SensorKind::Room => vec![
+            ("binary_sensor", "presence", presence("{{ 'ON' if value_json.targets | length > 0 else 'OFF' }}")),
+            (
+                "sensor",
+                "target_count",
+                json!({
+                    "name": "People",
+                    "state_class": "measurement",
+                    "value_template": "{{ value_json.targets | length }}",
+                }),
+            ),
+        ],
+        SensorKind::Rd03d => vec![
+            ("binary_sensor", "presence", presence(&format!("{{{{ 'ON' if {rd03d_count} > 0 else 'OFF' }}}}"))),
+            (
+                "sensor",
+                "target_count",
+                json!({
+                    "name": "Targets",
+                    "state_class": "measurement",
+                    "value_template": format!("{{{{ {rd03d_count} }}}}"),
+                }),
+            ),
+        ],
+    };
+    let state_topic = state_topic(config, sensor);
+    let availability = json!([
+        { "topic": format!("{}/status", config.prefix) },
+        { "topic": format!("{}/{id}/availability", config.prefix) },
+    ]);
+    entities
+        .into_iter()
+        .map(|(component, object_id, mut payload)| {
+            payload["unique_id"] = json!(format!("{node_id}_{object_id}"));
+            payload["state_topic"] = json!(state_topic);
+            payload["availability"] = availability.clone();
+            payload["availability_mode"] = json!("all");
+            payload["device"] = device.clone();
+            let topic = format!("{}/{component}/{node_id}/{object_id}/config", config.discovery_prefix);
+            (topic, payload)
+        })
+        .collect()
+}
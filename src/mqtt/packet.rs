@@ -0,0 +1,280 @@
+// packet.rs
+// Encoding and decoding of the MQTT 3.1.1 packets used by the publisher: CONNECT (with a last will),
+// CONNACK, PUBLISH (QoS 0 when sending), SUBSCRIBE and PINGREQ.
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const PROTOCOL_LEVEL: u8 = 4; // MQTT 3.1.1
+
+pub const CONNACK: u8 = 0x20;
+pub const PUBLISH: u8 = 0x30;
+
+// Largest packet accepted from the broker.
+const MAX_PACKET_LEN: usize = 256 * 1024;
+
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+}
+
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    pub keep_alive_secs: u16,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    pub will: Option<Will<'a>>,
+}
+
+// Packet is a packet received from the broker: the first byte of the fixed header and the rest of the packet.
+pub struct Packet {
+    pub header: u8,
+    pub body: Vec<u8>,
+}
+
+// Publish is an incoming PUBLISH packet.
+pub struct Publish {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+// connect() Encodes a CONNECT packet with a clean session.
+pub fn connect(connect: &Connect) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    body.push(PROTOCOL_LEVEL);
+    let mut flags = 0x02; // clean session
+    if let Some(will) = &connect.will {
+        flags |= 0x04;
+        if will.retain {
+            flags |= 0x20;
+        }
+    }
+    if connect.password.is_some() {
+        flags |= 0x40;
+    }
+    if connect.username.is_some() {
+        flags |= 0x80;
+    }
+    body.push(flags);
+    body.extend_from_slice(&connect.keep_alive_secs.to_be_bytes());
+    put_string(&mut body, connect.client_id);
+    if let Some(will) = &connect.will {
+        put_string(&mut body, will.topic);
+        put_bytes(&mut body, will.payload);
+    }
+    if let Some(username) = connect.username {
+        put_string(&mut body, username);
+    }
+    if let Some(password) = connect.password {
+        put_string(&mut body, password);
+    }
+    packet(0x10, &body)
+}
+
+// publish() Encodes a QoS 0 PUBLISH packet.
+pub fn publish(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+    put_string(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH | retain as u8, &body)
+}
+
+// subscribe() Encodes a SUBSCRIBE packet to `filters` with QoS 0.
+pub fn subscribe(packet_id: u16, filters: &[&str]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for filter in filters {
+        put_string(&mut body, filter);
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+pub fn pingreq() -> Vec<u8> {
+    vec![0xC0, 0x00]
+}
+
+// connack_code() Returns the return code of a CONNACK packet (0 when the connection is accepted).
+pub fn connack_code(packet: &Packet) -> Option<u8> {
+    (packet.header == CONNACK && packet.body.len() == 2).then(|| packet.body[1])
+}
+
+// decode_publish() Decodes an incoming PUBLISH packet (any QoS).
+pub fn decode_publish(packet: &Packet) -> Option<Publish> {
+    if packet.header & 0xF0 != PUBLISH {
+        return None;
+    }
+    let topic_len = u16::from_be_bytes([*packet.body.first()?, *packet.body.get(1)?]) as usize;
+    let topic = packet.body.get(2..2 + topic_len)?;
+    let qos = (packet.header >> 1) & 0x03;
+    // The packet identifier follows the topic for QoS 1 and 2.
+    let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+    Some(Publish {
+        topic: String::from_utf8(topic.to_vec()).ok()?,
+        payload: packet.body.get(payload_start..)?.to_vec(),
+    })
+}
+
+// read_packet() Reads the next packet from the broker.
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<Packet> {
+    let header = reader.read_u8().await?;
+    let mut len = 0usize;
+    for shift in (0..28).step_by(7) {
+        let byte = reader.read_u8().await?;
+        len |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if shift == 21 {
+            anyhow::bail!("invalid MQTT remaining length");
+        }
+    }
+    if len > MAX_PACKET_LEN {
+        anyhow::bail!("MQTT packet too large ({len} bytes)");
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).await?;
+    Ok(Packet { header, body })
+}
+
+// packet() Prepends the fixed header (packet type and remaining length) to `body`.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    put_bytes(buffer, value.as_bytes());
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_connect() {
+        let connect = Connect {
+            client_id: "c",
+            keep_alive_secs: 60,
+            username: None,
+            password: None,
+            will: None,
+        };
+        assert_eq!(
+            super::connect(&connect),
+            [0x10, 0x0D, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x3C, 0x00, 0x01, b'c']
+        );
+    }
+
+    #[test]
+    fn encode_connect_with_will_and_credentials() {
+        let connect = Connect {
+            client_id: "c",
+            keep_alive_secs: 60,
+            username: Some("u"),
+            password: Some("p"),
+            will: Some(Will {
+                topic: "s",
+                payload: b"off",
+                retain: true,
+            }),
+        };
+        let mut expected = vec![0x10, 0x1B, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0xE6, 0x00, 0x3C];
+        expected.extend_from_slice(&[0x00, 0x01, b'c', 0x00, 0x01, b's', 0x00, 0x03, b'o', b'f', b'f']);
+        expected.extend_from_slice(&[0x00, 0x01, b'u', 0x00, 0x01, b'p']);
+        assert_eq!(super::connect(&connect), expected);
+    }
+
+    #[test]
+    fn encode_publish() {
+        assert_eq!(publish("a/b", b"on", false), [0x30, 0x07, 0x00, 0x03, b'a', b'/', b'b', b'o', b'n']);
+        assert_eq!(publish("a/b", b"on", true)[0], 0x31);
+    }
+
+    #[test]
+    fn encode_subscribe() {
+        assert_eq!(
+            subscribe(1, &["s/#"]),
+            [0x82, 0x08, 0x00, 0x01, 0x00, 0x03, b's', b'/', b'#', 0x00]
+        );
+    }
+
+    #[test]
+    fn encode_multi_byte_remaining_length() {
+        // 2 + 1 + 200 = 203 bytes
+        let packet = publish("t", &[0; 200], false);
+        assert_eq!(packet[..3], [0x30, 0xCB, 0x01]);
+        assert_eq!(packet.len(), 3 + 203);
+        // 2 + 1 + 16381 = 16384 bytes
+        let packet = publish("t", &[0; 16381], false);
+        assert_eq!(packet[..4], [0x30, 0x80, 0x80, 0x01]);
+    }
+
+    #[tokio::test]
+    async fn decode_connack() {
+        let packet = read_packet(&mut &[0x20, 0x02, 0x00, 0x00][..]).await.unwrap();
+        assert_eq!(connack_code(&packet), Some(0));
+        let packet = read_packet(&mut &[0x20, 0x02, 0x00, 0x05][..]).await.unwrap();
+        assert_eq!(connack_code(&packet), Some(5));
+    }
+
+    #[tokio::test]
+    async fn decode_publish_qos0_and_qos1() {
+        let data = [0x30, 0x07, 0x00, 0x03, b'a', b'/', b'b', b'o', b'n'];
+        let publish = decode_publish(&read_packet(&mut &data[..]).await.unwrap()).unwrap();
+        assert_eq!((publish.topic.as_str(), publish.payload.as_slice()), ("a/b", &b"on"[..]));
+        // QoS 1: packet identifier 0x0102 after the topic
+        let data = [0x32, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x01, 0x02, b'o', b'n'];
+        let publish = decode_publish(&read_packet(&mut &data[..]).await.unwrap()).unwrap();
+        assert_eq!((publish.topic.as_str(), publish.payload.as_slice()), ("a/b", &b"on"[..]));
+    }
+
+    #[tokio::test]
+    async fn decode_puback() {
+        let packet = read_packet(&mut &[0x40, 0x02, 0x00, 0x01][..]).await.unwrap();
+        assert_eq!((packet.header, packet.body.as_slice()), (0x40, &[0x00, 0x01][..]));
+        assert!(decode_publish(&packet).is_none());
+        assert_eq!(connack_code(&packet), None);
+    }
+
+    #[tokio::test]
+    async fn decode_multi_byte_remaining_length() {
+        let mut data = vec![0x30, 0xCB, 0x01, 0x00, 0x01, b't'];
+        data.extend_from_slice(&[0xAA; 200]);
+        let packet = read_packet(&mut data.as_slice()).await.unwrap();
+        assert_eq!(packet.body.len(), 203);
+        assert_eq!(decode_publish(&packet).unwrap().payload, [0xAA; 200]);
+    }
+
+    #[tokio::test]
+    async fn reject_malformed_packets() {
+        // Remaining length longer than 4 bytes
+        assert!(read_packet(&mut &[0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..]).await.is_err());
+        // Larger than MAX_PACKET_LEN
+        assert!(read_packet(&mut &[0x30, 0xFF, 0xFF, 0x7F][..]).await.is_err());
+        // Body shorter than the remaining length
+        assert!(read_packet(&mut &[0x30, 0x05, 0x00, 0x01][..]).await.is_err());
+        // Topic longer than the packet, invalid UTF-8, truncated QoS 1 packet
+        let packet = |header, body: &[u8]| Packet { header, body: body.to_vec() };
+        assert!(decode_publish(&packet(0x30, &[0x00, 0x05, b'a'])).is_none());
+        assert!(decode_publish(&packet(0x30, &[0x00, 0x01, 0xFF])).is_none());
+        assert!(decode_publish(&packet(0x32, &[0x00, 0x01, b'a', 0x00])).is_none());
+        assert_eq!(connack_code(&packet(0x20, &[0x00])), None);
+    }
+}
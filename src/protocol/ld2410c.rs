@@ -0,0 +1,1037 @@
+// protocol/ld2410c.rs
+// Frame structs, command encoders and report decoders of the LD2410C radar module.
+// This module is no_std and allocation-free: command frames and gate energies are stored
+// in fixed-size buffers (heapless::Vec) so it can run on a microcontroller.
+use core::fmt;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+// DecodeError is returned when a report frame does not match the layout described in
+// 2.3 Radar Data Output Protocol (truncated frame, wrong head/tail, unknown data type...), or a
+// command frame the layout of 2.1 Command Protocol Frame Format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    TooShort { expected: usize, actual: usize },
+    InvalidHead(u8),
+    InvalidTail(u8),
+    InvalidCalibration(u8),
+    InvalidFrameEnd,
+    UnknownDataType(u8),
+    TooManyGates(u8),
+    MissingAck(u16),
+    AckFailed(u16),
+    InvalidFrameHeader,
+    UnknownCommand(u16),
+    InvalidCommandValue(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { expected, actual } => {
+                write!(f, "frame too short: expected {expected} bytes, got {actual}")
+            }
+            DecodeError::InvalidHead(head) => write!(f, "invalid data head: {head:#04X}"),
+            DecodeError::InvalidTail(tail) => write!(f, "invalid data tail: {tail:#04X}"),
+            DecodeError::InvalidCalibration(calibration) => {
+                write!(f, "invalid calibration byte: {calibration:#04X}")
+            }
+            DecodeError::InvalidFrameEnd => write!(f, "invalid frame end"),
+            DecodeError::UnknownDataType(data_type) => {
+                write!(f, "unknown data type: {data_type:#04X}")
+            }
+            DecodeError::TooManyGates(gate) => {
+                write!(f, "maximum distance gate {gate} exceeds {}", MAX_GATES - 1)
+            }
+            DecodeError::MissingAck(word) => write!(f, "no ACK for command {word:#06X}"),
+            DecodeError::AckFailed(word) => write!(f, "command {word:#06X} failed"),
+            DecodeError::InvalidFrameHeader => write!(f, "invalid frame header"),
+            DecodeError::UnknownCommand(word) => write!(f, "unknown command {word:#06X}"),
+            DecodeError::InvalidCommandValue(word) => {
+                write!(f, "invalid value for command {word:#06X}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+// CommandError is returned when a command value is out of the range accepted by the radar.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    InvalidPassword,
+    InvalidSensitivity,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::InvalidPassword => write!(f, "Password must be exactly 6 bytes long"),
+            CommandError::InvalidSensitivity => {
+                write!(f, "Sensitivity values must be between 0 and 100")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CommandError {}
+
+// Gates 0 to 8 are reported in engineering mode.
+pub const MAX_GATES: usize = 9;
+// Longest command value (distance gate sensitivity: 3 words and 3 double words).
+pub const MAX_COMMAND_VALUE_LEN: usize = 18;
+// Header (4) + intraframe length (2) + command word (2) + command value + end (4).
+pub const MAX_COMMAND_FRAME_LEN: usize = 12 + MAX_COMMAND_VALUE_LEN;
+pub const COMMAND_FRAME_HEADER: [u8; 4] = [0xFD, 0xFC, 0xFB, 0xFA];
+pub const COMMAND_FRAME_END: [u8; 4] = [0x04, 0x03, 0x02, 0x01];
+pub const REPORT_FRAME_HEADER: [u8; 4] = [0xF4, 0xF3, 0xF2, 0xF1];
+pub const REPORT_FRAME_END: [u8; 4] = [0xF8, 0xF7, 0xF6, 0xF5];
+// Longest ACK (read parameter): header (4) + length (2) + 28 bytes of intraframe data + end (4).
+pub const MAX_ACK_FRAME_LEN: usize = 64;
+
+// ack_word() The radar answers a command with an ACK whose command word is the sent word | 0x0100.
+pub fn ack_word(word: u16) -> u16 {
+    word | 0x0100
+}
+
+// find_ack() Returns the ACK of the command `word` found in `response`, starting at its header,
+// after checking that the radar reported a success (status 00 00).
+pub fn find_ack(response: &[u8], word: u16) -> Result<&[u8], DecodeError> {
+    let ack_word = ack_word(word).to_le_bytes();
+    let start = response
+        .windows(8)
+        .position(|w| w[..4] == COMMAND_FRAME_HEADER && w[6..8] == ack_word)
+        .ok_or(DecodeError::MissingAck(word))?;
+    let ack = &response[start..];
+    check_length(ack, 10)?;
+    if ack[8] != 0x00 || ack[9] != 0x00 {
+        return Err(DecodeError::AckFailed(word));
+    }
+    Ok(ack)
+}
+
+// check_length() Returns a decode error instead of letting the caller index out of bounds.
+fn check_length(data: &[u8], expected: usize) -> Result<(), DecodeError> {
+    if data.len() < expected {
+        return Err(DecodeError::TooShort {
+            expected,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DataType {
+    EngineeringMode = 0x01,
+    TargetBasicInformation = 0x02,
+    NoDataType,
+}
+impl DataType {
+    fn find_type(data: &[u8]) -> Result<Self, DecodeError> {
+        check_length(data, 1)?;
+        match data[0] {
+            0x01 => Ok(Self::EngineeringMode),
+            0x02 => Ok(Self::TargetBasicInformation),
+            other => Err(DecodeError::UnknownDataType(other)),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
+pub enum TargetStatus {
+    NoTarget,
+    #[serde(alias = "CampaignTarget")]
+    MovingTarget,
+    #[serde(alias = "StationnaryTarget")]
+    StationaryTarget,
+    #[serde(alias = "CampaignAndStationaryTarget")]
+    MovingAndStationaryTarget,
+}
+impl TargetStatus {
+    fn find_status(data: &[u8]) -> Result<Self, DecodeError> {
+        check_length(data, 3)?;
+        Ok(match data[2] {
+            0x00 => Self::NoTarget,
+            0x01 => Self::MovingTarget,
+            0x02 => Self::StationaryTarget,
+            0x03 => Self::MovingAndStationaryTarget,
+            _ => Self::NoTarget, // Default to NoTarget if unknown
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineeringModel {
+    pub maximum_mov_distance_gate: u8,
+    pub maximum_static_distance_gate: u8,
+    #[serde(alias = "mouvement_distance_gates")]
+    pub moving_distance_gates: Vec<u8, MAX_GATES>,
+    pub static_distance_gates: Vec<u8, MAX_GATES>,
+    pub light_sensor_value: Option<u8>,
+    pub out_pin_state: Option<OutPinLevel>,
+}
+
+impl EngineeringModel {
+    // Offset of the maximum moving distance gate N, right after the basic target information.
+    const MAX_GATE_OFFSET: usize = 11;
+
+    // new() Decodes the engineering part of a report frame (tail and calibration excluded):
+    // maximum moving gate M (1), maximum static gate S (1), moving gate energies 0..=M (M+1),
+    // static gate energies 0..=S (S+1), then the extra data. Only its first 2 bytes are known
+    // (photosensitive value, OUT pin level), the following ones are ignored.
+    fn new(data: &[u8]) -> Result<Self, DecodeError> {
+        check_length(data, Self::MAX_GATE_OFFSET + 2)?;
+        let maximum_mov_distance_gate = data[Self::MAX_GATE_OFFSET];
+        let maximum_static_distance_gate = data[Self::MAX_GATE_OFFSET + 1];
+        for gate in [maximum_mov_distance_gate, maximum_static_distance_gate] {
+            if gate as usize >= MAX_GATES {
+                return Err(DecodeError::TooManyGates(gate));
+            }
+        }
+        let mov_start = Self::MAX_GATE_OFFSET + 2;
+        let static_start = mov_start + maximum_mov_distance_gate as usize + 1;
+        let extra_start = static_start + maximum_static_distance_gate as usize + 1;
+        check_length(data, extra_start)?;
+        // The extra data carries the photosensitive value (0-255) and the OUT pin level
+        // on firmware that supports the auxiliary control.
+        let extra_data = &data[extra_start..];
+        Ok(Self {
+            maximum_mov_distance_gate,
+            maximum_static_distance_gate,
+            moving_distance_gates: data[mov_start..static_start].iter().copied().collect(),
+            static_distance_gates: data[static_start..extra_start].iter().copied().collect(),
+            light_sensor_value: extra_data.first().copied(),
+            out_pin_state: extra_data.get(1).map(|&level| OutPinLevel::from_u8(level)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetData {
+    pub target_status: TargetStatus,
+    pub movement_target_distance: u16, // cm
+    #[serde(alias = "exercise_target")]
+    pub movement_target_energy: u8,
+    pub stationary_target_distance: u16, // cm
+    #[serde(alias = "stationary_target")]
+    pub stationary_target_energy: u8,
+    pub detection_distance: u16, // cm
+    pub engineering_model: Option<EngineeringModel>,
+}
+impl TargetData {
+    // new() Decodes the target information. `data` is the intraframe data without the tail
+    // and calibration bytes.
+    fn new(target_status: TargetStatus, data_type: &DataType, data: &[u8]) -> Result<Self, DecodeError> {
+        check_length(data, 11)?;
+        Ok(Self {
+            target_status,
+            movement_target_distance: u16::from_be_bytes([data[4], data[3]]),
+            movement_target_energy: data[5],
+            stationary_target_distance: u16::from_be_bytes([data[7], data[6]]),
+            stationary_target_energy: data[8],
+            detection_distance: u16::from_be_bytes([data[10], data[9]]),
+            engineering_model: if *data_type == DataType::EngineeringMode {
+                Some(EngineeringModel::new(data)?)
+            } else {
+                None
+            },
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ld2410CData {
+    pub data_type: DataType,
+    pub head: u8,
+    pub target_data: TargetData,
+    pub tail: u8,
+    pub calibration: u8,
+}
+
+impl Ld2410CData {
+    const HEAD: u8 = 0xAA;
+    const TAIL: u8 = 0x55;
+    const CALIBRATION: u8 = 0x00;
+
+    // new() Decodes the intraframe data of a report frame:
+    // data type (1) | head 0xAA (1) | target data | tail 0x55 (1) | calibration 0x00 (1)
+    fn new(data: &[u8]) -> Result<Self, DecodeError> {
+        check_length(data, 13)?;
+        let data_type = DataType::find_type(data)?;
+        let head = data[1];
+        if head != Self::HEAD {
+            return Err(DecodeError::InvalidHead(head));
+        }
+        let tail = data[data.len() - 2];
+        if tail != Self::TAIL {
+            return Err(DecodeError::InvalidTail(tail));
+        }
+        let calibration = data[data.len() - 1];
+        if calibration != Self::CALIBRATION {
+            return Err(DecodeError::InvalidCalibration(calibration));
+        }
+        let target_status = TargetStatus::find_status(data)?;
+        let target_data = TargetData::new(target_status, &data_type, &data[..data.len() - 2])?;
+        Ok(Self {
+            data_type,
+            head,
+            target_data,
+            tail,
+            calibration,
+        })
+    }
+
+    // from_frame() Finds the first report frame in `buf` (F4 F3 F2 F1 | length (2) | data | F8 F7 F6 F5)
+    // and decodes it. Returns None if `buf` does not contain the start of a report frame.
+    pub fn from_frame(buf: &[u8]) -> Option<Result<Self, DecodeError>> {
+        let start = buf.windows(4).position(|w| w == REPORT_FRAME_HEADER)?;
+        let frame = &buf[start..];
+        if let Err(e) = check_length(frame, 6) {
+            return Some(Err(e));
+        }
+        let length = u16::from_le_bytes([frame[4], frame[5]]) as usize;
+        if let Err(e) = check_length(frame, 6 + length + 4) {
+            return Some(Err(e));
+        }
+        if frame[6 + length..6 + length + 4] != REPORT_FRAME_END {
+            return Some(Err(DecodeError::InvalidFrameEnd));
+        }
+        Some(Self::new(&frame[6..6 + length]))
+    }
+}
+
+impl Default for Ld2410CData {
+    fn default() -> Self {
+        Self {
+            data_type: DataType::NoDataType,
+            head: 0x00,
+            target_data: TargetData {
+                target_status: TargetStatus::NoTarget,
+                movement_target_distance: 0,
+                movement_target_energy: 0,
+                stationary_target_distance: 0,
+                stationary_target_energy: 0,
+                detection_distance: 0,
+                engineering_model: None,
+            },
+            tail: 0x00,
+            calibration: 0x00,
+        }
+    }
+}
+
+pub struct Ld2410CFrame {
+    intraframe_data: Ld2410CCommand,
+}
+
+impl Ld2410CFrame {
+    fn new(command: Ld2410CCommand) -> Self {
+        Self {
+            intraframe_data: command,
+        }
+    }
+    // command() Builds a command frame, the intraframe length being the command word (2 bytes)
+    // plus the command value.
+    fn command<const N: usize>(word: u8, value: [u8; N]) -> Self {
+        const { assert!(N <= MAX_COMMAND_VALUE_LEN) };
+        Self::new(Ld2410CCommand::new([word, 0x00], value.into_iter().collect()))
+    }
+    pub fn word(&self) -> u16 {
+        u16::from_le_bytes(self.intraframe_data.word)
+    }
+    pub fn value(&self) -> &[u8] {
+        &self.intraframe_data.value
+    }
+    // to_u8() Encodes the frame: FD FC FB FA | intraframe length (2) | command word (2) | command value | 04 03 02 01
+    pub fn to_u8(&self) -> Vec<u8, MAX_COMMAND_FRAME_LEN> {
+        let intraframe_length = ((self.intraframe_data.value.len() + 2) as u16).to_le_bytes();
+        COMMAND_FRAME_HEADER
+            .iter()
+            .chain(intraframe_length.iter())
+            .chain(self.intraframe_data.word.iter())
+            .chain(self.intraframe_data.value.iter())
+            .chain(COMMAND_FRAME_END.iter())
+            .copied()
+            .collect()
+    }
+
+    // The command frames below are shared by the async (tokio), blocking and embedded drivers.
+    pub fn enabling_configuration() -> Self {
+        Self::command(0xFF, [0x01, 0x00])
+    }
+    pub fn ending_configuration() -> Self {
+        Self::command(0xFE, [])
+    }
+    pub fn read_firmware_version() -> Self {
+        Self::command(0xA0, [])
+    }
+    pub fn bluetooth_module(module: BluetoothModule) -> Self {
+        Self::command(0xA4, module.to_bytes())
+    }
+    pub fn bluetooth_password(password: &str) -> Result<Self, CommandError> {
+        let password: [u8; 6] = password
+            .as_bytes()
+            .try_into()
+            .map_err(|_| CommandError::InvalidPassword)?;
+        Ok(Self::command(0xA9, password))
+    }
+    pub fn obtaining_bluetooth_permissions(password: &str) -> Result<Self, CommandError> {
+        let password: [u8; 6] = password
+            .as_bytes()
+            .try_into()
+            .map_err(|_| CommandError::InvalidPassword)?;
+        Ok(Self::command(0xA8, password))
+    }
+    pub fn engineering_mode() -> Self {
+        Self::command(0x62, [])
+    }
+    pub fn engineering_mode_off() -> Self {
+        Self::command(0x63, [])
+    }
+    pub fn read_parameter() -> Self {
+        Self::command(0x61, [])
+    }
+    pub fn restart_module() -> Self {
+        Self::command(0xA3, [])
+    }
+    pub fn restore_factory_settings() -> Self {
+        Self::command(0xA2, [])
+    }
+    pub fn distance_resolution_setting(distance_resolution: DistanceResolution) -> Self {
+        Self::command(0xAA, distance_resolution.to_bytes())
+    }
+    pub fn query_distance_resolution_setting() -> Self {
+        Self::command(0xAB, [])
+    }
+    pub fn mac_address() -> Self {
+        Self::command(0xA5, [0x01, 0x00])
+    }
+    pub fn serial_port_baud_rate(baud_rate: BaudRate) -> Self {
+        Self::command(0xA1, baud_rate.to_bytes())
+    }
+    pub fn distance_gate_sensitivity_configuration(
+        distance_gate: GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    ) -> Result<Self, CommandError> {
+        if motion_sensitivity > 100 || standstill_sensitivity > 100 {
+            return Err(CommandError::InvalidSensitivity);
+        }
+        let command_value = GateSensitivity::new(distance_gate, motion_sensitivity, standstill_sensitivity);
+        Ok(Self::command(0x64, command_value.to_bytes()))
+    }
+    pub fn auxiliary_control(auxiliary_control: AuxiliaryControl) -> Self {
+        Self::command(0xAD, auxiliary_control.to_bytes())
+    }
+    pub fn query_auxiliary_control() -> Self {
+        Self::command(0xAE, [])
+    }
+
+    // parse() Decodes a command frame, as sent to the radar, back into the command: the inverse of to_u8().
+    // Fails if the frame layout is invalid, the command word unknown or the value not one the radar accepts.
+    pub fn parse(frame: &[u8]) -> Result<DecodedCommand, DecodeError> {
+        check_length(frame, 12)?;
+        if frame[..4] != COMMAND_FRAME_HEADER {
+            return Err(DecodeError::InvalidFrameHeader);
+        }
+        let intraframe_length = u16::from_le_bytes([frame[4], frame[5]]) as usize;
+        check_length(frame, 6 + intraframe_length + 4)?;
+        if intraframe_length < 2
+            || frame.len() != 6 + intraframe_length + 4
+            || frame[6 + intraframe_length..] != COMMAND_FRAME_END
+        {
+            return Err(DecodeError::InvalidFrameEnd);
+        }
+        DecodedCommand::new(u16::from_le_bytes([frame[6], frame[7]]), &frame[8..6 + intraframe_length])
+    }
+}
+
+// DecodedCommand is a command frame decoded by Ld2410CFrame::parse(), one variant per constructor of
+// Ld2410CFrame with the values it was built from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedCommand {
+    EnablingConfiguration,
+    EndingConfiguration,
+    ReadFirmwareVersion,
+    BluetoothModule(BluetoothModule),
+    BluetoothPassword([u8; 6]),
+    ObtainingBluetoothPermissions([u8; 6]),
+    EngineeringMode,
+    EngineeringModeOff,
+    ReadParameter,
+    RestartModule,
+    RestoreFactorySettings,
+    DistanceResolutionSetting(DistanceResolution),
+    QueryDistanceResolutionSetting,
+    MacAddress,
+    SerialPortBaudRate(BaudRate),
+    DistanceGateSensitivityConfiguration {
+        distance_gate: GateValue,
+        motion_sensitivity: u8,
+        standstill_sensitivity: u8,
+    },
+    AuxiliaryControl(AuxiliaryControl),
+    QueryAuxiliaryControl,
+}
+
+impl DecodedCommand {
+    // new() Decodes the command value of the command `word`.
+    fn new(word: u16, value: &[u8]) -> Result<Self, DecodeError> {
+        let invalid = || DecodeError::InvalidCommandValue(word);
+        // Commands with a fixed value
+        let fixed = |expected: &[u8], command| if value == expected { Ok(command) } else { Err(invalid()) };
+        match word {
+            0x00FF => fixed(&[0x01, 0x00], DecodedCommand::EnablingConfiguration),
+            0x00FE => fixed(&[], DecodedCommand::EndingConfiguration),
+            0x00A0 => fixed(&[], DecodedCommand::ReadFirmwareVersion),
+            0x00A4 => BluetoothModule::from_bytes(value)
+                .map(DecodedCommand::BluetoothModule)
+                .ok_or_else(invalid),
+            0x00A9 => value
+                .try_into()
+                .map(DecodedCommand::BluetoothPassword)
+                .map_err(|_| invalid()),
+            0x00A8 => value
+                .try_into()
+                .map(DecodedCommand::ObtainingBluetoothPermissions)
+                .map_err(|_| invalid()),
+            0x0062 => fixed(&[], DecodedCommand::EngineeringMode),
+            0x0063 => fixed(&[], DecodedCommand::EngineeringModeOff),
+            0x0061 => fixed(&[], DecodedCommand::ReadParameter),
+            0x00A3 => fixed(&[], DecodedCommand::RestartModule),
+            0x00A2 => fixed(&[], DecodedCommand::RestoreFactorySettings),
+            0x00AA => DistanceResolution::from_bytes(value)
+                .map(DecodedCommand::DistanceResolutionSetting)
+                .ok_or_else(invalid),
+            0x00AB => fixed(&[], DecodedCommand::QueryDistanceResolutionSetting),
+            0x00A5 => fixed(&[0x01, 0x00], DecodedCommand::MacAddress),
+            0x00A1 => BaudRate::from_bytes(value)
+                .map(DecodedCommand::SerialPortBaudRate)
+                .ok_or_else(invalid),
+            0x0064 => GateSensitivity::from_bytes(value)
+                .map(|sensitivity| DecodedCommand::DistanceGateSensitivityConfiguration {
+                    distance_gate: sensitivity.distance_gate_value,
+                    motion_sensitivity: sensitivity.motion_sensitivity_value[0],
+                    standstill_sensitivity: sensitivity.standstill_sensitivity_value[0],
+                })
+                .ok_or_else(invalid),
+            0x00AD => AuxiliaryControl::from_bytes(value)
+                .map(DecodedCommand::AuxiliaryControl)
+                .ok_or_else(invalid),
+            0x00AE => fixed(&[], DecodedCommand::QueryAuxiliaryControl),
+            _ => Err(DecodeError::UnknownCommand(word)),
+        }
+    }
+}
+struct Ld2410CCommand {
+    word: [u8; 2],
+    value: Vec<u8, MAX_COMMAND_VALUE_LEN>,
+}
+impl Ld2410CCommand {
+    fn new(word: [u8; 2], value: Vec<u8, MAX_COMMAND_VALUE_LEN>) -> Self {
+        Self { word, value }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateValue{
+    GateValue0,
+    GateValue1,
+    GateValue2,
+    GateValue3,
+    GateValue4,
+    GateValue5,
+    GateValue6,
+    GateValue7,
+    GateValue8,
+    GateValueAll,
+}
+
+impl GateValue {
+    // from_gate() Returns the value of the distance gate 0 to 8, None if out of range.
+    pub fn from_gate(gate: u8) -> Option<Self> {
+        match gate {
+            0 => Some(GateValue::GateValue0),
+            1 => Some(GateValue::GateValue1),
+            2 => Some(GateValue::GateValue2),
+            3 => Some(GateValue::GateValue3),
+            4 => Some(GateValue::GateValue4),
+            5 => Some(GateValue::GateValue5),
+            6 => Some(GateValue::GateValue6),
+            7 => Some(GateValue::GateValue7),
+            8 => Some(GateValue::GateValue8),
+            _ => None,
+        }
+    }
+    fn to_bytes(&self) -> [u8; 4] {
+        match self {
+            GateValue::GateValue0 => [0x00, 0x00,0x00,0x00],
+            GateValue::GateValue1 => [0x01, 0x00,0x00,0x00],
+            GateValue::GateValue2 => [0x02, 0x00,0x00,0x00],
+            GateValue::GateValue3 => [0x03, 0x00,0x00,0x00],
+            GateValue::GateValue4 => [0x04, 0x00,0x00,0x00],
+            GateValue::GateValue5 => [0x05, 0x00,0x00,0x00],
+            GateValue::GateValue6 => [0x06, 0x00,0x00,0x00],
+            GateValue::GateValue7 => [0x07, 0x00,0x00,0x00],
+            GateValue::GateValue8 => [0x08, 0x00,0x00,0x00],
+            GateValue::GateValueAll => [0xFF, 0xFF,0x00,0x00],
+        }
+    }
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xFF, 0xFF, 0x00, 0x00] => Some(GateValue::GateValueAll),
+            [gate, 0x00, 0x00, 0x00] => Self::from_gate(*gate),
+            _ => None,
+        }
+    }
+}
+struct GateSensitivity {
+    distance_gate_word: [u8; 2],
+    distance_gate_value: GateValue,
+    motion_sensitivity_word: [u8; 2],
+    motion_sensitivity_value: [u8; 4],
+    standstill_sensitivity_word: [u8; 2],
+    standstill_sensitivity_value: [u8; 4],
+}
+impl GateSensitivity {
+    fn new(
+        distance_gate_value: GateValue,
+        motion_sensitivity_value: u8,
+        standstill_sensitivity_value: u8,
+    ) -> Self {
+        Self {
+            distance_gate_word: [0x00, 0x00],
+            distance_gate_value,
+            motion_sensitivity_word: [0x01, 0x00],
+            motion_sensitivity_value: [motion_sensitivity_value, 0x00, 0x00, 0x00],
+            standstill_sensitivity_word: [0x02, 0x00],
+            standstill_sensitivity_value: [standstill_sensitivity_value, 0x00, 0x00, 0x00],
+        }
+    }
+    fn to_bytes(&self) -> [u8; 18] {
+        let mut bytes = [0u8; 18];
+        let parts: [&[u8]; 6] = [
+            &self.distance_gate_word,
+            &self.distance_gate_value.to_bytes(),
+            &self.motion_sensitivity_word,
+            &self.motion_sensitivity_value,
+            &self.standstill_sensitivity_word,
+            &self.standstill_sensitivity_value,
+        ];
+        for (dst, src) in bytes.iter_mut().zip(parts.iter().flat_map(|part| part.iter())) {
+            *dst = *src;
+        }
+        bytes
+    }
+    // from_bytes() Decodes the command value built by to_bytes(), None if a word is wrong or a
+    // sensitivity is out of range.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let sensitivity = |value: &[u8]| match value {
+            [sensitivity @ 0..=100, 0x00, 0x00, 0x00] => Some(*sensitivity),
+            _ => None,
+        };
+        if bytes.len() != 18 || bytes[..2] != [0x00, 0x00] || bytes[6..8] != [0x01, 0x00] || bytes[12..14] != [0x02, 0x00] {
+            return None;
+        }
+        Some(Self::new(
+            GateValue::from_bytes(&bytes[2..6])?,
+            sensitivity(&bytes[8..12])?,
+            sensitivity(&bytes[14..18])?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BaudRate {
+    BaudRate115200,
+    BaudRate230400,
+    BaudRate256000,
+    BaudRate460800,
+    BaudRate57600,
+    BaudRate38400,
+    BaudRate19200,
+    BaudRate9600,
+}
+impl BaudRate {
+    // Baud rates supported by the radar, the factory default first: the order of the baud rate detection.
+    pub const ALL: [BaudRate; 8] = [
+        BaudRate::BaudRate256000,
+        BaudRate::BaudRate115200,
+        BaudRate::BaudRate460800,
+        BaudRate::BaudRate230400,
+        BaudRate::BaudRate57600,
+        BaudRate::BaudRate38400,
+        BaudRate::BaudRate19200,
+        BaudRate::BaudRate9600,
+    ];
+
+    // to_baud() Returns the baud rate in bits per second.
+    pub fn to_baud(&self) -> u32 {
+        match self {
+            BaudRate::BaudRate9600 => 9600,
+            BaudRate::BaudRate19200 => 19200,
+            BaudRate::BaudRate38400 => 38400,
+            BaudRate::BaudRate57600 => 57600,
+            BaudRate::BaudRate115200 => 115200,
+            BaudRate::BaudRate230400 => 230400,
+            BaudRate::BaudRate256000 => 256000,
+            BaudRate::BaudRate460800 => 460800,
+        }
+    }
+
+    // from_baud() Returns the setting of a baud rate supported by the radar.
+    pub fn from_baud(baud_rate: u32) -> Option<Self> {
+        match baud_rate {
+            9600 => Some(BaudRate::BaudRate9600),
+            19200 => Some(BaudRate::BaudRate19200),
+            38400 => Some(BaudRate::BaudRate38400),
+            57600 => Some(BaudRate::BaudRate57600),
+            115200 => Some(BaudRate::BaudRate115200),
+            230400 => Some(BaudRate::BaudRate230400),
+            256000 => Some(BaudRate::BaudRate256000),
+            460800 => Some(BaudRate::BaudRate460800),
+            _ => None,
+        }
+    }
+    fn to_bytes(&self) -> [u8; 2] {
+        match self {
+            BaudRate::BaudRate9600 => [0x01, 0x00],
+            BaudRate::BaudRate19200 => [0x02, 0x00],
+            BaudRate::BaudRate38400 => [0x03, 0x00],
+            BaudRate::BaudRate57600 => [0x04, 0x00],
+            BaudRate::BaudRate115200 => [0x05, 0x00],
+            BaudRate::BaudRate230400 => [0x06, 0x00],
+            BaudRate::BaudRate256000 => [0x07, 0x00],
+            BaudRate::BaudRate460800 => [0x08, 0x00],
+        }
+    }
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::ALL.into_iter().find(|baud_rate| baud_rate.to_bytes() == bytes)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DistanceResolution {
+    #[serde(rename = "0.75m")]
+    DistanceGate0_75m,
+    #[serde(rename = "0.2m")]
+    DistanceGate0_2m,
+}
+impl DistanceResolution {
+    fn to_bytes(&self) -> [u8; 2] {
+        match self {
+            DistanceResolution::DistanceGate0_75m => [0x00,0x00],
+            DistanceResolution::DistanceGate0_2m => [0x01,0x00],
+        }
+    }
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        [DistanceResolution::DistanceGate0_75m, DistanceResolution::DistanceGate0_2m]
+            .into_iter()
+            .find(|distance_resolution| distance_resolution.to_bytes() == bytes)
+    }
+    // from_response() Parses the ACK of the query distance resolution command:
+    // FD FC FB FA | 06 00 | AB 01 | status (2) | resolution index (2) | 04 03 02 01
+    pub fn from_response(response: &[u8]) -> Result<Self, DecodeError> {
+        let ack = find_ack(response, 0x00AB)?;
+        check_length(ack, 12)?;
+        match ack[10] {
+            0x01 => Ok(DistanceResolution::DistanceGate0_2m),
+            _ => Ok(DistanceResolution::DistanceGate0_75m),
+        }
+    }
+}
+
+// FirmwareVersion is read with the read firmware version command and displayed as V1.07.22091615.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareVersion {
+    pub firmware_type: u16,
+    pub major: u16,
+    pub minor: u32,
+}
+impl FirmwareVersion {
+    // from_response() Parses the ACK of the read firmware version command:
+    // FD FC FB FA | 0C 00 | A0 01 | status (2) | type (2) | major (2) | minor (4) | 04 03 02 01
+    pub fn from_response(response: &[u8]) -> Result<Self, DecodeError> {
+        let ack = find_ack(response, 0x00A0)?;
+        check_length(ack, 18)?;
+        Ok(Self {
+            firmware_type: u16::from_le_bytes([ack[10], ack[11]]),
+            major: u16::from_le_bytes([ack[12], ack[13]]),
+            minor: u32::from_le_bytes([ack[14], ack[15], ack[16], ack[17]]),
+        })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:x}.{:02x}.{:08x}", self.major >> 8, self.major & 0xFF, self.minor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MacAddress(pub [u8; 6]);
+impl MacAddress {
+    // from_response() Parses the ACK of the query MAC address command:
+    // FD FC FB FA | 0A 00 | A5 01 | status (2) | MAC address (6) | 04 03 02 01
+    pub fn from_response(response: &[u8]) -> Result<Self, DecodeError> {
+        let ack = find_ack(response, 0x00A5)?;
+        check_length(ack, 16)?;
+        let mut address = [0u8; 6];
+        address.copy_from_slice(&ack[10..16]);
+        Ok(Self(address))
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{g:02X}")
+    }
+}
+
+// RadarParameters is the configuration read with the read parameter command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RadarParameters {
+    pub maximum_distance_gate: u8,
+    pub maximum_moving_distance_gate: u8,
+    pub maximum_static_distance_gate: u8,
+    pub moving_sensitivities: Vec<u8, MAX_GATES>,
+    pub static_sensitivities: Vec<u8, MAX_GATES>,
+    // Time (s) before reporting no one after the target disappeared.
+    pub no_one_duration: u16,
+}
+impl RadarParameters {
+    // from_response() Parses the ACK of the read parameter command:
+    // FD FC FB FA | length (2) | 61 01 | status (2) | AA | max gate N | max moving gate | max static gate |
+    // moving sensitivities 0..=N | static sensitivities 0..=N | no one duration (2) | 04 03 02 01
+    pub fn from_response(response: &[u8]) -> Result<Self, DecodeError> {
+        let ack = find_ack(response, 0x0061)?;
+        check_length(ack, 15)?;
+        if ack[10] != 0xAA {
+            return Err(DecodeError::InvalidHead(ack[10]));
+        }
+        let maximum_distance_gate = ack[11];
+        if maximum_distance_gate as usize >= MAX_GATES {
+            return Err(DecodeError::TooManyGates(maximum_distance_gate));
+        }
+        let gates = maximum_distance_gate as usize + 1;
+        let moving_start = 14;
+        let static_start = moving_start + gates;
+        let duration_start = static_start + gates;
+        check_length(ack, duration_start + 2)?;
+        Ok(Self {
+            maximum_distance_gate,
+            maximum_moving_distance_gate: ack[12],
+            maximum_static_distance_gate: ack[13],
+            moving_sensitivities: ack[moving_start..static_start].iter().copied().collect(),
+            static_sensitivities: ack[static_start..duration_start].iter().copied().collect(),
+            no_one_duration: u16::from_le_bytes([ack[duration_start], ack[duration_start + 1]]),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightControl {
+    Off,
+    BelowThreshold,
+    AboveThreshold,
+}
+impl LightControl {
+    fn to_u8(self) -> u8 {
+        match self {
+            LightControl::Off => 0x00,
+            LightControl::BelowThreshold => 0x01,
+            LightControl::AboveThreshold => 0x02,
+        }
+    }
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => LightControl::BelowThreshold,
+            0x02 => LightControl::AboveThreshold,
+            _ => LightControl::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutPinLevel {
+    Low,
+    High,
+}
+impl OutPinLevel {
+    fn to_u8(self) -> u8 {
+        match self {
+            OutPinLevel::Low => 0x00,
+            OutPinLevel::High => 0x01,
+        }
+    }
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0x00 => OutPinLevel::Low,
+            _ => OutPinLevel::High,
+        }
+    }
+}
+
+// AuxiliaryControl gates the OUT pin on the photosensitive value: when the light control is
+// enabled, the OUT pin only reports presence if the light level is below/above the threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuxiliaryControl {
+    pub light_control: LightControl,
+    pub light_threshold: u8,
+    pub out_pin_default_level: OutPinLevel,
+}
+impl AuxiliaryControl {
+    pub fn new(light_control: LightControl, light_threshold: u8, out_pin_default_level: OutPinLevel) -> Self {
+        Self {
+            light_control,
+            light_threshold,
+            out_pin_default_level,
+        }
+    }
+    fn to_bytes(&self) -> [u8; 4] {
+        [
+            self.light_control.to_u8(),
+            self.light_threshold,
+            self.out_pin_default_level.to_u8(),
+            0x00,
+        ]
+    }
+    // from_bytes() Decodes the command value built by to_bytes(), None if a field is out of range.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [light_control @ 0x00..=0x02, light_threshold, out_pin_default_level @ 0x00..=0x01, 0x00] => Some(Self {
+                light_control: LightControl::from_u8(light_control),
+                light_threshold,
+                out_pin_default_level: OutPinLevel::from_u8(out_pin_default_level),
+            }),
+            _ => None,
+        }
+    }
+    // from_response() Parses the ACK of the query auxiliary control command:
+    // FD FC FB FA | 08 00 | AE 01 | status (2) | mode | threshold | OUT level | 00 | 04 03 02 01
+    pub fn from_response(response: &[u8]) -> Result<Self, DecodeError> {
+        let ack = find_ack(response, 0x00AE)?;
+        check_length(ack, 13)?;
+        Ok(Self {
+            light_control: LightControl::from_u8(ack[10]),
+            light_threshold: ack[11],
+            out_pin_default_level: OutPinLevel::from_u8(ack[12]),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BluetoothModule {
+    TurnOn,
+    TurnOff,
+}
+impl BluetoothModule {
+    fn to_bytes(&self) -> [u8; 2] {
+        match self {
+            BluetoothModule::TurnOn => [0x01, 0x00],
+            BluetoothModule::TurnOff => [0x00, 0x00],
+        }
+    }
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        [BluetoothModule::TurnOn, BluetoothModule::TurnOff]
+            .into_iter()
+            .find(|module| module.to_bytes() == bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // engineering_frame() Builds an engineering mode report frame with the maximum gates `mov` and `stat` and
+    // `extra` bytes after the gate energies.
+    fn engineering_frame(mov: u8, stat: u8, extra: &[u8]) -> Vec<u8, 128> {
+        let mut data: Vec<u8, 128> = Vec::new();
+        // Type, head, moving target at 120 cm (energy 50), stationary target at 300 cm (energy 40), 150 cm
+        data.extend_from_slice(&[0x01, 0xAA, 0x03, 0x78, 0x00, 0x32, 0x2C, 0x01, 0x28, 0x96, 0x00]).unwrap();
+        data.extend_from_slice(&[mov, stat]).unwrap();
+        data.extend((0..=mov).map(|gate| 10 + gate));
+        data.extend((0..=stat).map(|gate| 50 + gate));
+        data.extend_from_slice(extra).unwrap();
+        data.extend_from_slice(&[0x55, 0x00]).unwrap();
+        let mut frame: Vec<u8, 128> = Vec::new();
+        frame.extend_from_slice(&REPORT_FRAME_HEADER).unwrap();
+        frame.extend_from_slice(&(data.len() as u16).to_le_bytes()).unwrap();
+        frame.extend_from_slice(&data).unwrap();
+        frame.extend_from_slice(&REPORT_FRAME_END).unwrap();
+        frame
+    }
+
+    #[test]
+    fn engineering_frame_with_the_same_gate_counts() {
+        let data = Ld2410CData::from_frame(&engineering_frame(8, 8, &[0x80, 0x01])).unwrap().unwrap();
+        let model = data.target_data.engineering_model.unwrap();
+        assert_eq!(data.target_data.movement_target_distance, 120);
+        assert_eq!(data.target_data.stationary_target_distance, 300);
+        assert_eq!(model.moving_distance_gates, [10, 11, 12, 13, 14, 15, 16, 17, 18]);
+        assert_eq!(model.static_distance_gates, [50, 51, 52, 53, 54, 55, 56, 57, 58]);
+        assert_eq!(model.light_sensor_value, Some(0x80));
+        assert_eq!(model.out_pin_state, Some(OutPinLevel::High));
+    }
+
+    #[test]
+    fn engineering_frame_with_different_gate_counts() {
+        let data = Ld2410CData::from_frame(&engineering_frame(8, 5, &[])).unwrap().unwrap();
+        let model = data.target_data.engineering_model.unwrap();
+        assert_eq!((model.maximum_mov_distance_gate, model.maximum_static_distance_gate), (8, 5));
+        assert_eq!(model.moving_distance_gates, [10, 11, 12, 13, 14, 15, 16, 17, 18]);
+        assert_eq!(model.static_distance_gates, [50, 51, 52, 53, 54, 55]);
+        assert_eq!(model.light_sensor_value, None);
+    }
+
+    #[test]
+    fn engineering_frame_with_unknown_extra_data() {
+        // The bytes after the photosensitive value and the OUT pin level are ignored.
+        let data = Ld2410CData::from_frame(&engineering_frame(2, 3, &[0x40, 0x00, 0xAB, 0xCD, 0xEF])).unwrap().unwrap();
+        let model = data.target_data.engineering_model.unwrap();
+        assert_eq!(model.moving_distance_gates, [10, 11, 12]);
+        assert_eq!(model.static_distance_gates, [50, 51, 52, 53]);
+        assert_eq!(model.light_sensor_value, Some(0x40));
+        assert_eq!(model.out_pin_state, Some(OutPinLevel::Low));
+    }
+
+    #[test]
+    fn engineering_frame_not_matching_the_gate_counts() {
+        let mut frame = engineering_frame(8, 2, &[]);
+        // Static gate count larger than the frame
+        frame[6 + 12] = 8;
+        assert!(matches!(Ld2410CData::from_frame(&frame), Some(Err(DecodeError::TooShort { .. }))));
+        // Gate beyond the 9 gates of the radar
+        let frame = engineering_frame(8, 9, &[]);
+        assert_eq!(Ld2410CData::from_frame(&frame), Some(Err(DecodeError::TooManyGates(9))));
+    }
+
+    #[test]
+    fn serde_round_trip_with_engineering_data() {
+        let data = Ld2410CData::from_frame(&engineering_frame(8, 5, &[0x80, 0x01])).unwrap().unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<Ld2410CData>(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn deserializes_the_previous_names() {
+        // History recorded before the fields and statuses were renamed.
+        let data = Ld2410CData::from_frame(&engineering_frame(2, 2, &[])).unwrap().unwrap();
+        let mut json = serde_json::to_string(&data).unwrap();
+        for (name, previous) in [
+            ("\"moving_distance_gates\"", "\"mouvement_distance_gates\""),
+            ("\"movement_target_energy\"", "\"exercise_target\""),
+            ("\"stationary_target_energy\"", "\"stationary_target\""),
+            ("\"MovingAndStationaryTarget\"", "\"CampaignAndStationaryTarget\""),
+        ] {
+            assert!(json.contains(name), "{name} not in {json}");
+            json = json.replace(name, previous);
+        }
+        assert_eq!(serde_json::from_str::<Ld2410CData>(&json).unwrap(), data);
+        for (previous, status) in [
+            ("\"CampaignTarget\"", TargetStatus::MovingTarget),
+            ("\"StationnaryTarget\"", TargetStatus::StationaryTarget),
+        ] {
+            assert_eq!(serde_json::from_str::<TargetStatus>(previous).unwrap(), status);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// protocol
+// no_std, allocation-free protocol core: frame structs, command encoders and data decoders of
+// each sensor, using fixed-size buffers. The std drivers (tokio and blocking) are layered on top
+// of it, and it can be used on its own on a microcontroller.
+#[cfg(feature = "ld2410c")]
+pub mod ld2410c;
+#[cfg(feature = "rd03d")]
+pub mod rd03d;
+#[cfg(feature = "tf_luna")]
+pub mod tf_luna;
+#[cfg(feature = "tof200f")]
+pub mod tof200f;
+
+// FrameSearch is the result of looking for a length-prefixed frame
+// (header (4) | intraframe length (2, little endian) | intraframe data | end (4)) in a reception buffer.
+// The LD2410C report/ACK frames and the RD03D ACK frames use this layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameSearch {
+    // A complete frame spans buf[start..end].
+    Complete { start: usize, end: usize },
+    // A frame starts at `start` but has not been fully received yet.
+    Incomplete { start: usize },
+    NotFound,
+}
+
+// find_frame() Looks for the first frame starting with `header` in `buf`.
+pub fn find_frame(buf: &[u8], header: [u8; 4]) -> FrameSearch {
+    let start = match buf.windows(4).position(|w| w == header) {
+        Some(start) => start,
+        None => return FrameSearch::NotFound,
+    };
+    let frame = &buf[start..];
+    if frame.len() < 6 {
+        return FrameSearch::Incomplete { start };
+    }
+    let length = u16::from_le_bytes([frame[4], frame[5]]) as usize;
+    let frame_length = 6 + length + 4;
+    if frame.len() < frame_length {
+        return FrameSearch::Incomplete { start };
+    }
+    FrameSearch::Complete {
+        start,
+        end: start + frame_length,
+    }
+}
+
+// find_ack_frame() Looks for the first complete ACK frame of the command `word` in `buf`: a frame starting
+// with `header` whose command word is `word` | 0x0100. The other frames (ACKs of other commands) are skipped,
+// the report frames have another header.
+pub fn find_ack_frame(buf: &[u8], header: [u8; 4], word: u16) -> FrameSearch {
+    let ack_word = (word | 0x0100).to_le_bytes();
+    let mut offset = 0;
+    loop {
+        match find_frame(&buf[offset..], header) {
+            FrameSearch::Complete { start, end } => {
+                let frame = &buf[offset + start..offset + end];
+                if frame.len() >= 8 && frame[6..8] == ack_word {
+                    return FrameSearch::Complete {
+                        start: offset + start,
+                        end: offset + end,
+                    };
+                }
+                offset += end;
+            }
+            FrameSearch::Incomplete { start } => return FrameSearch::Incomplete { start: offset + start },
+            FrameSearch::NotFound => return FrameSearch::NotFound,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// protocol/rd03d.rs
+// Frame structs, command encoders and report decoders of the RD03D radar module.
+// This module is no_std and allocation-free: command frames and targets are stored in
+// fixed-size buffers (heapless::Vec) so it can run on a microcontroller.
+use core::f64::consts::PI;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+// The RD03D tracks up to three targets.
+pub const MAX_TARGETS: usize = 3;
+pub const MAX_COMMAND_VALUE_LEN: usize = 8;
+// Header (4) + frame length (2) + command word (2) + command value + end (4).
+pub const MAX_COMMAND_FRAME_LEN: usize = 12 + MAX_COMMAND_VALUE_LEN;
+pub const COMMAND_FRAME_HEADER: [u8; 4] = [0xFD, 0xFC, 0xFB, 0xFA];
+pub const COMMAND_FRAME_END: [u8; 4] = [0x04, 0x03, 0x02, 0x01];
+pub const REPORT_FRAME_HEADER: [u8; 2] = [0xAA, 0xFF];
+pub const REPORT_FRAME_END: [u8; 2] = [0x55, 0xCC];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Target {
+    pub x: i16,           // mm
+    pub y: i16,           // mm
+    pub speed: i16,       // cm/s
+    pub pixel_distance: u16, // mm
+    pub distance: f64,    // mm
+    pub angle: f64,       // degrees
+}
+
+impl Target {
+    pub fn new(x: i16, y: i16, speed: i16, pixel_distance: u16) -> Self {
+        let distance = libm::sqrt(libm::pow(x as f64, 2.0) + libm::pow(y as f64, 2.0));
+        let angle = libm::atan2(x as f64, y as f64) * 180.0 / PI;
+        Self { x, y, speed, pixel_distance, distance, angle }
+    }
+}
+
+
+pub struct RD03DCommand {
+    pub command_word: [u8; 2],
+    pub command_value: Vec<u8, MAX_COMMAND_VALUE_LEN>,
+}
+impl RD03DCommand {
+    pub fn new<const N: usize>(command_word: [u8; 2], command_value: [u8; N]) -> Self {
+        const { assert!(N <= MAX_COMMAND_VALUE_LEN) };
+        Self {
+            command_word,
+            command_value: command_value.into_iter().collect(),
+        }
+    }
+}
+pub struct RD03DFrame {
+    frame_data: RD03DCommand,
+}
+impl RD03DFrame {
+    fn new(frame_data: RD03DCommand) -> Self {
+        Self { frame_data }
+    }
+    // to_u8() Encodes the frame: FD FC FB FA | frame length (2) | command word (2) | command value | 04 03 02 01,
+    // the frame length being the command word plus the command value.
+    pub fn to_u8(&self) -> Vec<u8, MAX_COMMAND_FRAME_LEN> {
+        let frame_length = ((self.frame_data.command_value.len() + 2) as u16).to_le_bytes();
+        COMMAND_FRAME_HEADER
+            .iter()
+            .chain(frame_length.iter())
+            .chain(self.frame_data.command_word.iter())
+            .chain(self.frame_data.command_value.iter())
+            .chain(COMMAND_FRAME_END.iter())
+            .copied()
+            .collect()
+    }
+
+    // word() Returns the command word, the ACK of the radar carrying word | 0x0100.
+    pub fn word(&self) -> u16 {
+        u16::from_le_bytes(self.frame_data.command_word)
+    }
+
+    // The command frames below are shared by the async (tokio), blocking and embedded drivers.
+    pub fn open_command_mode() -> Self {
+        RD03DFrame::new(RD03DCommand::new([0xFF, 0x00], [0x01, 0x00]))
+    }
+    pub fn close_command_mode() -> Self {
+        RD03DFrame::new(RD03DCommand::new([0xFE, 0x00], []))
+    }
+    // set_mode() Parameter 0x0000 (mode) followed by its value on 4 bytes.
+    pub fn set_mode(mode: u8) -> Self {
+        RD03DFrame::new(RD03DCommand::new([0x12, 0x00], [0x00, 0x00, mode, 0x00, 0x00, 0x00]))
+    }
+
+    // parse() Decodes a command frame, as sent to the radar, back into the command: the inverse of to_u8().
+    // Returns None if the frame layout is invalid or the command unknown.
+    pub fn parse(frame: &[u8]) -> Option<DecodedCommand> {
+        let frame_length = u16::from_le_bytes([*frame.get(4)?, *frame.get(5)?]) as usize;
+        if frame_length < 2
+            || frame.len() != 6 + frame_length + 4
+            || frame[..4] != COMMAND_FRAME_HEADER
+            || frame[6 + frame_length..] != COMMAND_FRAME_END
+        {
+            return None;
+        }
+        match (u16::from_le_bytes([frame[6], frame[7]]), &frame[8..6 + frame_length]) {
+            (0x00FF, [0x01, 0x00]) => Some(DecodedCommand::OpenCommandMode),
+            (0x00FE, []) => Some(DecodedCommand::CloseCommandMode),
+            (0x0012, [0x00, 0x00, mode, 0x00, 0x00, 0x00]) => Some(DecodedCommand::SetMode(*mode)),
+            (0x0080, []) => Some(DecodedCommand::TargetMode { multi_mode: false }),
+            (0x0090, []) => Some(DecodedCommand::TargetMode { multi_mode: true }),
+            _ => None,
+        }
+    }
+}
+
+// DecodedCommand is a command frame decoded by RD03DFrame::parse().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodedCommand {
+    OpenCommandMode,
+    CloseCommandMode,
+    SetMode(u8),
+    // SINGLE_TARGET_CMD / MULTI_TARGET_CMD.
+    TargetMode { multi_mode: bool },
+}
+
+pub const SINGLE_TARGET_CMD: &[u8] = &[
+    0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x80, 0x00, 0x04, 0x03, 0x02, 0x01,
+];
+pub const MULTI_TARGET_CMD: &[u8] = &[
+    0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x90, 0x00, 0x04, 0x03, 0x02, 0x01,
+];
+
+// target_mode_command() Returns the command switching the radar to multi-target or single-target tracking.
+pub fn target_mode_command(multi_mode: bool) -> &'static [u8] {
+    if multi_mode { MULTI_TARGET_CMD } else { SINGLE_TARGET_CMD }
+}
+
+// parse_signed16() Decodes a coordinate or a speed: bit 15 is the sign (set for a positive value, as in the
+// datasheet) and bits 0-14 the absolute value.
+fn parse_signed16(high: u8, low: u8) -> i16 {
+    let raw = ((high as u16) << 8) | (low as u16);
+    let sign = if (raw & 0x8000) != 0 { 1 } else { -1 };
+    let value = (raw & 0x7FFF) as i16;
+    sign * value
+}
+
+// decode_frame() Decodes a report frame (AA FF ... 55 CC) into the three targets.
+pub fn decode_frame(data: &[u8]) -> Vec<Target, MAX_TARGETS> {
+    if data.len() < 30 || data[..2] != REPORT_FRAME_HEADER || data[data.len()-2..] != REPORT_FRAME_END {
+        return Vec::new();
+    }
+    (0..MAX_TARGETS)
+        .map(|i| {
+            let base = 4 + i * 8;
+            let x = parse_signed16(data[base+1], data[base]);
+            let y = parse_signed16(data[base+3], data[base+2]);
+            let speed = parse_signed16(data[base+5], data[base+4]);
+            let pixel_distance = (data[base+6] as u16) | ((data[base+7] as u16) << 8);
+            Target::new(x, y, speed, pixel_distance)
+        })
+        .collect()
+}
+
+// find_complete_frame() Returns the first complete report frame of `data` and the bytes following it.
+// If there is no complete frame, returns the bytes from the start of the incomplete frame (if any).
+pub fn find_complete_frame(data: &[u8]) -> (Option<&[u8]>, &[u8]) {
+    // Look for the start of the frame
+    let start = match data.windows(2).position(|w| w == REPORT_FRAME_HEADER) {
+        Some(idx) => idx,
+        None => return (None, data),
+    };
+    // Look for the end of the frame
+    match data[start+2..].windows(2).position(|w| w == REPORT_FRAME_END) {
+        Some(idx) => {
+            let end = start + 2 + idx + 2;
+            (Some(&data[start..end]), &data[end..])
+        }
+        None => (None, &data[start..]),
+    }
+}
+
+// latest_frame() Returns the last complete report frame of `data` and the number of bytes up to
+// the end of this frame, which can be dropped from the reception buffer.
+pub fn latest_frame(data: &[u8]) -> Option<(&[u8], usize)> {
+    let mut latest = None;
+    let mut remaining = data;
+    while let (Some(frame), rest) = find_complete_frame(remaining) {
+        latest = Some((frame, data.len() - rest.len()));
+        remaining = rest;
+    }
+    latest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_datasheet_frame() {
+        // Report of the datasheet: target 1 at x = -782 mm, y = 1713 mm, moving at -16 cm/s.
+        let mut frame: Vec<u8, 30> = Vec::new();
+        frame.extend_from_slice(&[0xAA, 0xFF, 0x03, 0x00, 0x0E, 0x03, 0xB1, 0x86, 0x10, 0x00, 0x40, 0x01]).unwrap();
+        frame.extend_from_slice(&[0x00; 16]).unwrap();
+        frame.extend_from_slice(&[0x55, 0xCC]).unwrap();
+        let targets = decode_frame(&frame);
+        assert_eq!(targets.len(), MAX_TARGETS);
+        let target = &targets[0];
+        assert_eq!((target.x, target.y, target.speed, target.pixel_distance), (-782, 1713, -16, 320));
+        assert!((target.distance - 1883.1).abs() < 0.1);
+        assert!((target.angle + 24.54).abs() < 0.01);
+        assert_eq!(targets[1].distance, 0.0);
+    }
+
+    #[test]
+    fn sign_bit_set_for_positive_values() {
+        assert_eq!(parse_signed16(0x80, 0x64), 100);
+        assert_eq!(parse_signed16(0x00, 0x64), -100);
+        assert_eq!(parse_signed16(0x00, 0x00), 0);
+        assert_eq!(parse_signed16(0xFF, 0xFF), i16::MAX);
+    }
+}
@@ -0,0 +1,230 @@
+// protocol/tf_luna.rs
+// Command byte layouts and data decoder of the TF-Luna lidar. This module is no_std and
+// allocation-free so it can run on a microcontroller.
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+// Standard data frame: 59 59 | distance (2) | amplitude (2) | temperature (2) | checksum.
+pub const DATA_FRAME_LEN: usize = 9;
+pub const DATA_FRAME_HEADER: [u8; 2] = [0x59, 0x59];
+
+// checksum() Returns the checksum of the data frames, commands and responses: the low byte of the sum of
+// their other bytes.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// find_data_frame() Returns the first data frame of `data` with a valid checksum (low byte of the
+// sum of the first 8 bytes), and the number of bytes up to its end.
+pub fn find_data_frame(data: &[u8]) -> Option<(&[u8], usize)> {
+    data.windows(DATA_FRAME_LEN)
+        .position(|frame| frame[..2] == DATA_FRAME_HEADER && checksum(&frame[..8]) == frame[8])
+        .map(|start| (&data[start..start + DATA_FRAME_LEN], start + DATA_FRAME_LEN))
+}
+
+// find_response() Returns the first response frame 5A | length | id | payload | checksum of the command `id`
+// in `data`, with a valid checksum (low byte of the sum of the other bytes), and the number of bytes up to its
+// end. The data frames (59 59 ...) and the responses to other commands are skipped.
+pub fn find_response(data: &[u8], id: u8) -> Option<(&[u8], usize)> {
+    (0..data.len()).find_map(|start| {
+        let frame = data.get(start..)?;
+        if frame.len() < 4 || frame[0] != COMMAND_HEADER || frame[2] != id {
+            return None;
+        }
+        let len = frame[1] as usize;
+        let frame = frame.get(..len).filter(|frame| frame.len() >= 4)?;
+        (checksum(&frame[..len - 1]) == frame[len - 1]).then_some((frame, start + len))
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct TfLunaData {
+    pub distance: u16, // cm
+    // Signal strength, the distance is unreliable below 100.
+    #[serde(default)]
+    pub amplitude: u16,
+    #[serde(default)]
+    pub temperature: f32, // °C
+}
+impl TfLunaData {
+    // new() Decodes a data frame; the fields missing from a too short frame are 0.
+    pub fn new(data: &[u8]) -> Self {
+        let word = |i: usize| data.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        Self {
+            distance: word(2).unwrap_or(0),
+            amplitude: word(4).unwrap_or(0),
+            temperature: word(6).map(|raw| raw as f32 / 8.0 - 256.0).unwrap_or(0.0),
+        }
+    }
+
+    pub fn distance(&self) -> u16 {
+        self.distance
+    }
+
+    pub fn amplitude(&self) -> u16 {
+        self.amplitude
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    NineByteCm = 0x01,
+    PIX = 0x02,
+    NineByteMm = 0x06,
+    ThirtyTwoTimestamp = 0x07,
+    IdZeroOutput = 0x08,
+    EightByteCm = 0x09,
+}
+impl OutputFormat {
+    // is_decoded() Returns whether the data frames of the format are decoded by TfLunaData (59 59, 9 bytes,
+    // distance in cm). The drivers cannot read the TF-Luna in the other formats.
+    pub fn is_decoded(&self) -> bool {
+        *self == OutputFormat::NineByteCm
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        [
+            OutputFormat::NineByteCm,
+            OutputFormat::PIX,
+            OutputFormat::NineByteMm,
+            OutputFormat::ThirtyTwoTimestamp,
+            OutputFormat::IdZeroOutput,
+            OutputFormat::EightByteCm,
+        ]
+        .into_iter()
+        .find(|format| *format as u8 == value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFrequency {
+    Freq1Hz = 1,
+    Freq2Hz = 2,
+    Freq4Hz = 4,
+    Freq8Hz = 8,
+    Freq16Hz = 16,
+    Freq10Hz = 10,
+    Freq32Hz = 32,
+    Freq64Hz = 64,
+    Freq128Hz = 128,
+    Freq100Hz = 100,
+    Freq250Hz = 250,
+}
+
+impl OutputFrequency {
+    // from_hz() Returns the output frequency setting, None if the TF-Luna does not support it.
+    pub fn from_hz(hz: u16) -> Option<Self> {
+        match hz {
+            1 => Some(OutputFrequency::Freq1Hz),
+            2 => Some(OutputFrequency::Freq2Hz),
+            4 => Some(OutputFrequency::Freq4Hz),
+            8 => Some(OutputFrequency::Freq8Hz),
+            10 => Some(OutputFrequency::Freq10Hz),
+            16 => Some(OutputFrequency::Freq16Hz),
+            32 => Some(OutputFrequency::Freq32Hz),
+            64 => Some(OutputFrequency::Freq64Hz),
+            100 => Some(OutputFrequency::Freq100Hz),
+            128 => Some(OutputFrequency::Freq128Hz),
+            250 => Some(OutputFrequency::Freq250Hz),
+            _ => None,
+        }
+    }
+}
+
+// Baud rates accepted by the TF-Luna.
+pub const BAUD_RATES: [u32; 9] = [9600, 14400, 19200, 38400, 56000, 57600, 115200, 460800, 921600];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    Frequency = 0x03,
+    DistanceLimit = 0x04,
+    OutputFormat = 0x05,
+    BaudRate = 0x06,
+}
+impl OutputMode {
+    fn from_u8(value: u8) -> Option<Self> {
+        [
+            OutputMode::Frequency,
+            OutputMode::DistanceLimit,
+            OutputMode::OutputFormat,
+            OutputMode::BaudRate,
+        ]
+        .into_iter()
+        .find(|mode| *mode as u8 == value)
+    }
+}
+
+// First byte of the commands and of their responses.
+pub const COMMAND_HEADER: u8 = 0x5A;
+// Longest command (distance limit): 0x5A | length | id | min (2) | max (2) | reserved | checksum.
+pub const MAX_COMMAND_LEN: usize = 9;
+
+// TfLunaCommand holds the byte layout of the TF-Luna commands, shared by the async (tokio),
+// blocking and embedded drivers: 0x5A | length | id | payload | checksum (low byte of the sum of the
+// other bytes).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TfLunaCommand {
+    OutputFormat(OutputFormat),
+    BaudRate(u32),
+    DistanceLimit { dist_min: u16, dist_max: u16 },
+    OutputFrequency(OutputFrequency),
+    GetConfiguration(OutputMode),
+}
+
+impl TfLunaCommand {
+    // id() Returns the command id (third byte), which the TF-Luna repeats in its response.
+    pub fn id(&self) -> u8 {
+        match self {
+            TfLunaCommand::OutputFormat(_) => 0x05,
+            TfLunaCommand::BaudRate(_) => 0x06,
+            TfLunaCommand::DistanceLimit { .. } => 0x3A,
+            TfLunaCommand::OutputFrequency(_) => 0x03,
+            TfLunaCommand::GetConfiguration(_) => 0x3F,
+        }
+    }
+
+    pub fn to_u8(&self) -> Vec<u8, MAX_COMMAND_LEN> {
+        let bytes: &[u8] = match *self {
+            TfLunaCommand::OutputFormat(format) => &[0x5A, 0x05, 0x05, format as u8],
+            TfLunaCommand::BaudRate(baud_rate) => {
+                let [b0, b1, b2, b3] = baud_rate.to_le_bytes();
+                &[0x5A, 0x08, 0x06, b0, b1, b2, b3]
+            }
+            TfLunaCommand::DistanceLimit { dist_min, dist_max } => {
+                let [min_lo, min_hi] = dist_min.to_le_bytes();
+                let [max_lo, max_hi] = dist_max.to_le_bytes();
+                &[0x5A, 0x09, 0x3A, min_lo, min_hi, max_lo, max_hi, 0x00]
+            }
+            TfLunaCommand::OutputFrequency(freq) => {
+                let [lo, hi] = (freq as u16).to_le_bytes();
+                &[0x5A, 0x06, 0x03, lo, hi]
+            }
+            TfLunaCommand::GetConfiguration(output_mode) => &[0x5A, 0x05, 0x3F, output_mode as u8],
+        };
+        bytes.iter().copied().chain([checksum(bytes)]).collect()
+    }
+
+    // parse() Decodes a command frame back into the command: the inverse of to_u8(). Returns None if the
+    // length or the checksum is wrong, or the command or its value unknown.
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        let (&sum, bytes) = frame.split_last()?;
+        if bytes.len() < 3 || bytes[0] != COMMAND_HEADER || bytes[1] as usize != frame.len() || checksum(bytes) != sum {
+            return None;
+        }
+        match (bytes[2], &bytes[3..]) {
+            (0x05, [format]) => OutputFormat::from_u8(*format).map(TfLunaCommand::OutputFormat),
+            (0x06, [b0, b1, b2, b3]) => Some(TfLunaCommand::BaudRate(u32::from_le_bytes([*b0, *b1, *b2, *b3]))),
+            (0x3A, [min_lo, min_hi, max_lo, max_hi, 0x00]) => Some(TfLunaCommand::DistanceLimit {
+                dist_min: u16::from_le_bytes([*min_lo, *min_hi]),
+                dist_max: u16::from_le_bytes([*max_lo, *max_hi]),
+            }),
+            (0x03, [lo, hi]) => OutputFrequency::from_hz(u16::from_le_bytes([*lo, *hi]))
+                .map(TfLunaCommand::OutputFrequency),
+            (0x3F, [mode]) => OutputMode::from_u8(*mode).map(TfLunaCommand::GetConfiguration),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// protocol/tof200f.rs
+// Data decoder of the TOF200F time-of-flight sensor. This module is no_std and allocation-free
+// so it can run on a microcontroller.
+use serde::{Deserialize, Serialize};
+
+// Response to the read of the distance register (Modbus RTU): address | 0x03 | 0x02 | distance (2, big endian) |
+// CRC (2, little endian).
+pub const RESPONSE_LEN: usize = 7;
+
+// find_response() Returns the first response of `data` with a valid CRC, and the number of bytes up to its end.
+pub fn find_response(data: &[u8]) -> Option<(&[u8], usize)> {
+    data.windows(RESPONSE_LEN)
+        .position(|frame| {
+            (1..=247).contains(&frame[0])
+                && frame[1..3] == [0x03, 0x02]
+                && modbus_crc(&frame[..5]).to_le_bytes() == frame[5..]
+        })
+        .map(|start| (&data[start..start + RESPONSE_LEN], start + RESPONSE_LEN))
+}
+
+// modbus_crc() CRC-16/MODBUS of `data`.
+pub fn modbus_crc(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tof200fData {
+    distance: u16, // mm
+}
+impl Tof200fData {
+    pub fn new(distance: u16) -> Self {
+        Self { distance }
+    }
+
+    // from_frame() Decodes the distance (mm) from a TOF200F response, the distance being in bytes 3 (high) and 4 (low).
+    // Returns None if the response is too short.
+    pub fn from_frame(data: &[u8]) -> Option<Self> {
+        let distance = data.get(3..5)?;
+        Some(Self::new(u16::from_be_bytes([distance[0], distance[1]])))
+    }
+
+    pub fn distance(&self) -> u16 {
+        self.distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modbus_crc_check_value() {
+        assert_eq!(modbus_crc(b"123456789"), 0x4B37);
+    }
+
+    #[test]
+    fn decode_distance_response() {
+        // Address 1, 300 mm then 4000 mm
+        let data = [0x00, 0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x09, 0x01, 0x03, 0x02, 0x0F, 0xA0, 0xBD, 0xCC];
+        let (frame, end) = find_response(&data).unwrap();
+        assert_eq!(end, 8);
+        assert_eq!(Tof200fData::from_frame(frame), Some(Tof200fData::new(300)));
+        let (frame, end) = find_response(&data[end..]).unwrap();
+        assert_eq!(end, 7);
+        assert_eq!(Tof200fData::from_frame(frame).unwrap().distance(), 4000);
+    }
+
+    #[test]
+    fn skip_response_with_invalid_crc() {
+        assert_eq!(find_response(&[0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x0A]), None);
+        assert_eq!(Tof200fData::from_frame(&[0x01, 0x03, 0x02, 0x01]), None);
+    }
+}
@@ -1,67 +1,55 @@
-use serde::{Deserialize, Serialize};
+pub use crate::protocol::rd03d::*;
+#[cfg(feature = "tokio")]
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "tokio")]
 use tokio_serial::SerialPortBuilderExt;
-use std::f64::consts::PI;
+#[cfg(feature = "tokio")]
 use std::time::Duration;
-
-
-#[derive(Debug, Clone,Serialize,Deserialize)]
-pub struct Target {
-    pub x: i16,           // mm
-    pub y: i16,           // mm
-    pub speed: i16,       // cm/s
-    pub pixel_distance: u16, // mm
-    pub distance: f64,    // mm
-    pub angle: f64,       // degrés
-}
-
-impl Target {
-    pub fn new(x: i16, y: i16, speed: i16, pixel_distance: u16) -> Self {
-        let distance = ((x as f64).powi(2) + (y as f64).powi(2)).sqrt();
-        let angle = (x as f64).atan2(y as f64) * 180.0 / PI;
-        Self { x, y, speed, pixel_distance, distance, angle }
-    }
-}
-
-
-struct RD03DCommand {
-    pub command_word: Vec<u8>,
-    pub command_value: Vec<u8>,
-}
-impl RD03DCommand {
-    pub fn new(command_word: Vec<u8>, command_value: Vec<u8>) -> Self {
-        Self {
-            command_word,
-            command_value,
-        }
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+use crate::protocol::{find_ack_frame, FrameSearch};
+
+// Default deadline of a read and of the ACK of a command. The radar reports about 10 times per second.
+#[cfg(feature = "tokio")]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+// Default number of times a command is sent again when its ACK is not received.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub const DEFAULT_RETRIES: u8 = 2;
+
+// extract_latest_targets() Consumes the complete frames accumulated in `buffer` and decodes the latest one.
+// Returns None if no complete frame with targets was found.
+pub fn extract_latest_targets(buffer: &mut Vec<u8>) -> Option<Vec<Target>> {
+    // Limit the size of the buffer
+    if buffer.len() > 300 {
+        *buffer = buffer[buffer.len()-150..].to_vec();
+    }
+    // Extract the latest complete frame
+    let (frame, consumed) = latest_frame(buffer)?;
+    let decoded = decode_frame(frame);
+    // Drop the consumed bytes from the buffer
+    buffer.drain(..consumed);
+    if decoded.is_empty() {
+        None
+    } else {
+        Some(decoded.to_vec())
     }
 }
-struct RD03DFrame {
-    frame_header: Vec<u8>,
-    frame_length: Vec<u8>,
-    frame_data: RD03DCommand,
-    end_frame: Vec<u8>,
-}
-impl RD03DFrame {
-    fn new(frame_length: Vec<u8>, frame_data: RD03DCommand) -> Self {
-        Self {
-            frame_header: [0xFD, 0xFC, 0xFB, 0xFA].to_vec(),
-            frame_length,
-            frame_data,
-            end_frame: [0x04, 0x03, 0x02, 0x01].to_vec(),
+// ack_frame() Returns the ACK frame of the command `word` found in `response`, or None if it has not been
+// (fully) received yet. Fails if the radar reported a failure (status other than 00 00).
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub(crate) fn ack_frame(response: &[u8], word: u16) -> anyhow::Result<Option<&[u8]>> {
+    match find_ack_frame(response, COMMAND_FRAME_HEADER, word) {
+        FrameSearch::Complete { start, end } => {
+            let ack = &response[start..end];
+            if ack.len() < 10 || ack[8..10] != [0x00, 0x00] {
+                anyhow::bail!("RD03D command {word:#06X} failed");
+            }
+            Ok(Some(ack))
         }
-    }
-    fn to_u8(&self) -> Vec<u8> {
-        [
-            &self.frame_header[..],
-            &self.frame_length[..],
-            &self.frame_data.command_word[..],
-            &self.frame_data.command_value[..],
-            &self.end_frame[..],
-        ]
-        .concat()
+        _ => Ok(None),
     }
 }
+
+#[cfg(feature = "tokio")]
 pub struct RD03D {
     pub path: String,
     pub baud_rate: u32,
@@ -69,15 +57,16 @@ pub struct RD03D {
     pub targets: Vec<Target>,
     pub buffer: Vec<u8>,
     pub multi_mode: bool,
+    // Deadline of a read and of the ACK of a command.
+    pub timeout: Duration,
+    // Number of times a command is sent again when its ACK is not received.
+    pub retries: u8,
 }
 
+#[cfg(feature = "tokio")]
 impl RD03D {
-    pub const SINGLE_TARGET_CMD: &'static [u8] = &[
-        0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x80, 0x00, 0x04, 0x03, 0x02, 0x01,
-    ];
-    pub const MULTI_TARGET_CMD: &'static [u8] = &[
-        0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x90, 0x00, 0x04, 0x03, 0x02, 0x01,
-    ];
+    pub const SINGLE_TARGET_CMD: &'static [u8] = SINGLE_TARGET_CMD;
+    pub const MULTI_TARGET_CMD: &'static [u8] = MULTI_TARGET_CMD;
     pub fn new(path: String) -> Self {
         Self {
             path,
@@ -86,6 +75,8 @@ impl RD03D {
             targets: Vec::new(),
             buffer: Vec::new(),
             multi_mode: true,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
         }
     }
 
@@ -103,100 +94,30 @@ impl RD03D {
     }
 
     pub async fn set_multi_mode(&mut self, multi_mode: bool) -> anyhow::Result<()> {
-        let cmd = if multi_mode { Self::MULTI_TARGET_CMD } else { Self::SINGLE_TARGET_CMD };
+        let cmd = target_mode_command(multi_mode);
         if let Some(stream) = self.stream.as_mut() {
             stream.write_all(cmd).await?;
             stream.flush().await?;
             tokio::time::sleep(Duration::from_millis(200)).await;
-            // Impossible de reset_input_buffer avec tokio_serial, donc on vide le buffer logiciel
+            // tokio_serial cannot reset the input buffer, so the software buffer is cleared instead
             self.buffer.clear();
             self.multi_mode = multi_mode;
         }
         Ok(())
     }
 
-    fn parse_signed16(high: u8, low: u8) -> i16 {
-        let raw = ((high as u16) << 8) | (low as u16);
-        let sign = if (raw & 0x8000) == 0 { 1 } else { -1 };
-        let value = (raw & 0x7FFF) as i16;
-        sign * value
-    }
-
-    fn decode_frame(data: &[u8]) -> Vec<Target> {
-        let mut targets = Vec::new();
-        if data.len() < 30 || data[0] != 0xAA || data[1] != 0xFF || data[data.len()-2] != 0x55 || data[data.len()-1] != 0xCC {
-            return targets;
-        }
-        for i in 0..3 {
-            let base = 4 + i * 8;
-            let x = Self::parse_signed16(data[base+1], data[base]);
-            let y = Self::parse_signed16(data[base+3], data[base+2]);
-            let speed = Self::parse_signed16(data[base+5], data[base+4]);
-            let pixel_distance = (data[base+6] as u16) | ((data[base+7] as u16) << 8);
-            targets.push(Target::new(x, y, speed, pixel_distance));
-        }
-        targets
-    }
-
-    fn find_complete_frame(data: &[u8]) -> (Option<Vec<u8>>, &[u8]) {
-        // Cherche le début de trame
-        let mut start_idx = None;
-        for i in 0..data.len().saturating_sub(1) {
-            if data[i] == 0xAA && data[i+1] == 0xFF {
-                start_idx = Some(i);
-                break;
-            }
-        }
-        let start = match start_idx {
-            Some(idx) => idx,
-            None => return (None, data),
-        };
-        // Cherche la fin de trame
-        for i in (start+2)..data.len().saturating_sub(1) {
-            if data[i] == 0x55 && data[i+1] == 0xCC {
-                let frame = data[start..=i+1].to_vec();
-                let remaining = &data[i+2..];
-                return (Some(frame), remaining);
-            }
-        }
-        (None, &data[start..])
-    }
-
+    // update() Reads the available bytes and decodes the latest complete frame. Returns true if the targets
+    // were updated. Fails if nothing was received before the timeout.
     pub async fn update(&mut self) -> anyhow::Result<bool> {
-        // Lire les données disponibles
+        // Read the available bytes
         let mut buf = [0u8; 256];
-        if let Some(stream) = self.stream.as_mut() {
-            let n = stream.read(&mut buf).await?;
-            if n > 0 {
-                self.buffer.extend_from_slice(&buf[..n]);
-            }
-        }
-        // Limiter la taille du buffer
-        if self.buffer.len() > 300 {
-            self.buffer = self.buffer[self.buffer.len()-150..].to_vec();
-        }
-        // Extraire la dernière trame complète
-        let mut latest_frame = None;
-        let mut temp_buffer = self.buffer.clone();
-        loop {
-            let (frame, remaining) = Self::find_complete_frame(&temp_buffer);
-            if let Some(f) = frame {
-                latest_frame = Some(f);
-                temp_buffer = remaining.to_vec();
-            } else {
-                break;
-            }
+        if self.stream.is_some() {
+            let n = self.read(&mut buf).await?;
+            self.buffer.extend_from_slice(&buf[..n]);
         }
-        // Mettre à jour le buffer
-        if let Some(frame) = latest_frame {
-            if let Some(pos) = self.buffer.windows(frame.len()).rposition(|w| w == frame.as_slice()) {
-                self.buffer = self.buffer[pos+frame.len()..].to_vec();
-            }
-            let decoded = Self::decode_frame(&frame);
-            if !decoded.is_empty() {
-                self.targets = decoded;
-                return Ok(true);
-            }
+        if let Some(targets) = extract_latest_targets(&mut self.buffer) {
+            self.targets = targets;
+            return Ok(true);
         }
         Ok(false)
     }
@@ -216,44 +137,83 @@ impl RD03D {
         Ok(())
     }
 
-    pub async fn read_data(&mut self) -> anyhow::Result<()> {
+    // read() Reads the available bytes, waiting at most `timeout` for the first one.
+    async fn read(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        let timeout = self.timeout;
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("RD03D is not connected"))?;
+        match tokio::time::timeout(timeout, stream.read(buf)).await {
+            Ok(Ok(0)) => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into()),
+            Ok(n) => Ok(n?),
+            Err(_) => anyhow::bail!("no data from the RD03D within {timeout:?}"),
+        }
+    }
+
+    // read_data() Returns the raw bytes available from the radar, waiting at most `timeout` for the first one.
+    pub async fn read_data(&mut self) -> anyhow::Result<Vec<u8>> {
         let mut buf = [0u8; 1024];
-        match self.stream.as_mut().unwrap().read(&mut buf).await {
-            Ok(n) => {
-                println!("{:02x?}", &buf[..n]);
-                Ok(())
+        let n = self.read(&mut buf).await?;
+        Ok(buf[..n].to_vec())
+    }
+    pub async fn write_data(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("RD03D is not connected"))?;
+        stream.write_all(data).await?;
+        Ok(())
+    }
+    // read_ack() Reads until the ACK of the command `word` is received and returns the ACK frame. The report
+    // frames received in between are discarded. Returns None if it was not received before the timeout.
+    async fn read_ack(&mut self, word: u16) -> anyhow::Result<Option<Vec<u8>>> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("RD03D is not connected"))?;
+        let mut response = Vec::new();
+        let read = async {
+            let mut buf = [0u8; 256];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                response.extend_from_slice(&buf[..n]);
+                if let Some(ack) = ack_frame(&response, word)? {
+                    return Ok(ack.to_vec());
+                }
             }
-            Err(e) => Err(e.into()),
+        };
+        match tokio::time::timeout(self.timeout, read).await {
+            Ok(ack) => ack.map(Some),
+            Err(_) => Ok(None),
         }
     }
-    pub async fn write_data(&mut self, data: &[u8]) -> anyhow::Result<()> {
-        match self.stream.as_mut().unwrap().write(data).await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
+
+    // send_command() Sends a command frame and returns its ACK frame, sending it again up to `retries` times
+    // when the ACK is not received before the timeout.
+    pub async fn send_command(&mut self, frame: RD03DFrame) -> anyhow::Result<Vec<u8>> {
+        let word = frame.word();
+        for _ in 0..=self.retries {
+            self.write_data(&frame.to_u8()).await?;
+            if let Some(ack) = self.read_ack(word).await? {
+                return Ok(ack);
+            }
         }
+        anyhow::bail!("no ACK for RD03D command {word:#06X}")
     }
     pub async fn open_command_mode(&mut self) -> anyhow::Result<()> {
-        let command = RD03DCommand::new(vec![0xFF, 0x00], vec![0x01, 0x00]);
-        let frame = RD03DFrame::new(vec![0x04, 0x00], command);
-        let data = frame.to_u8();
-        self.write_data(&data).await?;
-        self.read_data().await?;
+        self.send_command(RD03DFrame::open_command_mode()).await?;
         Ok(())
     }
     pub async fn close_command_mode(&mut self) -> anyhow::Result<()> {
-        let command = RD03DCommand::new(vec![0xFE, 0x00], vec![0x00, 0x00]);
-        let frame = RD03DFrame::new(vec![0x02, 0x00], command);
-        let data = frame.to_u8();
-        self.write_data(&data).await?;
-        self.read_data().await?;
+        self.send_command(RD03DFrame::close_command_mode()).await?;
         Ok(())
     }
     pub async fn set_mode(&mut self, mode: u8) -> anyhow::Result<()> {
-        let command = RD03DCommand::new(vec![0x12, 0x00], vec![mode, 0x00]);
-        let frame = RD03DFrame::new(vec![0x08, 0x00], command);
-        let data = frame.to_u8();
-        self.write_data(&data).await?;
-        self.read_data().await?;
+        self.send_command(RD03DFrame::set_mode(mode)).await?;
         Ok(())
     }
 }
@@ -0,0 +1,334 @@
+// stream.rs
+// Rate of the live streams (SSE and WebSocket). A client receives the readings of a sensor on every new
+// frame, or at a given rate; at a given rate the readings received during a period are either decimated
+// (the latest one is sent) or averaged. The rates are capped by the [stream] section of the configuration.
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Stream;
+use serde::Deserialize;
+
+use crate::config::StreamConfig;
+use crate::fusion::{presence, room};
+use crate::hub::{Reading, Sample, Sensor};
+use crate::{ld2410c, rd03d, tf_luna, tof200f};
+
+// Maximum number of readings averaged over one period.
+const MAX_AVERAGED_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rate {
+    EveryFrame,
+    Hz(f64),
+}
+
+// Reduce tells what is sent for the readings received during a period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reduce {
+    // The latest reading (decimation).
+    #[default]
+    Latest,
+    // The average of the readings.
+    Average,
+}
+
+impl std::str::FromStr for Rate {
+    type Err = String;
+
+    // from_str() Parses "frame" (every new frame) or a rate in Hz, e.g. "20".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "frame" {
+            return Ok(Rate::EveryFrame);
+        }
+        match s.parse::<f64>() {
+            Ok(hz) if hz > 0.0 && hz.is_finite() => Ok(Rate::Hz(hz)),
+            _ => Err(format!("invalid rate {s:?}, expected a number of Hz or \"frame\"")),
+        }
+    }
+}
+
+impl StreamConfig {
+    // rate() Returns the rate given to a client asking for `requested`: the default rate if it did not ask
+    // for one, at most max_hz.
+    pub fn rate(&self, requested: Option<Rate>) -> Rate {
+        let rate = requested.unwrap_or(Rate::Hz(self.default_hz));
+        match (rate, self.max_hz) {
+            (Rate::EveryFrame, Some(max_hz)) => Rate::Hz(max_hz),
+            (Rate::Hz(hz), Some(max_hz)) => Rate::Hz(hz.min(max_hz)),
+            (rate, None) => rate,
+        }
+    }
+}
+
+// samples() Returns the readings of `sensor` at `rate`. The current reading, if any, is sent first.
+pub fn samples(sensor: Arc<Sensor>, rate: Rate, reduce: Reduce) -> impl Stream<Item = Sample> {
+    async_stream::stream! {
+        let mut latest = sensor.subscribe();
+        let Rate::Hz(hz) = rate else {
+            latest.mark_changed();
+            while latest.changed().await.is_ok() {
+                let sample = latest.borrow_and_update().clone();
+                if let Some(sample) = sample {
+                    yield sample;
+                }
+            }
+            return;
+        };
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / hz));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut pending: VecDeque<Sample> = latest.borrow_and_update().iter().cloned().collect();
+        loop {
+            let reduced = tokio::select! {
+                _ = interval.tick() => reduce_samples(&mut pending, reduce),
+                changed = latest.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    let sample = latest.borrow_and_update().clone();
+                    if let Some(sample) = sample {
+                        if reduce == Reduce::Latest || pending.len() == MAX_AVERAGED_SAMPLES {
+                            pending.pop_front();
+                        }
+                        pending.push_back(sample);
+                    }
+                    None
+                }
+            };
+            if let Some(sample) = reduced {
+                yield sample;
+            }
+        }
+    }
+}
+
+// reduce_samples() Empties `pending` and returns its latest reading or its average.
+fn reduce_samples(pending: &mut VecDeque<Sample>, reduce: Reduce) -> Option<Sample> {
+    let samples: Vec<Sample> = pending.drain(..).collect();
+    let latest = samples.last()?.clone();
+    if reduce == Reduce::Latest || samples.len() == 1 {
+        return Some(latest);
+    }
+    let data = average(&samples).unwrap_or(latest.data);
+    Some(Sample {
+        timestamp_ms: latest.timestamp_ms,
+        data,
+    })
+}
+
+// average() Averages the readings of a sensor. Distances, energies and target positions are averaged, the
+// other fields (LD2410C status, engineering data) are the latest ones. RD03D targets are averaged by slot
+// over the readings where the slot has a target, presence distances over the readings with a person, and the
+// people of a room by track id over the readings where they are tracked.
+pub(crate) fn average(samples: &[Sample]) -> Option<Reading> {
+    match &samples.last()?.data {
+        Reading::Tfluna(_) => {
+            let data: Vec<&tf_luna::TfLunaData> = samples
+                .iter()
+                .filter_map(|sample| match &sample.data {
+                    Reading::Tfluna(data) => Some(data),
+                    _ => None,
+                })
+                .collect();
+            let field = |get: fn(&tf_luna::TfLunaData) -> f64| mean(data.iter().map(|data| get(data)));
+            Some(Reading::Tfluna(tf_luna::TfLunaData {
+                distance: field(|data| data.distance as f64)?.round() as u16,
+                amplitude: field(|data| data.amplitude as f64)?.round() as u16,
+                temperature: field(|data| data.temperature as f64)? as f32,
+            }))
+        }
+        Reading::Tof200f(_) => {
+            let distances = samples.iter().filter_map(|sample| match &sample.data {
+                Reading::Tof200f(data) => Some(data.distance() as f64),
+                _ => None,
+            });
+            let distance = mean(distances)?;
+            Some(Reading::Tof200f(tof200f::Tof200fData::new(distance.round() as u16)))
+        }
+        Reading::Presence(latest) => {
+            let data: Vec<&presence::Presence> = samples
+                .iter()
+                .filter_map(|sample| match &sample.data {
+                    Reading::Presence(data) => Some(data),
+                    _ => None,
+                })
+                .collect();
+            let mut averaged = latest.clone();
+            averaged.confidence = mean(data.iter().map(|data| data.confidence as f64))? as f32;
+            if latest.present {
+                let distances = data.iter().filter_map(|data| data.distance_cm);
+                averaged.distance_cm = mean(distances.map(|distance| distance as f64)).map(|distance| distance.round() as u32);
+            }
+            Some(Reading::Presence(averaged))
+        }
+        Reading::Room(latest) => {
+            let mut averaged = latest.clone();
+            for target in &mut averaged.targets {
+                let tracked: Vec<&room::RoomTarget> = samples
+                    .iter()
+                    .filter_map(|sample| match &sample.data {
+                        Reading::Room(room) => room.targets.iter().find(|other| other.id == target.id),
+                        _ => None,
+                    })
+                    .collect();
+                let field = |get: fn(&room::RoomTarget) -> f64| {
+                    mean(tracked.iter().map(|target| get(target))).unwrap_or_default().round() as i32
+                };
+                target.x_mm = field(|target| target.x_mm as f64);
+                target.y_mm = field(|target| target.y_mm as f64);
+                target.vx_mm_s = field(|target| target.vx_mm_s as f64);
+                target.vy_mm_s = field(|target| target.vy_mm_s as f64);
+            }
+            Some(Reading::Room(averaged))
+        }
+        Reading::Ld2410c(latest) => {
+            let data: Vec<&ld2410c::Ld2410CData> = samples
+                .iter()
+                .filter_map(|sample| match &sample.data {
+                    Reading::Ld2410c(data) => Some(data),
+                    _ => None,
+                })
+                .collect();
+            let field = |get: fn(&ld2410c::Ld2410CData) -> f64| mean(data.iter().map(|data| get(data)));
+            let mut averaged = latest.clone();
+            let target = &mut averaged.target_data;
+            target.movement_target_distance =
+                field(|data| data.target_data.movement_target_distance as f64)?.round() as u16;
+            target.movement_target_energy = field(|data| data.target_data.movement_target_energy as f64)?.round() as u8;
+            target.stationary_target_distance =
+                field(|data| data.target_data.stationary_target_distance as f64)?.round() as u16;
+            target.stationary_target_energy =
+                field(|data| data.target_data.stationary_target_energy as f64)?.round() as u8;
+            target.detection_distance = field(|data| data.target_data.detection_distance as f64)?.round() as u16;
+            Some(Reading::Ld2410c(averaged))
+        }
+        Reading::Rd03d(latest) => {
+            let targets = (0..latest.len())
+                .map(|slot| {
+                    let slot_targets: Vec<&rd03d::Target> = samples
+                        .iter()
+                        .filter_map(|sample| match &sample.data {
+                            Reading::Rd03d(targets) => targets.get(slot),
+                            _ => None,
+                        })
+                        // Empty slots have a zero distance.
+                        .filter(|target| target.distance > 0.0)
+                        .collect();
+                    let field = |get: fn(&rd03d::Target) -> f64| {
+                        mean(slot_targets.iter().map(|target| get(target))).unwrap_or_default().round()
+                    };
+                    rd03d::Target::new(
+                        field(|target| target.x as f64) as i16,
+                        field(|target| target.y as f64) as i16,
+                        field(|target| target.speed as f64) as i16,
+                        field(|target| target.pixel_distance as f64) as u16,
+                    )
+                })
+                .collect();
+            Some(Reading::Rd03d(targets))
+        }
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp_ms: u64, data: Reading) -> Sample {
+        Sample { timestamp_ms, data }
+    }
+
+    #[test]
+    fn rate_from_str() {
+        assert_eq!("frame".parse::<Rate>(), Ok(Rate::EveryFrame));
+        assert_eq!("20".parse::<Rate>(), Ok(Rate::Hz(20.0)));
+        assert_eq!("0.5".parse::<Rate>(), Ok(Rate::Hz(0.5)));
+        for invalid in ["0", "-1", "inf", "NaN", "fast", ""] {
+            assert!(invalid.parse::<Rate>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn rate_capped_by_max_hz() {
+        let config = StreamConfig { default_hz: 2.0, max_hz: Some(10.0) };
+        assert_eq!(config.rate(None), Rate::Hz(2.0));
+        assert_eq!(config.rate(Some(Rate::Hz(5.0))), Rate::Hz(5.0));
+        assert_eq!(config.rate(Some(Rate::Hz(50.0))), Rate::Hz(10.0));
+        assert_eq!(config.rate(Some(Rate::EveryFrame)), Rate::Hz(10.0));
+        let unlimited = StreamConfig { default_hz: 2.0, max_hz: None };
+        assert_eq!(unlimited.rate(Some(Rate::EveryFrame)), Rate::EveryFrame);
+        assert_eq!(unlimited.rate(Some(Rate::Hz(50.0))), Rate::Hz(50.0));
+    }
+
+    #[test]
+    fn reduce_latest_and_average() {
+        let tof = |timestamp_ms, distance| sample(timestamp_ms, Reading::Tof200f(tof200f::Tof200fData::new(distance)));
+        let mut pending: VecDeque<Sample> = [tof(1, 100), tof(2, 200), tof(3, 600)].into_iter().collect();
+        let latest = reduce_samples(&mut pending.clone(), Reduce::Latest).unwrap();
+        assert!(matches!(latest.data, Reading::Tof200f(data) if data.distance() == 600));
+        let averaged = reduce_samples(&mut pending, Reduce::Average).unwrap();
+        assert_eq!(averaged.timestamp_ms, 3);
+        assert!(matches!(averaged.data, Reading::Tof200f(data) if data.distance() == 300));
+        assert!(pending.is_empty());
+        assert!(reduce_samples(&mut pending, Reduce::Average).is_none());
+    }
+
+    #[test]
+    fn average_rd03d_slots() {
+        let empty = rd03d::Target::new(0, 0, 0, 0);
+        let readings = [
+            vec![rd03d::Target::new(100, 1000, 10, 360), empty.clone()],
+            vec![rd03d::Target::new(300, 2000, 30, 360), rd03d::Target::new(-500, 1500, 0, 360)],
+            vec![rd03d::Target::new(200, 3000, 20, 360), empty.clone()],
+        ];
+        let samples: Vec<Sample> = readings
+            .into_iter()
+            .enumerate()
+            .map(|(i, targets)| sample(i as u64, Reading::Rd03d(targets)))
+            .collect();
+        let Some(Reading::Rd03d(targets)) = average(&samples) else {
+            panic!("not an RD03D reading");
+        };
+        assert_eq!((targets[0].x, targets[0].y, targets[0].speed), (200, 2000, 20));
+        // The second slot is averaged over the only reading where it has a target.
+        assert_eq!((targets[1].x, targets[1].y), (-500, 1500));
+        let samples = [sample(0, Reading::Rd03d(vec![empty.clone()])), sample(1, Reading::Rd03d(vec![empty]))];
+        let Some(Reading::Rd03d(targets)) = average(&samples) else {
+            panic!("not an RD03D reading");
+        };
+        assert_eq!(targets[0].distance, 0.0);
+    }
+
+    #[test]
+    fn average_room_tracks_by_id() {
+        let person = |id, x_mm, vx_mm_s| room::RoomTarget {
+            id,
+            x_mm,
+            y_mm: 2000,
+            vx_mm_s,
+            vy_mm_s: 0,
+            radars: vec!["a".to_string()],
+        };
+        let readings = [
+            vec![person(0, 100, 100), person(2, 5000, 0)],
+            vec![person(0, 200, 300), person(1, 1000, -100)],
+            vec![person(1, 1200, -300), person(0, 600, 200)],
+        ];
+        let samples: Vec<Sample> = readings
+            .into_iter()
+            .enumerate()
+            .map(|(i, targets)| sample(i as u64, Reading::Room(room::Room { targets })))
+            .collect();
+        let Some(Reading::Room(averaged)) = average(&samples) else {
+            panic!("not a room reading");
+        };
+        // The people of the latest reading, each averaged over the readings where it is tracked.
+        assert_eq!(averaged.targets, [person(1, 1100, -200), person(0, 300, 200)]);
+    }
+}
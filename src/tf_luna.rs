@@ -1,58 +1,34 @@
-use serde::Serialize;
+pub use crate::protocol::tf_luna::*;
+#[cfg(feature = "tokio")]
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "tokio")]
 use tokio_serial::{SerialPortBuilderExt, SerialStream};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
 
+// Default deadline of a read and of the response to a command, longer than the period of the lowest output
+// frequency (1 Hz).
+#[cfg(feature = "tokio")]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2000);
+// Default number of times a command is sent again when its response is not received.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+pub const DEFAULT_RETRIES: u8 = 2;
+// Delay after a baud rate command before reopening the port, for the TF-Luna to switch.
+#[cfg(feature = "tokio")]
+const BAUD_RATE_DELAY: Duration = Duration::from_millis(100);
+
+#[cfg(feature = "tokio")]
 pub struct TfLuna {
     path: String,
     baud_rate: u32,
+    timeout: Duration,
+    retries: u8,
     stream: Option<SerialStream>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
 }
 
-#[derive(Debug,Serialize,Clone)]
-pub struct TfLunaData {
-    distance: u16,
-}
-impl TfLunaData {
-    pub fn new(data: Vec<u8>) -> Self {
-        Self {
-            distance: u16::from_be_bytes([data[3], data[2]]),
-        }
-    }
-
-    pub fn distance(&self) -> u16 {
-        self.distance
-    }
-}
-pub enum OutputFormat {
-    NineByteCm = 0x01,
-    PIX = 0x02,
-    NineByteMm = 0x06,
-    ThirtyTwoTimestamp = 0x07,
-    IdZeroOutput = 0x08,
-    EightByteCm = 0x09,
-}
-
-pub enum OutputFrequency {
-    Freq1Hz = 1,
-    Freq2Hz = 2,
-    Freq4Hz = 4,
-    Freq8Hz = 8,
-    Freq16Hz = 16,
-    Freq10Hz = 10,
-    Freq32Hz = 32,
-    Freq64Hz = 64,
-    Freq128Hz = 128,
-    Freq100Hz = 100,
-    Freq250Hz = 250,
-}
-
-pub enum OutputMode {
-    Frequency = 0x03,
-    DistanceLimit = 0x04,
-    OutputFormat = 0x05,
-    BaudRate = 0x06,
-}
-
+#[cfg(feature = "tokio")]
 impl TfLuna {
     pub fn new(path: String) -> Self {
         Self {
@@ -60,7 +36,10 @@ impl TfLuna {
             // Set default baud rate to 115200
             // This can be changed later using the `set_baud_rate` method
             baud_rate: 115200,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
             stream: None,
+            buffer: Vec::new(),
         }
     }
 
@@ -68,40 +47,110 @@ impl TfLuna {
         self.baud_rate = baud_rate;
     }
 
-    pub async fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    // baud_rate() Returns the baud rate the port is (or will be) opened at.
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    // set_timeout() Sets the deadline of a read and of the response to a command (DEFAULT_TIMEOUT by default).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // set_retries() Sets the number of times a command is sent again when its response is not received.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    pub async fn connect(&mut self) -> anyhow::Result<()> {
         match tokio_serial::new(&self.path, self.baud_rate).open_native_async() {
             Ok(stream) => {
                 self.stream = Some(stream);
                 Ok(())
             }
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub async fn read_data(&mut self) -> Result<TfLunaData, Box<dyn std::error::Error>> {
-        let mut buf = [0u8; 32];
-        match self.stream.as_mut().unwrap().read(&mut buf).await {
-            Ok(n) => {
-                let _ = std::time::Duration::from_millis(250);
-                let tf_luna_data = TfLunaData::new(buf[..n].to_vec()); // Wait for 250ms to ensure data is read
-                Ok(tf_luna_data)
-            }
-            Err(e) => {
-                println!("Error: {:?}", e);
-                Err(Box::new(e))
+    // read_data() Reads until a data frame with a valid checksum is received and decodes it. Fails if no data
+    // frame was received before the timeout.
+    pub async fn read_data(&mut self) -> anyhow::Result<TfLunaData> {
+        let timeout = self.timeout;
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("TF-Luna is not connected"))?;
+        let buffer = &mut self.buffer;
+        let read = async {
+            let mut buf = [0u8; 32];
+            loop {
+                if let Some((frame, end)) = find_data_frame(buffer) {
+                    let data = TfLunaData::new(frame);
+                    buffer.drain(..end);
+                    return Ok(data);
+                }
+                // Keep the last bytes, they may be the beginning of a frame.
+                buffer.drain(..buffer.len().saturating_sub(DATA_FRAME_LEN - 1));
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                buffer.extend_from_slice(&buf[..n]);
             }
+        };
+        match tokio::time::timeout(timeout, read).await {
+            Ok(data) => data,
+            Err(_) => anyhow::bail!("no data frame from the TF-Luna within {timeout:?}"),
         }
     }
-    pub async fn write_data(&mut self, command: &[u8]) {
-        match self.stream.as_mut().unwrap().write(&command).await {
-            Ok(_) => {
-                println!("Command sent: {:02X?}", command);
+    // write_data() Sends a command to the TF-Luna.
+    pub async fn write_data(&mut self, command: &[u8]) -> anyhow::Result<()> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("TF-Luna is not connected"))?;
+        stream.write_all(command).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    // read_response() Reads until the response to the command `id` is received and returns it. The data frames
+    // received in between are discarded. Returns None if it was not received before the timeout.
+    async fn read_response(&mut self, id: u8) -> anyhow::Result<Option<Vec<u8>>> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("TF-Luna is not connected"))?;
+        let mut response = Vec::new();
+        let read = async {
+            let mut buf = [0u8; 256];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                response.extend_from_slice(&buf[..n]);
+                if let Some((frame, _)) = find_response(&response, id) {
+                    return Ok(frame.to_vec());
+                }
             }
-            Err(e) => {
-                println!("Failed to send command: {:?}", e);
-                return;
+        };
+        match tokio::time::timeout(self.timeout, read).await {
+            Ok(frame) => frame.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // send_command() Sends a command and returns the response of the TF-Luna, which repeats the command id,
+    // sending it again up to `retries` times when the response is not received before the timeout.
+    pub async fn send_command(&mut self, command: TfLunaCommand) -> anyhow::Result<Vec<u8>> {
+        for _ in 0..=self.retries {
+            self.write_data(&command.to_u8()).await?;
+            if let Some(response) = self.read_response(command.id()).await? {
+                return Ok(response);
             }
         }
+        anyhow::bail!("no response from the TF-Luna to command {:#04X}", command.id())
     }
 
     // pub async fn get_version_information(&mut self) {
@@ -116,55 +165,54 @@ impl TfLuna {
     //     println!("Version information sent. {:?}", version);
     // }
 
-    pub async fn set_output_format_setting(&mut self, format: OutputFormat) {
-        let command: [u8; 5] = [0x5A, 0x05, 0x05, format as u8, 0x00];
-        self.write_data(&command).await;
-    }
-
-    pub async fn set_baud_rate_setting(&mut self, baud_rate: u32) {
-        let command = [
-            0x5A,
-            0x08,
-            0x06,
-            baud_rate.to_be_bytes()[3],
-            baud_rate.to_be_bytes()[2],
-            baud_rate.to_be_bytes()[1],
-            baud_rate.to_be_bytes()[0],
-            0x00,
-        ];
-        self.write_data(&command).await;
-    }
-
-    pub async fn set_distance_limit_setting(&mut self, dist_min: u16, dist_max: u16) {
-        let command = [
-            0x5A,
-            0x09,
-            0x3A,
-            dist_min.to_be_bytes()[1],
-            dist_min.to_be_bytes()[0],
-            dist_max.to_be_bytes()[1],
-            dist_max.to_be_bytes()[0],
-            0x00,
-            0x00,
-        ];
-        self.write_data(&command).await;
-    }
-
-    pub async fn set_output_frequency(&mut self, freq: OutputFrequency) {
-        let bytes_freq = freq as u16;
-        let command = [
-            0x5A,
-            0x06,
-            0x3,
-            bytes_freq.to_be_bytes()[1],
-            bytes_freq.to_be_bytes()[0],
-            0x00,
-        ];
-        self.write_data(&command).await;
-    }
-    pub async fn get_configuration(&mut self, output_mode: OutputMode) {
-        let command = [0x5A, 0x05, 0x3F, output_mode as u8, 0x00];
-        self.write_data(&command).await;
-        self.read_data().await.unwrap();
+    pub async fn set_output_format_setting(&mut self, format: OutputFormat) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::OutputFormat(format)).await?;
+        Ok(())
+    }
+
+    pub async fn set_baud_rate_setting(&mut self, baud_rate: u32) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::BaudRate(baud_rate)).await?;
+        Ok(())
+    }
+
+    // change_baud_rate() Sets the baud rate of the TF-Luna and reopens the port at the new rate. The TF-Luna
+    // may already answer at the new rate, so its response is not waited for: the new rate is checked by
+    // reading a data frame. If none is received, the port is reopened at the previous rate, so that the
+    // sensor can still be read, and an error is returned.
+    pub async fn change_baud_rate(&mut self, baud_rate: u32) -> anyhow::Result<()> {
+        let previous = self.baud_rate;
+        self.write_data(&TfLunaCommand::BaudRate(baud_rate).to_u8()).await?;
+        tokio::time::sleep(BAUD_RATE_DELAY).await;
+        self.reopen(baud_rate).await?;
+        if let Err(e) = self.read_data().await {
+            self.reopen(previous).await?;
+            match self.read_data().await {
+                Ok(_) => anyhow::bail!("the TF-Luna does not send data at {baud_rate} baud ({e}), kept {previous} baud"),
+                Err(_) => anyhow::bail!("the TF-Luna does not send data at {baud_rate} baud ({e}) nor at {previous} baud"),
+            }
+        }
+        Ok(())
+    }
+
+    // reopen() Closes the port and opens it again at `baud_rate`.
+    async fn reopen(&mut self, baud_rate: u32) -> anyhow::Result<()> {
+        self.stream = None;
+        self.buffer.clear();
+        self.baud_rate = baud_rate;
+        self.connect().await
+    }
+
+    pub async fn set_distance_limit_setting(&mut self, dist_min: u16, dist_max: u16) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::DistanceLimit { dist_min, dist_max }).await?;
+        Ok(())
+    }
+
+    pub async fn set_output_frequency(&mut self, freq: OutputFrequency) -> anyhow::Result<()> {
+        self.send_command(TfLunaCommand::OutputFrequency(freq)).await?;
+        Ok(())
+    }
+    pub async fn get_configuration(&mut self, output_mode: OutputMode) -> anyhow::Result<TfLunaData> {
+        self.write_data(&TfLunaCommand::GetConfiguration(output_mode).to_u8()).await?;
+        self.read_data().await
     }
 }
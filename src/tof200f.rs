@@ -1,19 +1,35 @@
 
 
+pub use crate::protocol::tof200f::*;
+#[cfg(feature = "tokio")]
 use tokio::io::AsyncReadExt;
+#[cfg(feature = "tokio")]
 use tokio_serial::{SerialPortBuilderExt, SerialStream};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
 
+// Default deadline of a read.
+#[cfg(feature = "tokio")]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[cfg(feature = "tokio")]
 pub struct TOF200F {
     path: String,
     baud_rate: u32,
+    timeout: Duration,
     stream: Option<SerialStream>,
+    // Received bytes not decoded yet.
+    buffer: Vec<u8>,
 }
+#[cfg(feature = "tokio")]
 impl TOF200F {
     pub fn new(path: String) -> Self {
         Self {
             path,
             baud_rate: 115200,
+            timeout: DEFAULT_TIMEOUT,
             stream: None,
+            buffer: Vec::new(),
         }
     }
 
@@ -21,6 +37,11 @@ impl TOF200F {
         self.baud_rate = baud_rate;
     }
 
+    // set_timeout() Sets the deadline of a read (DEFAULT_TIMEOUT by default).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
     pub async fn connect(&mut self) -> anyhow::Result<()> {
         match tokio_serial::new(&self.path, self.baud_rate).open_native_async() {
             Ok(stream) => {
@@ -31,18 +52,35 @@ impl TOF200F {
         }
     }
     
-    pub async fn read_data(&mut self)-> anyhow::Result<()>{
-        let mut buf = [0u8; 1024];
-        match self.stream.as_mut().unwrap().read(&mut buf).await {
-            Ok(n) => {
-                println!("Buffer: {:?}", &buf[3..n]);
-                let distance = (u16::from(buf[4]) + (u16::from(buf[3]) * 255)) as u16;
-                println!("Distance: {:?} mm", distance);
-                Ok(())
-            }
-            Err(e) => {
-                Err(e.into())
+    // read_data() Reads until a response with a valid CRC is received and decodes the distance. Fails if no
+    // response was received before the timeout.
+    pub async fn read_data(&mut self)-> anyhow::Result<Tof200fData>{
+        let timeout = self.timeout;
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("TOF200F is not connected"))?;
+        let buffer = &mut self.buffer;
+        let read = async {
+            let mut buf = [0u8; 256];
+            loop {
+                if let Some((frame, end)) = find_response(buffer) {
+                    let data = Tof200fData::from_frame(frame);
+                    buffer.drain(..end);
+                    return data.ok_or_else(|| anyhow::anyhow!("invalid TOF200F response"));
+                }
+                // Keep the last bytes, they may be the beginning of a response.
+                buffer.drain(..buffer.len().saturating_sub(RESPONSE_LEN - 1));
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into());
+                }
+                buffer.extend_from_slice(&buf[..n]);
             }
+        };
+        match tokio::time::timeout(timeout, read).await {
+            Ok(data) => data,
+            Err(_) => anyhow::bail!("no response from the TOF200F within {timeout:?}"),
         }
     }
 }
\ No newline at end of file
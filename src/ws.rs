@@ -0,0 +1,410 @@
+// ws.rs
+// WebSocket endpoint (/ws) of the web server. A client subscribes to any set of sensors, at a given rate or
+// on every new frame, and can send the configuration commands of the REST API over the same connection.
+//
+// Client messages (request_id is optional and copied into the answer):
+//   {"type": "subscribe", "request_id": 1, "sensors": ["rd03d", "tfluna"], "hz": 10, "mode": "average"}
+//   (every frame without hz, latest reading of each period without mode, capped by max_hz of [stream])
+//   {"type": "unsubscribe", "request_id": 2, "sensors": ["tfluna"]}
+//   {"type": "command", "request_id": 3, "sensor": "ld2410c", "method": "PUT", "setting": "engineering-mode",
+//    "body": {"enabled": true}}
+// Server messages:
+//   {"type": "data", "sensor": "rd03d", "timestamp_ms": 1718000000000, "data": [...]}
+//   {"type": "ack", "request_id": 3, "result": {...}}
+//   {"type": "error", "request_id": 3, "error": "..."}
+//
+// Commands run the configuration endpoints (/api/sensors/{sensor}/{setting}) with the token given when
+// connecting (`/ws?token=...` or the Authorization header).
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{header, HeaderMap, Method, Request},
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tower::ServiceExt;
+
+use crate::config::StreamConfig;
+use crate::hub::{Hub, Sensor};
+use crate::stream::{self, Rate, Reduce};
+
+// Maximum size of a command response read back from the configuration endpoints.
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+// Number of messages waiting to be sent to a client.
+const OUTGOING_QUEUE_LEN: usize = 64;
+
+#[derive(Clone)]
+struct WsState {
+    hub: Hub,
+    // Configuration endpoints, run for the command messages.
+    commands: Router,
+    stream: StreamConfig,
+}
+
+// router() Returns the /ws route. `commands` is the router of the configuration endpoints.
+pub fn router(hub: Hub, commands: Router, stream: StreamConfig) -> Router {
+    Router::new()
+        .route("/ws", get(ws_handler))
+        .with_state(WsState { hub, commands, stream })
+}
+
+#[derive(Deserialize)]
+struct WsQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        request_id: Option<Value>,
+        sensors: Vec<String>,
+        // Messages per second for each sensor, every new frame if missing.
+        #[serde(default)]
+        hz: Option<f64>,
+        #[serde(default)]
+        mode: Reduce,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        request_id: Option<Value>,
+        sensors: Vec<String>,
+    },
+    Command {
+        #[serde(default)]
+        request_id: Option<Value>,
+        sensor: String,
+        #[serde(default = "default_method")]
+        method: String,
+        setting: String,
+        #[serde(default)]
+        body: Option<Value>,
+    },
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Data {
+        sensor: &'a str,
+        timestamp_ms: u64,
+        data: &'a crate::hub::Reading,
+    },
+    Ack {
+        request_id: Option<Value>,
+        result: Value,
+    },
+    Error {
+        request_id: Option<Value>,
+        error: String,
+    },
+}
+
+impl ServerMessage<'_> {
+    fn to_message(&self) -> Message {
+        Message::text(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+async fn ws_handler(
+    State(state): State<WsState>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let authorization = query
+        .token
+        .map(|token| format!("Bearer {token}"))
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        });
+    ws.on_upgrade(move |socket| connection(socket, state, authorization))
+}
+
+// connection() Handles the messages of a client until it disconnects.
+async fn connection(socket: WebSocket, state: WsState, authorization: Option<String>) {
+    let (mut sink, mut stream) = socket.split();
+    let (outgoing, mut outgoing_rx) = mpsc::channel::<Message>(OUTGOING_QUEUE_LEN);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = outgoing_rx.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut subscriptions: HashMap<String, JoinHandle<()>> = HashMap::new();
+    // The command tasks are aborted when the client disconnects (JoinSet dropped).
+    let mut commands = JoinSet::new();
+    while let Some(Ok(message)) = stream.next().await {
+        // Reaps the commands that are done.
+        while commands.try_join_next().is_some() {}
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let answer = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Subscribe {
+                request_id,
+                sensors,
+                hz,
+                mode,
+            }) => {
+                subscribe(&state, &mut subscriptions, &outgoing, &sensors, hz, mode)
+                    .map(|()| ack(request_id.clone(), serde_json::json!({ "subscribed": sensors })))
+                    .unwrap_or_else(|error| ServerMessage::Error { request_id, error })
+            }
+            Ok(ClientMessage::Unsubscribe { request_id, sensors }) => {
+                for id in &sensors {
+                    if let Some(task) = subscriptions.remove(id) {
+                        task.abort();
+                    }
+                }
+                ack(request_id, serde_json::json!({ "unsubscribed": sensors }))
+            }
+            Ok(ClientMessage::Command {
+                request_id,
+                sensor,
+                method,
+                setting,
+                body,
+            }) => {
+                // The command may take a few seconds, the answer is sent when it is done and the other messages
+                // of the client are handled meanwhile.
+                let (router, authorization, outgoing) =
+                    (state.commands.clone(), authorization.clone(), outgoing.clone());
+                commands.spawn(async move {
+                    let result = command(&router, authorization.as_deref(), &sensor, &method, &setting, body).await;
+                    let answer = match result {
+                        Ok(result) => ack(request_id, result),
+                        Err(error) => ServerMessage::Error { request_id, error },
+                    };
+                    let _ = outgoing.send(answer.to_message()).await;
+                });
+                continue;
+            }
+            Err(e) => ServerMessage::Error {
+                request_id: None,
+                error: format!("invalid message: {e}"),
+            },
+        };
+        if outgoing.send(answer.to_message()).await.is_err() {
+            break;
+        }
+    }
+    for task in subscriptions.into_values() {
+        task.abort();
+    }
+    writer.abort();
+}
+
+fn ack(request_id: Option<Value>, result: Value) -> ServerMessage<'static> {
+    ServerMessage::Ack { request_id, result }
+}
+
+// subscribe() Starts (or restarts with the new rate) the forwarding of the readings of `sensors`.
+fn subscribe(
+    state: &WsState,
+    subscriptions: &mut HashMap<String, JoinHandle<()>>,
+    outgoing: &mpsc::Sender<Message>,
+    sensors: &[String],
+    hz: Option<f64>,
+    mode: Reduce,
+) -> Result<(), String> {
+    if let Some(unknown) = sensors.iter().find(|id| state.hub.get(id).is_none()) {
+        return Err(format!("unknown sensor {unknown:?}"));
+    }
+    let requested = match hz {
+        Some(hz) if !(hz > 0.0 && hz.is_finite()) => return Err(format!("invalid rate {hz} Hz")),
+        Some(hz) => Rate::Hz(hz),
+        None => Rate::EveryFrame,
+    };
+    let rate = state.stream.rate(Some(requested));
+    for id in sensors {
+        let sensor = state.hub.get(id).cloned().expect("sensor checked above");
+        let task = tokio::spawn(forward(sensor, outgoing.clone(), rate, mode));
+        if let Some(previous) = subscriptions.insert(id.clone(), task) {
+            previous.abort();
+        }
+    }
+    Ok(())
+}
+
+// forward() Sends the readings of a sensor to the client.
+async fn forward(sensor: Arc<Sensor>, outgoing: mpsc::Sender<Message>, rate: Rate, mode: Reduce) {
+    let samples = stream::samples(sensor.clone(), rate, mode);
+    futures::pin_mut!(samples);
+    while let Some(sample) = samples.next().await {
+        let message = ServerMessage::Data {
+            sensor: &sensor.config.id,
+            timestamp_ms: sample.timestamp_ms,
+            data: &sample.data,
+        }
+        .to_message();
+        if outgoing.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+// command() Runs a configuration endpoint and returns its JSON result, or its error message.
+async fn command(
+    commands: &Router,
+    authorization: Option<&str>,
+    sensor: &str,
+    method: &str,
+    setting: &str,
+    body: Option<Value>,
+) -> Result<Value, String> {
+    let valid = |part: &str| !part.is_empty() && !part.contains(['/', '?', '#', '%']);
+    if !valid(sensor) || !valid(setting) {
+        return Err("invalid sensor or setting".to_string());
+    }
+    let method = Method::from_bytes(method.to_uppercase().as_bytes()).map_err(|e| e.to_string())?;
+    let mut request = Request::builder()
+        .method(method)
+        .uri(format!("/api/sensors/{sensor}/{setting}"))
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(authorization) = authorization {
+        request = request.header(header::AUTHORIZATION, authorization);
+    }
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let request = request.body(Body::from(body)).map_err(|e| e.to_string())?;
+    let response = commands.clone().oneshot(request).await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), MAX_RESPONSE_LEN)
+        .await
+        .map_err(|e| e.to_string())?;
+    let json: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if status.is_success() {
+        Ok(json)
+    } else {
+        let error = json["error"].as_str().map(str::to_string);
+        Err(error.unwrap_or_else(|| format!("{status}: {}", String::from_utf8_lossy(&bytes))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Path, http::StatusCode, routing::any, Json};
+
+    #[test]
+    fn parses_client_messages() {
+        let message = r#"{"type": "subscribe", "sensors": ["rd03d", "tfluna"], "hz": 10, "mode": "average"}"#;
+        let Ok(ClientMessage::Subscribe { request_id, sensors, hz, mode }) = serde_json::from_str(message) else {
+            panic!("not a subscribe message");
+        };
+        assert_eq!(request_id, None);
+        assert_eq!(sensors, ["rd03d", "tfluna"]);
+        assert_eq!((hz, mode), (Some(10.0), Reduce::Average));
+
+        let message = r#"{"type": "subscribe", "request_id": "a", "sensors": []}"#;
+        let Ok(ClientMessage::Subscribe { request_id, hz, mode, .. }) = serde_json::from_str(message) else {
+            panic!("not a subscribe message");
+        };
+        assert_eq!((request_id, hz, mode), (Some(Value::from("a")), None, Reduce::Latest));
+
+        let message = r#"{"type": "command", "request_id": 3, "sensor": "ld2410c", "setting": "engineering-mode"}"#;
+        let Ok(ClientMessage::Command {
+            request_id,
+            sensor,
+            method,
+            setting,
+            body,
+        }) = serde_json::from_str(message)
+        else {
+            panic!("not a command message");
+        };
+        assert_eq!(request_id, Some(Value::from(3)));
+        assert_eq!((sensor.as_str(), method.as_str(), setting.as_str()), ("ld2410c", "GET", "engineering-mode"));
+        assert_eq!(body, None);
+
+        for message in [
+            r#"{"type": "publish", "sensors": []}"#,
+            r#"{"type": "unsubscribe"}"#,
+            r#"{"type": "command", "sensor": "ld2410c"}"#,
+            r#"{"sensors": []}"#,
+        ] {
+            assert!(serde_json::from_str::<ClientMessage>(message).is_err(), "{message}");
+        }
+    }
+
+    // commands() Returns a router that answers the configuration endpoints with the request it received, or
+    // with an error for the "broken" setting.
+    fn commands() -> Router {
+        Router::new().route(
+            "/api/sensors/{sensor}/{setting}",
+            any(|Path((sensor, setting)): Path<(String, String)>, request: Request<Body>| async move {
+                if setting == "broken" {
+                    return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "broken setting" })));
+                }
+                let authorization = request.headers().get(header::AUTHORIZATION);
+                let authorization = authorization.map(|value| value.to_str().unwrap().to_string());
+                let method = request.method().to_string();
+                let bytes = axum::body::to_bytes(request.into_body(), MAX_RESPONSE_LEN).await.unwrap();
+                let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+                let json = serde_json::json!({
+                    "sensor": sensor,
+                    "setting": setting,
+                    "method": method,
+                    "authorization": authorization,
+                    "body": body,
+                });
+                (StatusCode::OK, Json(json))
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn command_runs_the_configuration_endpoint() {
+        let body = Some(serde_json::json!({ "enabled": true }));
+        let result = command(&commands(), Some("Bearer secret"), "radar", "put", "engineering-mode", body).await;
+        assert_eq!(
+            result.unwrap(),
+            serde_json::json!({
+                "sensor": "radar", "setting": "engineering-mode", "method": "PUT",
+                "authorization": "Bearer secret", "body": { "enabled": true },
+            })
+        );
+        let result = command(&commands(), None, "radar", "GET", "settings", None).await.unwrap();
+        assert_eq!((&result["authorization"], &result["body"]), (&Value::Null, &Value::Null));
+    }
+
+    #[tokio::test]
+    async fn command_returns_the_error_of_the_endpoint() {
+        let result = command(&commands(), None, "radar", "GET", "broken", None).await;
+        assert_eq!(result.unwrap_err(), "broken setting");
+        assert!(command(&commands(), None, "radar", "NOT A METHOD", "settings", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn command_rejects_the_parts_that_change_the_uri() {
+        for part in ["", "a/b", "..%2F", "a?b=c", "a#b", "%2e%2e"] {
+            for (sensor, setting) in [(part, "settings"), ("radar", part)] {
+                let result = command(&commands(), None, sensor, "GET", setting, None).await;
+                assert_eq!(result.unwrap_err(), "invalid sensor or setting", "{sensor:?} {setting:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+// tests/commands.rs
+// Tests of the command encoders: golden bytes taken from the examples of the datasheets in docs/,
+// and encoding -> parse() round trips over the whole range of the command values.
+use proptest::prelude::*;
+
+use sensor::protocol::ld2410c::{
+    self, AuxiliaryControl, BaudRate, BluetoothModule, CommandError, DecodeError, DistanceResolution,
+    GateValue, LightControl, Ld2410CFrame, OutPinLevel,
+};
+use sensor::protocol::rd03d::{self, RD03DFrame, MULTI_TARGET_CMD, SINGLE_TARGET_CMD};
+use sensor::protocol::tf_luna::{OutputFormat, OutputFrequency, OutputMode, TfLunaCommand, BAUD_RATES};
+
+// LD2410C: docs/LD2410C/Protocolo_comunicacion_serial_LD2410C.pdf, 2.2 Command Protocol.
+#[test]
+fn ld2410c_golden_frames() {
+    let frames: [(Ld2410CFrame, &[u8]); 14] = [
+        (
+            Ld2410CFrame::enabling_configuration(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xFF, 0x00, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::ending_configuration(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xFE, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::read_parameter(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x61, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::engineering_mode(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x62, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::engineering_mode_off(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0x63, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::read_firmware_version(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xA0, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::serial_port_baud_rate(BaudRate::BaudRate256000),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xA1, 0x00, 0x07, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::restore_factory_settings(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xA2, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::restart_module(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xA3, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::bluetooth_module(BluetoothModule::TurnOn),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xA4, 0x00, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::mac_address(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xA5, 0x00, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::distance_resolution_setting(DistanceResolution::DistanceGate0_2m),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xAA, 0x00, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::query_distance_resolution_setting(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xAB, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+        (
+            Ld2410CFrame::query_auxiliary_control(),
+            &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xAE, 0x00, 0x04, 0x03, 0x02, 0x01],
+        ),
+    ];
+    for (frame, expected) in frames {
+        assert_eq!(&frame.to_u8()[..], expected, "command {:#06X}", frame.word());
+    }
+}
+
+#[test]
+fn ld2410c_golden_passwords() {
+    // Default password "HiLink".
+    let hilink = [0x48, 0x69, 0x4C, 0x69, 0x6E, 0x6B];
+    let permissions = Ld2410CFrame::obtaining_bluetooth_permissions("HiLink").unwrap();
+    let expected = [&[0xFD, 0xFC, 0xFB, 0xFA, 0x08, 0x00, 0xA8, 0x00][..], &hilink, &[0x04, 0x03, 0x02, 0x01]].concat();
+    assert_eq!(&permissions.to_u8()[..], &expected[..]);
+    let password = Ld2410CFrame::bluetooth_password("HiLink").unwrap();
+    let expected = [&[0xFD, 0xFC, 0xFB, 0xFA, 0x08, 0x00, 0xA9, 0x00][..], &hilink, &[0x04, 0x03, 0x02, 0x01]].concat();
+    assert_eq!(&password.to_u8()[..], &expected[..]);
+    assert_eq!(Ld2410CFrame::bluetooth_password("Hi").err(), Some(CommandError::InvalidPassword));
+}
+
+#[test]
+fn ld2410c_golden_gate_sensitivity() {
+    // Motion and standstill sensitivity 40 (0x28), for all the gates then for gate 3.
+    let all = Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValueAll, 40, 40).unwrap();
+    assert_eq!(
+        &all.to_u8()[..],
+        &[
+            0xFD, 0xFC, 0xFB, 0xFA, 0x14, 0x00, 0x64, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x01, 0x00, 0x28,
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x28, 0x00, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01,
+        ]
+    );
+    let gate3 = Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValue3, 40, 40).unwrap();
+    assert_eq!(
+        &gate3.to_u8()[..],
+        &[
+            0xFD, 0xFC, 0xFB, 0xFA, 0x14, 0x00, 0x64, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x28,
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x28, 0x00, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01,
+        ]
+    );
+    assert_eq!(
+        Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValue3, 101, 40).err(),
+        Some(CommandError::InvalidSensitivity)
+    );
+}
+
+#[test]
+fn ld2410c_golden_auxiliary_control() {
+    let auxiliary_control = AuxiliaryControl::new(LightControl::BelowThreshold, 0x80, OutPinLevel::High);
+    assert_eq!(
+        &Ld2410CFrame::auxiliary_control(auxiliary_control).to_u8()[..],
+        &[0xFD, 0xFC, 0xFB, 0xFA, 0x06, 0x00, 0xAD, 0x00, 0x01, 0x80, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01]
+    );
+}
+
+#[test]
+fn ld2410c_parse_rejects_invalid_frames() {
+    let frame = Ld2410CFrame::read_firmware_version().to_u8();
+    assert!(matches!(Ld2410CFrame::parse(&frame[..8]), Err(DecodeError::TooShort { .. })));
+    let mut header = frame.clone();
+    header[0] = 0x00;
+    assert_eq!(Ld2410CFrame::parse(&header), Err(DecodeError::InvalidFrameHeader));
+    let mut end = frame.clone();
+    end[11] = 0x00;
+    assert_eq!(Ld2410CFrame::parse(&end), Err(DecodeError::InvalidFrameEnd));
+    let mut word = frame.clone();
+    word[6] = 0x42;
+    assert_eq!(Ld2410CFrame::parse(&word), Err(DecodeError::UnknownCommand(0x0042)));
+    // Known command, value out of range (gate 9).
+    let mut gate = Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValue8, 10, 10)
+        .unwrap()
+        .to_u8();
+    gate[10] = 0x09;
+    assert_eq!(Ld2410CFrame::parse(&gate), Err(DecodeError::InvalidCommandValue(0x0064)));
+}
+
+// RD03D: target mode commands of docs/RD-03D/rpi_mmwave/rd03d.py, the other ones from
+// docs/RD-03D/rd-03_v1.0.1_serial_communication_protocol.pdf.
+#[test]
+fn rd03d_golden_frames() {
+    assert_eq!(
+        &RD03DFrame::open_command_mode().to_u8()[..],
+        &[0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xFF, 0x00, 0x01, 0x00, 0x04, 0x03, 0x02, 0x01]
+    );
+    assert_eq!(
+        &RD03DFrame::close_command_mode().to_u8()[..],
+        &[0xFD, 0xFC, 0xFB, 0xFA, 0x02, 0x00, 0xFE, 0x00, 0x04, 0x03, 0x02, 0x01]
+    );
+    // Working mode (0x64).
+    assert_eq!(
+        &RD03DFrame::set_mode(0x64).to_u8()[..],
+        &[0xFD, 0xFC, 0xFB, 0xFA, 0x08, 0x00, 0x12, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01]
+    );
+    assert_eq!(
+        RD03DFrame::parse(SINGLE_TARGET_CMD),
+        Some(rd03d::DecodedCommand::TargetMode { multi_mode: false })
+    );
+    assert_eq!(
+        RD03DFrame::parse(MULTI_TARGET_CMD),
+        Some(rd03d::DecodedCommand::TargetMode { multi_mode: true })
+    );
+}
+
+// TF-Luna: docs/TF-Luna/SJ-PM-TF-Luna-A03-Product-Manual.pdf, 6.2 Command list.
+#[test]
+fn tf_luna_golden_commands() {
+    let commands: [(TfLunaCommand, &[u8]); 4] = [
+        (TfLunaCommand::OutputFormat(OutputFormat::NineByteCm), &[0x5A, 0x05, 0x05, 0x01, 0x65]),
+        (TfLunaCommand::OutputFormat(OutputFormat::NineByteMm), &[0x5A, 0x05, 0x05, 0x06, 0x6A]),
+        (TfLunaCommand::OutputFrequency(OutputFrequency::Freq100Hz), &[0x5A, 0x06, 0x03, 0x64, 0x00, 0xC7]),
+        (TfLunaCommand::BaudRate(115200), &[0x5A, 0x08, 0x06, 0x00, 0xC2, 0x01, 0x00, 0x2B]),
+    ];
+    for (command, expected) in commands {
+        assert_eq!(&command.to_u8()[..], expected, "{command:?}");
+    }
+}
+
+#[test]
+fn tf_luna_parse_rejects_invalid_commands() {
+    let command = TfLunaCommand::OutputFrequency(OutputFrequency::Freq10Hz).to_u8();
+    let mut checksum = command.clone();
+    checksum[5] ^= 0xFF;
+    assert_eq!(TfLunaCommand::parse(&checksum), None);
+    assert_eq!(TfLunaCommand::parse(&command[..5]), None);
+    // 12 Hz is not a frequency of the TF-Luna.
+    let mut frequency = vec![0x5A, 0x06, 0x03, 0x0C, 0x00];
+    frequency.push(frequency.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+    assert_eq!(TfLunaCommand::parse(&frequency), None);
+}
+
+fn gate_value() -> impl Strategy<Value = GateValue> {
+    prop_oneof![
+        (0u8..=8).prop_map(|gate| GateValue::from_gate(gate).unwrap()),
+        Just(GateValue::GateValueAll),
+    ]
+}
+
+fn auxiliary_control() -> impl Strategy<Value = AuxiliaryControl> {
+    let light_control = prop_oneof![
+        Just(LightControl::Off),
+        Just(LightControl::BelowThreshold),
+        Just(LightControl::AboveThreshold),
+    ];
+    let out_pin_level = prop_oneof![Just(OutPinLevel::Low), Just(OutPinLevel::High)];
+    (light_control, any::<u8>(), out_pin_level)
+        .prop_map(|(light_control, threshold, level)| AuxiliaryControl::new(light_control, threshold, level))
+}
+
+fn output_frequency() -> impl Strategy<Value = OutputFrequency> {
+    prop::sample::select(vec![1u16, 2, 4, 8, 10, 16, 32, 64, 100, 128, 250])
+        .prop_map(|hz| OutputFrequency::from_hz(hz).unwrap())
+}
+
+fn tf_luna_command() -> impl Strategy<Value = TfLunaCommand> {
+    let output_format = prop::sample::select(vec![
+        OutputFormat::NineByteCm,
+        OutputFormat::PIX,
+        OutputFormat::NineByteMm,
+        OutputFormat::ThirtyTwoTimestamp,
+        OutputFormat::IdZeroOutput,
+        OutputFormat::EightByteCm,
+    ]);
+    let output_mode = prop::sample::select(vec![
+        OutputMode::Frequency,
+        OutputMode::DistanceLimit,
+        OutputMode::OutputFormat,
+        OutputMode::BaudRate,
+    ]);
+    prop_oneof![
+        output_format.prop_map(TfLunaCommand::OutputFormat),
+        prop::sample::select(BAUD_RATES.to_vec()).prop_map(TfLunaCommand::BaudRate),
+        any::<u32>().prop_map(TfLunaCommand::BaudRate),
+        (any::<u16>(), any::<u16>())
+            .prop_map(|(dist_min, dist_max)| TfLunaCommand::DistanceLimit { dist_min, dist_max }),
+        output_frequency().prop_map(TfLunaCommand::OutputFrequency),
+        output_mode.prop_map(TfLunaCommand::GetConfiguration),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn ld2410c_gate_sensitivity_round_trip(
+        distance_gate in gate_value(),
+        motion_sensitivity in 0u8..=100,
+        standstill_sensitivity in 0u8..=100,
+    ) {
+        let frame = Ld2410CFrame::distance_gate_sensitivity_configuration(
+            distance_gate.clone(),
+            motion_sensitivity,
+            standstill_sensitivity,
+        )
+        .unwrap();
+        prop_assert_eq!(
+            Ld2410CFrame::parse(&frame.to_u8()),
+            Ok(ld2410c::DecodedCommand::DistanceGateSensitivityConfiguration {
+                distance_gate,
+                motion_sensitivity,
+                standstill_sensitivity,
+            })
+        );
+    }
+
+    #[test]
+    fn ld2410c_sensitivity_out_of_range_is_rejected(sensitivity in 101u8..) {
+        prop_assert!(Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValue0, sensitivity, 0).is_err());
+        prop_assert!(Ld2410CFrame::distance_gate_sensitivity_configuration(GateValue::GateValue0, 0, sensitivity).is_err());
+    }
+
+    #[test]
+    fn ld2410c_password_round_trip(password in "[a-zA-Z0-9]{6}") {
+        let bytes: [u8; 6] = password.as_bytes().try_into().unwrap();
+        prop_assert_eq!(
+            Ld2410CFrame::parse(&Ld2410CFrame::bluetooth_password(&password).unwrap().to_u8()),
+            Ok(ld2410c::DecodedCommand::BluetoothPassword(bytes))
+        );
+        prop_assert_eq!(
+            Ld2410CFrame::parse(&Ld2410CFrame::obtaining_bluetooth_permissions(&password).unwrap().to_u8()),
+            Ok(ld2410c::DecodedCommand::ObtainingBluetoothPermissions(bytes))
+        );
+    }
+
+    #[test]
+    fn ld2410c_baud_rate_round_trip(baud_rate in prop::sample::select(BaudRate::ALL.to_vec())) {
+        prop_assert_eq!(
+            Ld2410CFrame::parse(&Ld2410CFrame::serial_port_baud_rate(baud_rate.clone()).to_u8()),
+            Ok(ld2410c::DecodedCommand::SerialPortBaudRate(baud_rate))
+        );
+    }
+
+    #[test]
+    fn ld2410c_auxiliary_control_round_trip(auxiliary_control in auxiliary_control()) {
+        prop_assert_eq!(
+            Ld2410CFrame::parse(&Ld2410CFrame::auxiliary_control(auxiliary_control.clone()).to_u8()),
+            Ok(ld2410c::DecodedCommand::AuxiliaryControl(auxiliary_control))
+        );
+    }
+
+    #[test]
+    fn ld2410c_parse_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..40)) {
+        let _ = Ld2410CFrame::parse(&bytes);
+    }
+
+    #[test]
+    fn rd03d_set_mode_round_trip(mode in any::<u8>()) {
+        prop_assert_eq!(
+            RD03DFrame::parse(&RD03DFrame::set_mode(mode).to_u8()),
+            Some(rd03d::DecodedCommand::SetMode(mode))
+        );
+    }
+
+    #[test]
+    fn rd03d_parse_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..30)) {
+        let _ = RD03DFrame::parse(&bytes);
+    }
+
+    #[test]
+    fn tf_luna_command_round_trip(command in tf_luna_command()) {
+        let bytes = command.to_u8();
+        prop_assert_eq!(bytes[1] as usize, bytes.len());
+        prop_assert_eq!(TfLunaCommand::parse(&bytes), Some(command));
+    }
+
+    #[test]
+    fn tf_luna_parse_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..12)) {
+        let _ = TfLunaCommand::parse(&bytes);
+    }
+}
+
+#[test]
+fn ld2410c_fixed_commands_round_trip() {
+    use ld2410c::DecodedCommand;
+    let frames = [
+        (Ld2410CFrame::enabling_configuration(), DecodedCommand::EnablingConfiguration),
+        (Ld2410CFrame::ending_configuration(), DecodedCommand::EndingConfiguration),
+        (Ld2410CFrame::read_firmware_version(), DecodedCommand::ReadFirmwareVersion),
+        (
+            Ld2410CFrame::bluetooth_module(BluetoothModule::TurnOff),
+            DecodedCommand::BluetoothModule(BluetoothModule::TurnOff),
+        ),
+        (Ld2410CFrame::engineering_mode(), DecodedCommand::EngineeringMode),
+        (Ld2410CFrame::engineering_mode_off(), DecodedCommand::EngineeringModeOff),
+        (Ld2410CFrame::read_parameter(), DecodedCommand::ReadParameter),
+        (Ld2410CFrame::restart_module(), DecodedCommand::RestartModule),
+        (Ld2410CFrame::restore_factory_settings(), DecodedCommand::RestoreFactorySettings),
+        (
+            Ld2410CFrame::distance_resolution_setting(DistanceResolution::DistanceGate0_75m),
+            DecodedCommand::DistanceResolutionSetting(DistanceResolution::DistanceGate0_75m),
+        ),
+        (Ld2410CFrame::query_distance_resolution_setting(), DecodedCommand::QueryDistanceResolutionSetting),
+        (Ld2410CFrame::mac_address(), DecodedCommand::MacAddress),
+        (Ld2410CFrame::query_auxiliary_control(), DecodedCommand::QueryAuxiliaryControl),
+    ];
+    for (frame, expected) in frames {
+        assert_eq!(Ld2410CFrame::parse(&frame.to_u8()), Ok(expected));
+    }
+}
+
+#[test]
+fn rd03d_fixed_commands_round_trip() {
+    assert_eq!(
+        RD03DFrame::parse(&RD03DFrame::open_command_mode().to_u8()),
+        Some(rd03d::DecodedCommand::OpenCommandMode)
+    );
+    assert_eq!(
+        RD03DFrame::parse(&RD03DFrame::close_command_mode().to_u8()),
+        Some(rd03d::DecodedCommand::CloseCommandMode)
+    );
+}
@@ -0,0 +1,292 @@
+// tests/embedded.rs
+// Tests of the embedded-io drivers against an in-memory serial port.
+use std::convert::Infallible;
+
+use sensor::embedded::ld2410c::{Ld2410C, Ld2410CAsync};
+use sensor::embedded::rd03d::{RD03DAsync, RD03D};
+use sensor::embedded::tf_luna::{TfLuna, TfLunaAsync};
+use sensor::embedded::Error;
+use sensor::protocol::ld2410c::{
+    DataType, DecodeError, LightControl, Ld2410CFrame, OutPinLevel, TargetStatus,
+};
+use sensor::protocol::rd03d::{target_mode_command, RD03DFrame};
+use sensor::protocol::tf_luna::{OutputFrequency, TfLunaCommand};
+
+// MockSerial returns the bytes of `rx` by chunks of at most `chunk` bytes, then end of stream,
+// and records the written bytes in `tx`.
+struct MockSerial {
+    rx: Vec<u8>,
+    position: usize,
+    chunk: usize,
+    tx: Vec<u8>,
+}
+
+impl MockSerial {
+    fn new(rx: &[u8], chunk: usize) -> Self {
+        Self {
+            rx: rx.to_vec(),
+            position: 0,
+            chunk,
+            tx: Vec::new(),
+        }
+    }
+
+    fn next_chunk(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.chunk).min(self.rx.len() - self.position);
+        buf[..n].copy_from_slice(&self.rx[self.position..self.position + n]);
+        self.position += n;
+        n
+    }
+}
+
+impl embedded_io::ErrorType for MockSerial {
+    type Error = Infallible;
+}
+
+impl embedded_io::Read for MockSerial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(self.next_chunk(buf))
+    }
+}
+
+impl embedded_io::Write for MockSerial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl embedded_io_async::Read for MockSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(self.next_chunk(buf))
+    }
+}
+
+impl embedded_io_async::Write for MockSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+const LD2410C_BASIC_REPORT: [u8; 23] = [
+    0xF4, 0xF3, 0xF2, 0xF1, 0x0D, 0x00, 0x02, 0xAA, 0x02, 0x51, 0x00, 0x00, 0x00, 0x00, 0x3B, 0x00,
+    0x00, 0x55, 0x00, 0xF8, 0xF7, 0xF6, 0xF5,
+];
+const LD2410C_ENABLE_ACK: [u8; 18] = [
+    0xFD, 0xFC, 0xFB, 0xFA, 0x08, 0x00, 0xFF, 0x01, 0x00, 0x00, 0x01, 0x00, 0x40, 0x00, 0x04, 0x03,
+    0x02, 0x01,
+];
+const LD2410C_END_ACK: [u8; 14] = [
+    0xFD, 0xFC, 0xFB, 0xFA, 0x04, 0x00, 0xFE, 0x01, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01,
+];
+const LD2410C_FIRMWARE_ACK: [u8; 22] = [
+    0xFD, 0xFC, 0xFB, 0xFA, 0x0C, 0x00, 0xA0, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x16, 0x15,
+    0x09, 0x22, 0x04, 0x03, 0x02, 0x01,
+];
+const LD2410C_AUXILIARY_ACK: [u8; 18] = [
+    0xFD, 0xFC, 0xFB, 0xFA, 0x08, 0x00, 0xAE, 0x01, 0x00, 0x00, 0x01, 0x80, 0x01, 0x00, 0x04, 0x03,
+    0x02, 0x01,
+];
+
+fn concat(parts: &[&[u8]]) -> Vec<u8> {
+    parts.concat()
+}
+
+#[test]
+fn ld2410c_reads_report_split_across_reads() {
+    let rx = concat(&[&[0x00, 0x13], &LD2410C_BASIC_REPORT]);
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&rx, 5));
+    let data = ld2410c.read_data().unwrap();
+    assert_eq!(data.data_type, DataType::TargetBasicInformation);
+    assert_eq!(data.target_data.target_status, TargetStatus::StationaryTarget);
+    assert_eq!(data.target_data.movement_target_distance, 0x51);
+    assert_eq!(data.target_data.stationary_target_energy, 0x3B);
+}
+
+#[test]
+fn ld2410c_read_data_skips_ack_frames() {
+    let rx = concat(&[&LD2410C_END_ACK, &LD2410C_BASIC_REPORT]);
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&rx, 64));
+    assert_eq!(
+        ld2410c.read_data().unwrap().data_type,
+        DataType::TargetBasicInformation
+    );
+}
+
+#[test]
+fn ld2410c_read_data_reports_invalid_frames() {
+    let mut report = LD2410C_BASIC_REPORT;
+    report[7] = 0xAB;
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&report, 64));
+    assert!(matches!(
+        ld2410c.read_data(),
+        Err(Error::Decode(DecodeError::InvalidHead(0xAB)))
+    ));
+}
+
+#[test]
+fn ld2410c_read_data_end_of_stream() {
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&LD2410C_BASIC_REPORT[..10], 64));
+    assert!(matches!(ld2410c.read_data(), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn ld2410c_send_command_matches_ack_and_discards_reports() {
+    let rx = concat(&[
+        &LD2410C_ENABLE_ACK,
+        &LD2410C_BASIC_REPORT,
+        &LD2410C_FIRMWARE_ACK,
+        &LD2410C_BASIC_REPORT,
+        &LD2410C_END_ACK,
+    ]);
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&rx, 7));
+    let ack = ld2410c.read_firmware_version().unwrap();
+    assert_eq!(&ack[..], &LD2410C_FIRMWARE_ACK[..]);
+
+    let serial = ld2410c.release();
+    let expected = concat(&[
+        &Ld2410CFrame::enabling_configuration().to_u8(),
+        &Ld2410CFrame::read_firmware_version().to_u8(),
+        &Ld2410CFrame::ending_configuration().to_u8(),
+    ]);
+    assert_eq!(serial.tx, expected);
+}
+
+#[test]
+fn ld2410c_send_command_reports_failed_ack() {
+    let mut failed = LD2410C_FIRMWARE_ACK;
+    failed[8] = 0x01;
+    let rx = concat(&[&LD2410C_ENABLE_ACK, &failed]);
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&rx, 64));
+    assert!(matches!(
+        ld2410c.read_firmware_version(),
+        Err(Error::Decode(DecodeError::AckFailed(0x00A0)))
+    ));
+}
+
+#[test]
+fn ld2410c_query_auxiliary_control() {
+    let rx = concat(&[&LD2410C_ENABLE_ACK, &LD2410C_AUXILIARY_ACK, &LD2410C_END_ACK]);
+    let mut ld2410c = Ld2410C::new(MockSerial::new(&rx, 64));
+    let auxiliary_control = ld2410c.query_auxiliary_control().unwrap();
+    assert_eq!(auxiliary_control.light_control, LightControl::BelowThreshold);
+    assert_eq!(auxiliary_control.light_threshold, 0x80);
+    assert_eq!(auxiliary_control.out_pin_default_level, OutPinLevel::High);
+}
+
+#[tokio::test]
+async fn ld2410c_async_send_command() {
+    let rx = concat(&[
+        &LD2410C_ENABLE_ACK,
+        &LD2410C_FIRMWARE_ACK,
+        &LD2410C_END_ACK,
+        &LD2410C_BASIC_REPORT,
+    ]);
+    let mut ld2410c = Ld2410CAsync::new(MockSerial::new(&rx, 3));
+    let ack = ld2410c.read_firmware_version().await.unwrap();
+    assert_eq!(&ack[..], &LD2410C_FIRMWARE_ACK[..]);
+    assert_eq!(
+        ld2410c.read_data().await.unwrap().data_type,
+        DataType::TargetBasicInformation
+    );
+}
+
+// rd03d_report() Builds a report frame with the first target at (x, y) and the two others empty.
+fn rd03d_report(x: i16, y: i16) -> Vec<u8> {
+    let encode = |value: i16| -> [u8; 2] {
+        // Bit 15 set for a positive value.
+        let raw = if value < 0 { (-value) as u16 } else { value as u16 | 0x8000 };
+        raw.to_le_bytes()
+    };
+    let mut frame = vec![0xAA, 0xFF, 0x03, 0x00];
+    frame.extend_from_slice(&encode(x));
+    frame.extend_from_slice(&encode(y));
+    frame.extend_from_slice(&[0x00, 0x00, 0x68, 0x01]);
+    frame.extend_from_slice(&[0x00; 16]);
+    frame.extend_from_slice(&[0x55, 0xCC]);
+    frame
+}
+
+#[test]
+fn rd03d_update_decodes_latest_frame() {
+    let rx = concat(&[&[0x12, 0x34], &rd03d_report(100, 200), &rd03d_report(-300, 400)]);
+    let mut rd03d = RD03D::new(MockSerial::new(&rx, 256));
+    assert!(rd03d.update().unwrap());
+    let target = rd03d.get_target(1).unwrap();
+    assert_eq!((target.x, target.y), (-300, 400));
+    assert!(rd03d.get_target(0).is_none());
+    assert!(rd03d.get_target(4).is_none());
+}
+
+#[test]
+fn rd03d_update_waits_for_complete_frame() {
+    let rx = rd03d_report(100, 200);
+    let mut rd03d = RD03D::new(MockSerial::new(&rx, 20));
+    assert!(!rd03d.update().unwrap());
+    assert!(rd03d.update().unwrap());
+    assert_eq!(rd03d.get_target(1).unwrap().x, 100);
+    assert!(matches!(rd03d.update(), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn rd03d_writes_commands() {
+    let mut rd03d = RD03D::new(MockSerial::new(&[], 1));
+    rd03d.set_multi_mode(false).unwrap();
+    rd03d.open_command_mode().unwrap();
+    assert!(!rd03d.multi_mode);
+    let serial = rd03d.release();
+    let expected = concat(&[target_mode_command(false), &RD03DFrame::open_command_mode().to_u8()]);
+    assert_eq!(serial.tx, expected);
+}
+
+#[tokio::test]
+async fn rd03d_async_update() {
+    let rx = rd03d_report(-50, 75);
+    let mut rd03d = RD03DAsync::new(MockSerial::new(&rx, 64));
+    assert!(rd03d.update().await.unwrap());
+    let target = rd03d.get_target(1).unwrap();
+    assert_eq!((target.x, target.y), (-50, 75));
+}
+
+// tf_luna_frame() Builds a data frame with the given distance (cm) and a valid checksum.
+fn tf_luna_frame(distance: u16) -> Vec<u8> {
+    let mut frame = vec![0x59, 0x59];
+    frame.extend_from_slice(&distance.to_le_bytes());
+    frame.extend_from_slice(&[0x10, 0x02, 0x00, 0x09]);
+    frame.push(frame.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+    frame
+}
+
+#[test]
+fn tf_luna_read_data_skips_invalid_frames() {
+    let mut corrupted = tf_luna_frame(42);
+    corrupted[8] ^= 0xFF;
+    let rx = concat(&[&[0x59], &corrupted, &tf_luna_frame(123), &tf_luna_frame(456)]);
+    let mut tf_luna = TfLuna::new(MockSerial::new(&rx, 4));
+    assert_eq!(tf_luna.read_data().unwrap().distance(), 123);
+    assert_eq!(tf_luna.read_data().unwrap().distance(), 456);
+    assert!(matches!(tf_luna.read_data(), Err(Error::UnexpectedEof)));
+}
+
+#[test]
+fn tf_luna_writes_commands() {
+    let mut tf_luna = TfLuna::new(MockSerial::new(&[], 1));
+    tf_luna.set_output_frequency(OutputFrequency::Freq100Hz).unwrap();
+    let serial = tf_luna.release();
+    assert_eq!(
+        serial.tx,
+        TfLunaCommand::OutputFrequency(OutputFrequency::Freq100Hz).to_u8().to_vec()
+    );
+}
+
+#[tokio::test]
+async fn tf_luna_async_read_data() {
+    let rx = tf_luna_frame(789);
+    let mut tf_luna = TfLunaAsync::new(MockSerial::new(&rx, 2));
+    assert_eq!(tf_luna.read_data().await.unwrap().distance(), 789);
+}